@@ -0,0 +1,178 @@
+use crate::model_manager::ModelError;
+use crate::settings::ModelVariant;
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::{ Arc, Mutex };
+use std::time::{ SystemTime, UNIX_EPOCH };
+use sysinfo::{ Pid, System };
+
+/// Present in a variant directory while a download writes into it, so other
+/// windows and later app runs see the download too
+const LOCK_FILE: &str = ".download.lock";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LockInfo {
+    pid: u32,
+    /// Unix seconds
+    started: u64,
+}
+
+fn read_lock(model_path: &Path) -> Option<LockInfo> {
+    let content = std::fs::read_to_string(model_path.join(LOCK_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn process_alive(pid: u32) -> bool {
+    System::new().refresh_process(Pid::from_u32(pid))
+}
+
+/// Whether a running process holds the download lock of `model_path`; locks
+/// left by a process that exited don't count
+pub fn is_locked(model_path: &Path) -> bool {
+    read_lock(model_path).is_some_and(|info| process_alive(info.pid))
+}
+
+/// Take the download lock of `model_path`, replacing a stale one. `None`
+/// when a running process holds it.
+fn acquire_lock(model_path: &Path) -> std::io::Result<Option<PathBuf>> {
+    let lock_path = model_path.join(LOCK_FILE);
+    let info = LockInfo {
+        pid: std::process::id(),
+        started: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0),
+    };
+
+    for _ in 0..2 {
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&lock_path) {
+            Ok(file) => {
+                serde_json::to_writer(file, &info)?;
+                return Ok(Some(lock_path));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                if is_locked(model_path) {
+                    return Ok(None);
+                }
+                log::info!("Removing stale download lock {:?}", lock_path);
+                std::fs::remove_file(&lock_path)?;
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        }
+    }
+    Ok(None)
+}
+
+type Running = Arc<Mutex<HashMap<ModelVariant, Arc<AtomicBool>>>>;
+
+/// Downloads running in this process, with their cancellation flags, shared
+/// through Tauri managed state
+#[derive(Clone, Default)]
+pub struct ActiveDownloads {
+    running: Running,
+}
+
+/// Held for the length of a download; releases the variant when dropped
+pub struct DownloadGuard {
+    variant: ModelVariant,
+    lock_path: PathBuf,
+    cancelled: Arc<AtomicBool>,
+    running: Running,
+}
+
+impl DownloadGuard {
+    /// Set by `ActiveDownloads::cancel`
+    pub fn cancelled(&self) -> &AtomicBool {
+        &self.cancelled
+    }
+}
+
+impl Drop for DownloadGuard {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.running.lock() {
+            running.remove(&self.variant);
+        }
+        let _ = std::fs::remove_file(&self.lock_path);
+    }
+}
+
+impl ActiveDownloads {
+    /// Register a download of `variant` into `model_path`, refusing a second
+    /// one while this or another process is still downloading it
+    pub fn begin(&self, variant: &ModelVariant, model_path: &Path) -> Result<DownloadGuard, ModelError> {
+        let mut running = self.running.lock().map_err(|_| ModelError::Other(anyhow::anyhow!("Download registry poisoned")))?;
+        if running.contains_key(variant) {
+            return Err(ModelError::AlreadyDownloading(variant.to_string()));
+        }
+        let lock_path = acquire_lock(model_path)?.ok_or_else(|| ModelError::AlreadyDownloading(variant.to_string()))?;
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        running.insert(variant.clone(), cancelled.clone());
+        Ok(DownloadGuard {
+            variant: variant.clone(),
+            lock_path,
+            cancelled,
+            running: self.running.clone(),
+        })
+    }
+
+    /// Ask the download of `variant` to stop; false when none is running here
+    pub fn cancel(&self, variant: &ModelVariant) -> bool {
+        match self.running.lock().ok().and_then(|running| running.get(variant).cloned()) {
+            Some(cancelled) => {
+                cancelled.store(true, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_one_download_per_variant() {
+        let temp_dir = TempDir::new().unwrap();
+        let downloads = ActiveDownloads::default();
+
+        let guard = downloads.begin(&ModelVariant::Qwen3VL2B, temp_dir.path()).unwrap();
+        assert!(is_locked(temp_dir.path()));
+        assert!(matches!(
+            downloads.begin(&ModelVariant::Qwen3VL2B, temp_dir.path()),
+            Err(ModelError::AlreadyDownloading(_))
+        ));
+        // Another process sees the lock file
+        assert!(matches!(
+            ActiveDownloads::default().begin(&ModelVariant::Qwen3VL2B, temp_dir.path()),
+            Err(ModelError::AlreadyDownloading(_))
+        ));
+
+        assert!(downloads.cancel(&ModelVariant::Qwen3VL2B));
+        assert!(guard.cancelled().load(Ordering::SeqCst));
+        assert!(!downloads.cancel(&ModelVariant::Qwen3VL4B));
+
+        drop(guard);
+        assert!(!is_locked(temp_dir.path()));
+        assert!(!temp_dir.path().join(LOCK_FILE).exists());
+        assert!(downloads.begin(&ModelVariant::Qwen3VL2B, temp_dir.path()).is_ok());
+    }
+
+    #[test]
+    fn test_stale_lock_is_replaced() {
+        let temp_dir = TempDir::new().unwrap();
+        // Left by a process that no longer exists
+        std::fs::write(temp_dir.path().join(LOCK_FILE), r#"{"pid":4294967295,"started":0}"#).unwrap();
+        assert!(!is_locked(temp_dir.path()));
+
+        let guard = ActiveDownloads::default().begin(&ModelVariant::Qwen3VL2B, temp_dir.path()).unwrap();
+        assert_eq!(read_lock(temp_dir.path()).unwrap().pid, std::process::id());
+        drop(guard);
+    }
+}
@@ -0,0 +1,235 @@
+use crate::candle_inference::InferenceStats;
+use crate::model_manager::{ resolve_model_files, ModelFiles };
+use crate::model_pool::ModelPool;
+use crate::offline_analyzer::ChatFormat;
+use crate::settings::{ AppSettings, ModelVariant, RuntimeConfig, SamplingConfig };
+use crate::spec_grammar::dataset_spec_grammar;
+use anyhow::{ Context, Result };
+use image::DynamicImage;
+use serde::{ Deserialize, Serialize };
+use std::path::{ Path, PathBuf };
+use std::time::{ Instant, SystemTime, UNIX_EPOCH };
+use sysinfo::{ Pid, System };
+
+/// Reference image for benchmarks (256x256, an 8x8 visual token grid)
+const BENCHMARK_IMAGE: &[u8] = include_bytes!("../assets/benchmark.png");
+
+/// SREF code used in the benchmark prompt
+const BENCHMARK_SREF: &str = "1234567890";
+
+/// Tokens generated per benchmark; the grammar keeps generation from ending early
+const BENCHMARK_MAX_TOKENS: usize = 256;
+
+/// Measured speed of a model on this machine
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BenchmarkResult {
+    pub variant: ModelVariant,
+    /// File names, so quantizations of the same variant can be compared
+    pub model_file: String,
+    pub mmproj_file: String,
+    pub runtime: RuntimeConfig,
+    pub load_ms: u64,
+    pub image_encode_ms: u64,
+    pub prompt_tokens: usize,
+    pub prompt_tokens_per_second: f32,
+    pub generated_tokens: usize,
+    pub generation_tokens_per_second: f32,
+    /// Highest resident memory observed during the run
+    pub peak_rss_bytes: u64,
+    /// Unix timestamp of the run
+    pub timestamp: u64,
+}
+
+/// Tracks the highest resident memory of this process across samples
+struct RssSampler {
+    sys: System,
+    pid: Option<Pid>,
+    peak: u64,
+}
+
+impl RssSampler {
+    fn new() -> Self {
+        let mut sampler = Self {
+            sys: System::new(),
+            pid: sysinfo::get_current_pid().ok(),
+            peak: 0,
+        };
+        sampler.sample();
+        sampler
+    }
+
+    fn sample(&mut self) {
+        let Some(pid) = self.pid else {
+            return;
+        };
+        self.sys.refresh_process(pid);
+        if let Some(process) = self.sys.process(pid) {
+            self.peak = self.peak.max(process.memory());
+        }
+    }
+}
+
+fn tokens_per_second(tokens: usize, ms: u64) -> f32 {
+    if ms == 0 {
+        return 0.0;
+    }
+    (tokens as f32) / ((ms as f32) / 1000.0)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Results are saved next to the model file, one per model file
+pub fn benchmark_path(files: &ModelFiles) -> PathBuf {
+    files.model.with_extension("benchmark.json")
+}
+
+/// Load the saved benchmark for a model, if it has been run
+pub fn load_result(files: &ModelFiles) -> Result<Option<BenchmarkResult>> {
+    let path = benchmark_path(files);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = std::fs::read_to_string(&path).context("Failed to read benchmark results")?;
+    let result = serde_json::from_str(&content).context("Failed to parse benchmark results")?;
+    Ok(Some(result))
+}
+
+pub fn save_result(files: &ModelFiles, result: &BenchmarkResult) -> Result<()> {
+    let json = serde_json::to_string_pretty(result).context("Failed to serialize benchmark results")?;
+    std::fs::write(benchmark_path(files), json).context("Failed to write benchmark results")?;
+    Ok(())
+}
+
+fn build_result(
+    variant: &ModelVariant,
+    files: &ModelFiles,
+    runtime: &RuntimeConfig,
+    load_ms: u64,
+    stats: &InferenceStats,
+    peak_rss_bytes: u64
+) -> BenchmarkResult {
+    BenchmarkResult {
+        variant: variant.clone(),
+        model_file: file_name(&files.model),
+        mmproj_file: file_name(&files.mmproj),
+        runtime: runtime.clone(),
+        load_ms,
+        image_encode_ms: stats.image_encode_ms,
+        prompt_tokens: stats.prompt_tokens,
+        prompt_tokens_per_second: tokens_per_second(stats.prompt_tokens, stats.prompt_eval_ms),
+        generated_tokens: stats.generated_tokens,
+        generation_tokens_per_second: tokens_per_second(stats.generated_tokens, stats.generation_ms),
+        peak_rss_bytes,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    }
+}
+
+/// Load the model cold, run the benchmark prompt and save the results.
+/// Runs in the inference worker, so peak memory is the worker's.
+///
+/// Blocks for the whole run.
+pub fn run_benchmark(
+    variant: &ModelVariant,
+    settings: &AppSettings,
+    pool: &ModelPool
+) -> Result<BenchmarkResult> {
+    let files = resolve_model_files(variant, settings)?;
+    let image = image::load_from_memory(BENCHMARK_IMAGE).context("Failed to decode benchmark image")?;
+
+    let prompt = ChatFormat::for_variant(variant, settings)?.analysis_prompt(BENCHMARK_SREF, 1);
+    let grammar = dataset_spec_grammar(BENCHMARK_SREF);
+    let sampling = SamplingConfig {
+        temperature: 0.0,
+        max_new_tokens: BENCHMARK_MAX_TOKENS,
+        seed: Some(0),
+        ..SamplingConfig::default()
+    };
+
+    let mut rss = RssSampler::new();
+
+    // Measure a cold load
+    pool.unload(variant, "benchmark");
+    let load_started = Instant::now();
+    let inference = pool.get_or_load(variant, &files, &settings.runtime)?;
+    let load_ms = load_started.elapsed().as_millis() as u64;
+    rss.sample();
+
+    let output = inference
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Model lock poisoned"))?
+        .infer(
+            vec![DynamicImage::ImageRgb8(image.to_rgb8())],
+            &prompt,
+            Some(&grammar),
+            &sampling,
+            &settings.runtime,
+            &mut |_| {
+                rss.sample();
+                true
+            }
+        );
+    rss.sample();
+
+    if !settings.keep_model_loaded {
+        pool.unload(variant, "keep_model_loaded disabled");
+    }
+
+    let result = build_result(variant, &files, &settings.runtime, load_ms, &output?.stats, rss.peak);
+    log::info!("Benchmark of {}: {:?}", variant, result);
+    save_result(&files, &result)?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_benchmark_image_decodes() {
+        let image = image::load_from_memory(BENCHMARK_IMAGE).unwrap();
+        assert_eq!((image.width(), image.height()), (256, 256));
+    }
+
+    #[test]
+    fn test_save_and_load_result() {
+        let temp_dir = TempDir::new().unwrap();
+        let files = ModelFiles {
+            model: temp_dir.path().join("Qwen3VL-2B-Instruct-Q4_K_M.gguf"),
+            mmproj: temp_dir.path().join("mmproj-Qwen3VL-2B-Instruct-Q8_0.gguf"),
+        };
+        assert!(benchmark_path(&files).ends_with("Qwen3VL-2B-Instruct-Q4_K_M.benchmark.json"));
+        assert_eq!(load_result(&files).unwrap(), None);
+
+        let stats = InferenceStats {
+            image_encode_ms: 800,
+            prompt_tokens: 120,
+            prompt_eval_ms: 400,
+            generated_tokens: 256,
+            generation_ms: 8000,
+        };
+        let result = build_result(
+            &ModelVariant::Qwen3VL2B,
+            &files,
+            &RuntimeConfig::default(),
+            1500,
+            &stats,
+            2_000_000_000
+        );
+        assert_eq!(result.prompt_tokens_per_second, 300.0);
+        assert_eq!(result.generation_tokens_per_second, 32.0);
+        assert_eq!(result.model_file, "Qwen3VL-2B-Instruct-Q4_K_M.gguf");
+
+        save_result(&files, &result).unwrap();
+        assert_eq!(load_result(&files).unwrap(), Some(result));
+    }
+}
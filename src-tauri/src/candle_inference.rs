@@ -1,42 +1,122 @@
-use crate::settings::ModelVariant;
-use anyhow::Result;
+use crate::hardware::thread_count;
+use crate::settings::{ ModelVariant, RuntimeConfig, SamplingConfig };
+use anyhow::{ Context, Result };
 use image::DynamicImage;
-use std::path::Path;
+use llama_cpp_2::context::params::LlamaContextParams;
+use llama_cpp_2::context::LlamaContext;
+use llama_cpp_2::llama_backend::LlamaBackend;
+use llama_cpp_2::llama_batch::LlamaBatch;
+use llama_cpp_2::model::params::LlamaModelParams;
+use llama_cpp_2::model::{ LlamaModel, Special };
+use llama_cpp_2::mtmd::{
+    MtmdBitmap,
+    MtmdContext,
+    MtmdContextParams,
+    MtmdInputText,
+};
+use llama_cpp_2::sampling::LlamaSampler;
+use llama_cpp_sys_2::{ LLAMA_FLASH_ATTN_TYPE_DISABLED, LLAMA_FLASH_ATTN_TYPE_ENABLED };
+use serde::{ Deserialize, Serialize };
+use std::ffi::CString;
+use std::num::NonZeroU32;
+use std::path::{ Path, PathBuf };
+use std::sync::{ Mutex, OnceLock };
+use std::time::{ Duration, Instant };
+use thiserror::Error;
 
-pub struct Qwen2VLInference {
-    variant: ModelVariant,
-    model_path: std::path::PathBuf,
-    mmproj_path: std::path::PathBuf,
+/// Image placeholder emitted by `build_qwen_prompt` for each reference image
+const QWEN_IMAGE_PLACEHOLDER: &str = "<|vision_start|><|image_pad|><|vision_end|>";
+
+/// Marker mtmd replaces with image embeddings (and model-specific image tokens)
+const MEDIA_MARKER: &str = "<__media__>";
+
+/// Typical length of a generated spec, used for the generation ETA
+const EXPECTED_SPEC_TOKENS: usize = 2500;
+
+/// Minimum time between `Generating` progress reports
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Stage of a local inference run, reported through the progress callback
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum InferenceProgress {
+    LoadingModel,
+    /// Describing one reference image before the spec is written
+    CaptioningImage {
+        index: usize,
+        total: usize,
+    },
+    EncodingImage {
+        index: usize,
+        total: usize,
+    },
+    /// The whole prompt is in context; `tokens` is where generation starts
+    PromptEval {
+        tokens: i32,
+    },
+    Generating {
+        tokens_generated: usize,
+        tokens_per_second: f32,
+        eta_secs: Option<f32>,
+    },
 }
 
-impl Qwen2VLInference {
-    pub async fn new(model_path: &Path, variant: ModelVariant) -> Result<Self> {
-        log::info!("Loading Qwen3-VL model from {:?}", model_path);
+/// Stage timings of one inference run
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct InferenceStats {
+    /// Encoding images and decoding their embeddings (with preceding text)
+    pub image_encode_ms: u64,
+    /// Text tokens evaluated after the last image
+    pub prompt_tokens: usize,
+    pub prompt_eval_ms: u64,
+    pub generated_tokens: usize,
+    pub generation_ms: u64,
+}
 
-        // Validate paths exist
-        if !model_path.exists() {
-            anyhow::bail!("Model path does not exist: {:?}", model_path);
-        }
+/// Model output and how long each stage took
+#[derive(Debug, Clone)]
+pub struct InferenceOutput {
+    pub text: String,
+    pub stats: InferenceStats,
+}
 
-        // Determine model and mmproj file names based on variant
-        // These MUST match the filenames in model_manager.rs exactly
-        let (model_file, mmproj_file) = match variant {
-            ModelVariant::Qwen3VL2B => (
-                "Qwen3VL-2B-Instruct-Q8_0.gguf",
-                "mmproj-Qwen3VL-2B-Instruct-Q8_0.gguf",
-            ),
-            ModelVariant::Qwen3VL4B => (
-                "Qwen3VL-4B-Instruct-Q8_0.gguf",
-                "mmproj-Qwen3VL-4B-Instruct-Q8_0.gguf",
-            ),
-            ModelVariant::Qwen3VL8B => (
-                "Qwen3VL-8B-Instruct-Q8_0.gguf",
-                "mmproj-Qwen3VL-8B-Instruct-Q8_0.gguf",
-            ),
-        };
+/// Returned (inside `anyhow::Error`) when the progress callback asks to stop
+#[derive(Debug, Error)]
+#[error("Inference cancelled")]
+pub struct Cancelled;
+
+static BACKEND: OnceLock<LlamaBackend> = OnceLock::new();
+static BACKEND_INIT: Mutex<()> = Mutex::new(());
+
+/// Get the process-wide llama.cpp backend, initializing it on first use
+fn backend() -> Result<&'static LlamaBackend> {
+    let _guard = BACKEND_INIT.lock().map_err(|_| anyhow::anyhow!("Backend lock poisoned"))?;
+
+    if let Some(backend) = BACKEND.get() {
+        return Ok(backend);
+    }
+
+    let backend = LlamaBackend::init().context("Failed to initialize llama.cpp backend")?;
+    Ok(BACKEND.get_or_init(|| backend))
+}
 
-        let model_file_path = model_path.join(model_file);
-        let mmproj_path = model_path.join(mmproj_file);
+pub struct Qwen2VLInference {
+    variant: ModelVariant,
+    model_path: PathBuf,
+    mmproj_path: PathBuf,
+    model: LlamaModel,
+    mtmd: MtmdContext,
+}
+
+impl Qwen2VLInference {
+    /// Load a model from its GGUF and mmproj file paths
+    pub fn from_files(
+        model_file_path: &Path,
+        mmproj_path: &Path,
+        variant: ModelVariant,
+        runtime: &RuntimeConfig,
+    ) -> Result<Self> {
+        log::info!("Loading {:?} model from {:?}", variant, model_file_path);
 
         // Verify both files exist
         if !model_file_path.exists() {
@@ -48,166 +128,398 @@ impl Qwen2VLInference {
 
         log::info!("Model files validated: {:?}", model_file_path);
 
+        let backend = backend()?;
+
+        // CPU-only by default; GPU offload is compiled in via the cuda/metal features
+        let model_params = LlamaModelParams::default().with_use_mlock(runtime.use_mlock);
+        let model = LlamaModel::load_from_file(backend, model_file_path, &model_params).with_context(||
+            format!("Failed to load model {:?}", model_file_path)
+        )?;
+
+        let mmproj_str = mmproj_path
+            .to_str()
+            .with_context(|| format!("Non UTF-8 mmproj path: {:?}", mmproj_path))?;
+        let mtmd_params = MtmdContextParams {
+            use_gpu: cfg!(any(feature = "cuda", feature = "metal")),
+            print_timings: false,
+            n_threads: thread_count(runtime) as i32,
+            media_marker: CString::new(MEDIA_MARKER)?,
+        };
+        let mtmd = MtmdContext::init_from_file(mmproj_str, &model, &mtmd_params).with_context(||
+            format!("Failed to load vision projection {:?}", mmproj_path)
+        )?;
+
+        log::info!("Loaded {:?} with vision projection {:?}", model_file_path, mmproj_path);
+
         Ok(Self {
             variant,
-            model_path: model_file_path,
-            mmproj_path,
+            model_path: model_file_path.to_path_buf(),
+            mmproj_path: mmproj_path.to_path_buf(),
+            model,
+            mtmd,
         })
     }
 
+    /// Run the prompt over the images and extract the JSON spec from the
+    /// response. See [`Self::infer`] for the arguments.
     pub fn analyze_images(
         &mut self,
         images: Vec<DynamicImage>,
         prompt: &str,
-    ) -> Result<String> {
-        log::info!("Analyzing {} images with Qwen3-VL ({:?})", images.len(), self.variant);
+        grammar: Option<&str>,
+        sampling: &SamplingConfig,
+        runtime: &RuntimeConfig,
+        progress: &mut dyn FnMut(InferenceProgress) -> bool,
+    ) -> Result<InferenceOutput> {
+        let output = self.infer(images, prompt, grammar, sampling, runtime, progress)?;
+        Ok(InferenceOutput {
+            text: extract_json(&output.text)?,
+            stats: output.stats,
+        })
+    }
+
+    /// Run the prompt over the images, constraining sampling to `grammar`
+    /// (GBNF, rooted at `root`) when given, and return the raw response.
+    /// `sampling.seed` should already be resolved so the run can be reproduced.
+    ///
+    /// `progress` is called at each stage and periodically while generating;
+    /// returning `false` stops inference with a [`Cancelled`] error.
+    pub fn infer(
+        &mut self,
+        images: Vec<DynamicImage>,
+        prompt: &str,
+        grammar: Option<&str>,
+        sampling: &SamplingConfig,
+        runtime: &RuntimeConfig,
+        progress: &mut dyn FnMut(InferenceProgress) -> bool,
+    ) -> Result<InferenceOutput> {
+        log::info!("Analyzing {} images with {:?}", images.len(), self.variant);
+        log::debug!("Model: {:?}, mmproj: {:?}", self.model_path, self.mmproj_path);
         log::debug!("Prompt: {}", prompt);
 
-        // TODO: Implement actual llama.cpp inference
-        //
-        // STEP 1: Initialize llama-cpp-2 context (do this in new() method)
-        //   use llama_cpp_2::model::LlamaModel;
-        //   use llama_cpp_2::context::LlamaContext;
-        //
-        //   let model = LlamaModel::load_from_file(&self.model_path, params)?;
-        //   let mut context = model.new_context(ctx_params)?;
-        //   context.load_mmproj(&self.mmproj_path)?;
-        //
-        // STEP 2: Preprocess images
-        //   let processed = images.iter()
-        //       .map(|img| {
-        //           let resized = img.resize_exact(448, 448, FilterType::Lanczos3);
-        //           let rgb = resized.to_rgb8();
-        //           // Normalize to [0, 1] and convert to format expected by llama.cpp
-        //           rgb
-        //       })
-        //       .collect::<Vec<_>>();
-        //
-        // STEP 3: Encode images using vision projection
-        //   let image_embeddings = context.encode_images(&processed)?;
-        //
-        // STEP 4: Tokenize prompt with vision tokens
-        //   let tokens = context.tokenize(prompt, true)?;
-        //
-        // STEP 5: Run inference
-        //   let mut output_tokens = Vec::new();
-        //   let mut batch = LlamaBatch::new(512, 1);
-        //
-        //   // Add image embeddings and text tokens to batch
-        //   batch.add_sequence(&image_embeddings, 0);
-        //   batch.add_sequence(&tokens, 0);
-        //
-        //   // Generate response
-        //   while output_tokens.len() < max_tokens {
-        //       context.decode(&batch)?;
-        //       let logits = context.get_logits();
-        //       let next_token = sample_token(logits);
-        //       output_tokens.push(next_token);
-        //       if next_token == eos_token { break; }
-        //       batch.clear();
-        //       batch.add(next_token, output_tokens.len() - 1, &[0], true);
-        //   }
-        //
-        // STEP 6: Decode response
-        //   let response = context.detokenize(&output_tokens)?;
-        //
-        // STEP 7: Extract and validate JSON
-        //   let json_start = response.find('{').ok_or(...)?;
-        //   let json_end = response.rfind('}').ok_or(...)?;
-        //   let json_str = &response[json_start..=json_end];
-        //
-        //   // Validate it parses correctly
-        //   serde_json::from_str::<serde_json::Value>(json_str)?;
-        //
-        //   return Ok(json_str.to_string());
-        //
-        // REFERENCES:
-        // - llama-cpp-2 docs: https://docs.rs/llama-cpp-2
-        // - Examples: https://github.com/utilityai/llama-cpp-rs/tree/main/examples
-        // - llama.cpp multimodal: https://github.com/ggml-org/llama.cpp/blob/master/docs/multimodal.md
-
-        log::warn!("llama.cpp inference not yet implemented - returning stub data");
-        log::warn!("See TODO comments at {}:{} for implementation guide", file!(), line!() - 40);
-
-        // Return realistic stub data for testing
-        self.generate_stub_response()
+        // Convert images to the RGB8 bitmaps the vision encoder expects
+        let bitmaps = images
+            .iter()
+            .map(|img| {
+                let rgb = img.to_rgb8();
+                MtmdBitmap::from_image_data(rgb.width(), rgb.height(), rgb.as_raw()).context(
+                    "Failed to create image bitmap"
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let threads = thread_count(runtime) as i32;
+        let flash_attention = if runtime.flash_attention {
+            LLAMA_FLASH_ATTN_TYPE_ENABLED
+        } else {
+            LLAMA_FLASH_ATTN_TYPE_DISABLED
+        };
+        let ctx_params = LlamaContextParams::default()
+            .with_n_ctx(NonZeroU32::new(runtime.n_ctx))
+            .with_n_batch(runtime.n_batch)
+            .with_n_ubatch(runtime.n_ubatch.min(runtime.n_batch))
+            .with_n_threads(threads)
+            .with_n_threads_batch(threads)
+            .with_flash_attention_policy(flash_attention);
+        let n_batch = runtime.n_batch as i32;
+        let mut ctx = self.model
+            .new_context(backend()?, ctx_params)
+            .context("Failed to create inference context")?;
+
+        // Swap the Qwen image placeholders for the mtmd media marker; mtmd
+        // re-inserts the model-specific vision start/end tokens itself
+        let marker = MEDIA_MARKER;
+        let prompt = prompt.replace(QWEN_IMAGE_PLACEHOLDER, marker);
+        let segments: Vec<&str> = prompt.split(marker).collect();
+        if segments.len() != bitmaps.len() + 1 {
+            anyhow::bail!(
+                "Prompt has {} image placeholders but {} images were given",
+                segments.len() - 1,
+                bitmaps.len()
+            );
+        }
+
+        // Encode one image at a time (with the text preceding it) so progress
+        // can be reported per image
+        let mut stats = InferenceStats::default();
+        let encode_started = Instant::now();
+        let mut n_past = 0;
+        for (index, bitmap) in bitmaps.iter().enumerate() {
+            if !progress(InferenceProgress::EncodingImage { index: index + 1, total: bitmaps.len() }) {
+                return Err(Cancelled.into());
+            }
+
+            let text = MtmdInputText {
+                text: format!("{}{}", segments[index], marker),
+                add_special: index == 0,
+                parse_special: true,
+            };
+            let chunks = self.mtmd
+                .tokenize(text, &[bitmap])
+                .with_context(|| format!("Failed to tokenize image {}", index + 1))?;
+            n_past = chunks
+                .eval_chunks(&self.mtmd, &ctx, n_past, 0, n_batch, false)
+                .with_context(|| format!("Failed to encode image {}", index + 1))?;
+        }
+
+        stats.image_encode_ms = encode_started.elapsed().as_millis() as u64;
+
+        // Evaluate the remaining instruction text
+        let prompt_started = Instant::now();
+        let image_tokens = n_past;
+        let text = MtmdInputText {
+            text: segments[bitmaps.len()].to_string(),
+            add_special: bitmaps.is_empty(),
+            parse_special: true,
+        };
+        let chunks = self.mtmd.tokenize(text, &[]).context("Failed to tokenize prompt")?;
+        let n_past = chunks
+            .eval_chunks(&self.mtmd, &ctx, n_past, 0, n_batch, true)
+            .context("Failed to evaluate prompt")?;
+        stats.prompt_eval_ms = prompt_started.elapsed().as_millis() as u64;
+        stats.prompt_tokens = (n_past - image_tokens) as usize;
+        if !progress(InferenceProgress::PromptEval { tokens: n_past }) {
+            return Err(Cancelled.into());
+        }
+
+        log::info!("Prompt evaluated ({} tokens), generating response", n_past);
+
+        let generation_started = Instant::now();
+        let (response, generated_tokens) = self.generate(&mut ctx, n_past, grammar, sampling, progress)?;
+        stats.generation_ms = generation_started.elapsed().as_millis() as u64;
+        stats.generated_tokens = generated_tokens;
+        log::debug!("Raw model output: {}", response);
+        log::info!("Inference stats: {:?}", stats);
+
+        Ok(InferenceOutput {
+            text: response,
+            stats,
+        })
     }
 
-    /// Generate a realistic stub response for development/testing
-    fn generate_stub_response(&self) -> Result<String> {
-        let stub_json = r###"{
-            "sref_code": "stub-qwen3vl",
-            "style_analysis": {
-                "primary_style": "Development Stub Mode",
-                "era_influence": "Model downloaded successfully, inference pending implementation",
-                "color_palette": ["#1E3A8A", "#3B82F6", "#60A5FA", "#93C5FD"],
-                "key_characteristics": [
-                    "llama.cpp integration ready",
-                    "Model files validated and loaded",
-                    "Inference pipeline needs completion"
-                ],
-                "best_subjects": [
-                    "Once llama-cpp-2 inference is implemented, real style analysis will appear here"
-                ],
-                "avoid_subjects": [
-                    "This is stub data for testing the download and initialization flow"
-                ]
-            },
-            "training_recommendations": {
-                "recommended_dataset_size": 120,
-                "optimal_subject_distribution": {
-                    "nature": 0.30,
-                    "objects": 0.25,
-                    "people": 0.20,
-                    "abstract": 0.15,
-                    "architecture": 0.10
-                }
-            },
-            "permutation_batches": [
-                {
-                    "batch_number": 1,
-                    "batch_name": "Nature Landscapes",
-                    "category": "Stub Example Batch",
-                    "image_count": 40,
-                    "prompt": "{mountains, forests, lakes, valleys, meadows} with {golden hour, sunset, dawn, overcast, foggy, clear, twilight, storm} --sref stub-qwen3vl",
-                    "priority": "high",
-                    "notes": "Real batches will be generated after llama.cpp integration"
+    /// Sample tokens until end-of-generation, the token limit or the end of
+    /// the context window, returning the text and the number of tokens generated
+    fn generate(
+        &self,
+        ctx: &mut LlamaContext,
+        mut n_past: i32,
+        grammar: Option<&str>,
+        sampling: &SamplingConfig,
+        progress: &mut dyn FnMut(InferenceProgress) -> bool,
+    ) -> Result<(String, usize)> {
+        let mut samplers = Vec::new();
+        if let Some(grammar) = grammar {
+            samplers.push(
+                LlamaSampler::grammar(&self.model, grammar, "root").map_err(|e|
+                    anyhow::anyhow!("Failed to load output grammar: {:?}", e)
+                )?
+            );
+        }
+        samplers.push(LlamaSampler::penalties(64, sampling.repeat_penalty, 0.0, 0.0));
+        if sampling.temperature <= 0.0 {
+            samplers.push(LlamaSampler::greedy());
+        } else {
+            if sampling.top_k > 0 {
+                samplers.push(LlamaSampler::top_k(sampling.top_k));
+            }
+            samplers.extend([
+                LlamaSampler::top_p(sampling.top_p, 1),
+                LlamaSampler::temp(sampling.temperature),
+                LlamaSampler::dist(sampling.seed.unwrap_or_else(random_seed)),
+            ]);
+        }
+        let mut sampler = LlamaSampler::chain_simple(samplers);
+
+        let n_ctx = ctx.n_ctx() as i32;
+        if n_past >= n_ctx {
+            anyhow::bail!(
+                "The images and prompt take {} tokens, filling the {} token context window. Raise n_ctx or use fewer images.",
+                n_past,
+                n_ctx
+            );
+        }
+        let max_new_tokens = sampling.max_new_tokens.min((n_ctx - n_past) as usize);
+
+        // One sampled token is decoded at a time
+        let mut batch = LlamaBatch::new(1, 1);
+        let mut decoder = encoding_rs::UTF_8.new_decoder();
+        let mut output = String::new();
+
+        let started = Instant::now();
+        let mut last_report = started;
+        let mut token_count = 0;
+
+        for generated in 1..=max_new_tokens {
+            let token = sampler.sample(ctx, -1);
+
+            if self.model.is_eog_token(token) {
+                break;
+            }
+            token_count = generated;
+
+            // A character can span tokens; the decoder holds back its
+            // incomplete bytes until the rest arrives
+            let bytes = self.model.token_to_bytes(token, Special::Plaintext).context("Failed to decode token")?;
+            decode_utf8(&mut decoder, &bytes, &mut output, false);
+
+            if last_report.elapsed() >= PROGRESS_INTERVAL {
+                last_report = Instant::now();
+                if !progress(generation_progress(generated, started.elapsed())) {
+                    return Err(Cancelled.into());
                 }
-            ],
-            "prompt_guidelines": {
-                "keep_simple": true,
-                "avoid_style_keywords": ["artistic", "stylized", "rendered"],
-                "recommended_additions": ["lighting", "weather", "time of day"]
             }
-        }"###;
 
-        Ok(stub_json.to_string())
+            batch.clear();
+            batch.add(token, n_past, &[0], true).context("Failed to add token to batch")?;
+            n_past += 1;
+
+            ctx.decode(&mut batch).context("Failed to decode batch")?;
+        }
+        decode_utf8(&mut decoder, &[], &mut output, true);
+
+        Ok((output, token_count))
     }
 }
 
-pub fn build_qwen_prompt(sref_code: &str, num_images: usize) -> String {
-    format!(
-        "<|im_start|>system\nYou are Qwen, a vision-language AI assistant specialized in analyzing artistic styles.<|im_end|>
-<|im_start|>user\n{}Analyze these {} style reference images for Midjourney SREF code {}.
+/// Append the text `bytes` complete to `output`; `last` flushes what the
+/// decoder still holds
+fn decode_utf8(decoder: &mut encoding_rs::Decoder, bytes: &[u8], output: &mut String, last: bool) {
+    // The decoder only writes into spare capacity
+    output.reserve(decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len() * 3 + 3));
+    let _ = decoder.decode_to_string(bytes, output, last);
+}
+
+/// Pick a seed for runs without a fixed one
+pub fn random_seed() -> u32 {
+    std::time::SystemTime
+        ::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() ^ (d.as_secs() as u32))
+        .unwrap_or(0)
+}
+
+/// Build a `Generating` report with throughput and an ETA against the
+/// typical spec length
+fn generation_progress(tokens_generated: usize, elapsed: Duration) -> InferenceProgress {
+    let secs = elapsed.as_secs_f32();
+    let tokens_per_second = if secs > 0.0 { (tokens_generated as f32) / secs } else { 0.0 };
+    let eta_secs = (tokens_per_second > 0.0).then(|| {
+        (EXPECTED_SPEC_TOKENS.saturating_sub(tokens_generated) as f32) / tokens_per_second
+    });
 
-Generate a LoRA training dataset specification with:
+    InferenceProgress::Generating {
+        tokens_generated,
+        tokens_per_second,
+        eta_secs,
+    }
+}
+
+/// Extract the JSON object from raw model output and validate it parses
+pub fn extract_json(response: &str) -> Result<String> {
+    let json_start = response.find('{').context("No JSON object in model output")?;
+    let json_end = response.rfind('}').context("Unterminated JSON object in model output")?;
+    if json_end < json_start {
+        anyhow::bail!("Malformed JSON object in model output");
+    }
+
+    let json_str = &response[json_start..=json_end];
+
+    // Validate it parses correctly
+    serde_json::from_str::<serde_json::Value>(json_str)
+        .context("Model output is not valid JSON")?;
+
+    Ok(json_str.to_string())
+}
+
+/// Spec requirements shared by the single-prompt and aggregation instructions
+fn spec_requirements(sref_code: &str) -> String {
+    format!(
+        "Generate a LoRA training dataset specification with:
 1. Style analysis (colors, patterns, era, characteristics)
 2. 8-10 permutation batches with EXACTLY 40 images each
 3. Use format: {{{{subjects}}}} with {{{{modifiers}}}} --sref {}
 
-Output ONLY valid JSON matching the expected schema.<|im_end|>
-<|im_start|>assistant\n",
-        "<|vision_start|><|image_pad|><|vision_end|>".repeat(num_images),
+Output ONLY valid JSON matching the expected schema.",
+        sref_code
+    )
+}
+
+/// User instruction shared by all offline prompt templates
+fn analysis_instruction(sref_code: &str, num_images: usize) -> String {
+    format!(
+        "Analyze these {} style reference images for Midjourney SREF code {}.\n\n{}",
         num_images,
         sref_code,
-        sref_code
+        spec_requirements(sref_code)
     )
 }
 
+/// Instruction for describing one reference image on its own
+pub const CAPTION_INSTRUCTION: &str =
+    "Describe the visual style of this image in 3-5 sentences: color palette, medium and technique, composition, lighting and mood, and any era influence. Describe the style rather than the subject.";
+
+/// Text-only instruction that merges per-image style descriptions into a spec
+pub fn aggregation_instruction(sref_code: &str, captions: &[String]) -> String {
+    let descriptions = captions
+        .iter()
+        .enumerate()
+        .map(|(index, caption)| format!("Image {}: {}", index + 1, caption.trim()))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    format!(
+        "These are style descriptions of {} reference images for Midjourney SREF code {}:\n\n{}\n\nMerge what they have in common into one style analysis.\n\n{}",
+        captions.len(),
+        sref_code,
+        descriptions,
+        spec_requirements(sref_code)
+    )
+}
+
+/// Qwen chat prompt with an image placeholder per image before the instruction
+pub fn build_qwen_chat(instruction: &str, num_images: usize) -> String {
+    format!(
+        "<|im_start|>system\nYou are Qwen, a vision-language AI assistant specialized in analyzing artistic styles.<|im_end|>
+<|im_start|>user\n{}{}<|im_end|>
+<|im_start|>assistant\n",
+        QWEN_IMAGE_PLACEHOLDER.repeat(num_images),
+        instruction
+    )
+}
+
+pub fn build_qwen_prompt(sref_code: &str, num_images: usize) -> String {
+    build_qwen_chat(&analysis_instruction(sref_code, num_images), num_images)
+}
+
+/// Fill a chat template (`{images}` and `{prompt}` placeholders)
+pub fn apply_chat_template(template: &str, instruction: &str, num_images: usize) -> String {
+    template
+        .replace("{images}", &MEDIA_MARKER.repeat(num_images))
+        .replace("{prompt}", instruction)
+}
+
+/// Fill a custom model's chat template (`{images}` and `{prompt}` placeholders)
+pub fn build_prompt_from_template(template: &str, sref_code: &str, num_images: usize) -> String {
+    apply_chat_template(template, &analysis_instruction(sref_code, num_images), num_images)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_decode_utf8_across_tokens() {
+        let mut decoder = encoding_rs::UTF_8.new_decoder();
+        let mut output = String::new();
+        // "é" split over two tokens, then a dangling lead byte
+        decode_utf8(&mut decoder, b"caf\xc3", &mut output, false);
+        assert_eq!(output, "caf");
+        decode_utf8(&mut decoder, b"\xa9 \xe2", &mut output, false);
+        assert_eq!(output, "café ");
+        decode_utf8(&mut decoder, &[], &mut output, true);
+        assert_eq!(output, "café \u{fffd}");
+    }
+
     #[test]
     fn test_prompt_generation() {
         let prompt = build_qwen_prompt("123456", 3);
@@ -215,4 +527,95 @@ mod tests {
         assert!(prompt.contains("3 style reference images"));
         assert!(prompt.contains("<|vision_start|>"));
     }
+
+    #[test]
+    fn test_prompt_from_template() {
+        let template = "USER: {images}\n{prompt}\nASSISTANT:";
+        let prompt = build_prompt_from_template(template, "123456", 2);
+        assert!(prompt.starts_with("USER: <__media__><__media__>\nAnalyze these 2 style"));
+        assert!(prompt.contains("--sref 123456"));
+        assert!(prompt.ends_with("ASSISTANT:"));
+    }
+
+    #[test]
+    fn test_aggregation_prompt_is_text_only() {
+        let captions = vec!["Muted earth tones.".to_string(), " Loose watercolor washes. ".to_string()];
+        let prompt = build_qwen_chat(&aggregation_instruction("123456", &captions), 0);
+        assert!(prompt.contains("2 reference images for Midjourney SREF code 123456"));
+        assert!(prompt.contains("Image 2: Loose watercolor washes.\n"));
+        assert!(prompt.contains("--sref 123456"));
+        assert!(!prompt.contains("<|vision_start|>"));
+    }
+
+    #[test]
+    fn test_generation_progress() {
+        match generation_progress(500, Duration::from_secs(10)) {
+            InferenceProgress::Generating { tokens_generated, tokens_per_second, eta_secs } => {
+                assert_eq!(tokens_generated, 500);
+                assert_eq!(tokens_per_second, 50.0);
+                assert_eq!(eta_secs, Some(40.0));
+            }
+            other => panic!("Unexpected progress: {:?}", other),
+        }
+
+        // Past the expected length the ETA bottoms out at zero
+        match generation_progress(3000, Duration::from_secs(10)) {
+            InferenceProgress::Generating { eta_secs, .. } => assert_eq!(eta_secs, Some(0.0)),
+            other => panic!("Unexpected progress: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_extract_json() {
+        let output = "Here is the spec:\n```json\n{\"sref_code\": \"123\"}\n```";
+        assert_eq!(extract_json(output).unwrap(), "{\"sref_code\": \"123\"}");
+        assert!(extract_json("no json here").is_err());
+        assert!(extract_json("} backwards {").is_err());
+        assert!(extract_json("{\"truncated\": ").is_err());
+    }
+
+    /// Runs real CPU inference against a small GGUF vision model; the
+    /// `inference` CI workflow downloads one and sets both variables.
+    ///
+    /// `LORA_TEST_GGUF=... LORA_TEST_MMPROJ=... cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_real_inference() {
+        let model = PathBuf::from(std::env::var("LORA_TEST_GGUF").expect("LORA_TEST_GGUF must point to a GGUF model"));
+        let mmproj = PathBuf::from(
+            std::env::var("LORA_TEST_MMPROJ").expect("LORA_TEST_MMPROJ must point to the model's mmproj file")
+        );
+
+        let runtime = RuntimeConfig::default();
+        let mut inference = Qwen2VLInference::from_files(&model, &mmproj, ModelVariant::Qwen3VL2B, &runtime).unwrap();
+        let image = DynamicImage::ImageRgb8(
+            image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([(x * 4) as u8, (y * 4) as u8, 128]))
+        );
+        let prompt = build_qwen_prompt("123456", 1);
+
+        let grammar = crate::spec_grammar::dataset_spec_grammar("123456");
+        let mut stages = Vec::new();
+        let sampling = SamplingConfig { seed: Some(42), max_new_tokens: 64, ..SamplingConfig::default() };
+        let output = inference
+            .infer(vec![image], &prompt, Some(&grammar), &sampling, &runtime, &mut |p| {
+                stages.push(p);
+                true
+            })
+            .unwrap();
+
+        assert_eq!(stages[0], InferenceProgress::EncodingImage { index: 1, total: 1 });
+        let prompt_tokens = stages
+            .iter()
+            .find_map(|p| match p {
+                InferenceProgress::PromptEval { tokens } => Some(*tokens),
+                _ => None,
+            })
+            .expect("no prompt_eval progress");
+        assert!(output.stats.prompt_tokens > 0);
+        // Reported after the text is evaluated, so it covers images and text
+        assert!(prompt_tokens as usize > output.stats.prompt_tokens);
+        assert!((1..=64).contains(&output.stats.generated_tokens));
+        // The grammar forces a JSON object, even when the token limit cuts it short
+        assert!(output.text.trim_start().starts_with('{'), "unexpected output: {}", output.text);
+    }
 }
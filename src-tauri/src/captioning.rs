@@ -0,0 +1,150 @@
+use crate::candle_inference::{ Cancelled, InferenceProgress, Qwen2VLInference };
+use crate::settings::{ RuntimeConfig, SamplingConfig };
+use anyhow::{ Context, Result };
+use image::DynamicImage;
+use serde::{ Deserialize, Serialize };
+use sha2::{ Digest, Sha256 };
+use std::path::{ Path, PathBuf };
+
+/// Tokens allowed for one image description
+const CAPTION_MAX_TOKENS: usize = 256;
+
+/// Style description of one reference image, kept with the project as evidence
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImageCaption {
+    pub image_path: String,
+    pub caption: String,
+    /// Reused from an earlier run rather than generated for this one
+    pub cached: bool,
+}
+
+/// Captions on disk for one model, keyed by image pixels and caption prompt
+pub struct CaptionCache {
+    dir: PathBuf,
+    model_key: String,
+}
+
+impl CaptionCache {
+    pub fn new(dir: PathBuf, model_path: &Path) -> Self {
+        Self {
+            dir,
+            model_key: model_path.to_string_lossy().to_string(),
+        }
+    }
+
+    /// Cache in the app's cache directory
+    pub fn open(model_path: &Path) -> Result<Self> {
+        let dir = dirs
+            ::cache_dir()
+            .context("Failed to get cache directory")?
+            .join("rzem-mj-lora")
+            .join("captions");
+        Ok(Self::new(dir, model_path))
+    }
+
+    fn key(&self, image: &DynamicImage, prompt: &str) -> String {
+        let rgb = image.to_rgb8();
+        let mut hasher = Sha256::new();
+        hasher.update(rgb.width().to_le_bytes());
+        hasher.update(rgb.height().to_le_bytes());
+        hasher.update(rgb.as_raw());
+        hasher.update(self.model_key.as_bytes());
+        hasher.update([0]);
+        hasher.update(prompt.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.txt", key))
+    }
+
+    pub fn get(&self, image: &DynamicImage, prompt: &str) -> Option<String> {
+        std::fs::read_to_string(self.path(&self.key(image, prompt))).ok()
+    }
+
+    pub fn put(&self, image: &DynamicImage, prompt: &str, caption: &str) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).context("Failed to create caption cache")?;
+        std::fs
+            ::write(self.path(&self.key(image, prompt)), caption)
+            .context("Failed to write caption cache")?;
+        Ok(())
+    }
+}
+
+/// Describe each image on its own with `prompt` (one image, no grammar),
+/// reusing cached descriptions. Progress is reported per image; returning
+/// `false` cancels.
+pub fn caption_images(
+    model: &mut Qwen2VLInference,
+    images: Vec<(String, DynamicImage)>,
+    prompt: &str,
+    sampling: &SamplingConfig,
+    runtime: &RuntimeConfig,
+    cache: Option<&CaptionCache>,
+    progress: &mut dyn FnMut(InferenceProgress) -> bool
+) -> Result<Vec<ImageCaption>> {
+    let sampling = SamplingConfig {
+        max_new_tokens: sampling.max_new_tokens.min(CAPTION_MAX_TOKENS),
+        ..sampling.clone()
+    };
+    let total = images.len();
+    let mut captions = Vec::with_capacity(total);
+
+    for (index, (image_path, image)) in images.into_iter().enumerate() {
+        let stage = InferenceProgress::CaptioningImage { index: index + 1, total };
+        if !progress(stage.clone()) {
+            return Err(Cancelled.into());
+        }
+
+        if let Some(caption) = cache.and_then(|cache| cache.get(&image, prompt)) {
+            captions.push(ImageCaption { image_path, caption, cached: true });
+            continue;
+        }
+
+        // Inner stages are reported as this image's captioning stage
+        let output = model.infer(vec![image.clone()], prompt, None, &sampling, runtime, &mut |_| {
+            progress(stage.clone())
+        })?;
+        let caption = output.text.trim().to_string();
+        if caption.is_empty() {
+            anyhow::bail!("Model returned no description for image {}", index + 1);
+        }
+
+        if let Some(cache) = cache {
+            if let Err(e) = cache.put(&image, prompt, &caption) {
+                log::warn!("Failed to cache caption: {:#}", e);
+            }
+        }
+        captions.push(ImageCaption { image_path, caption, cached: false });
+    }
+
+    Ok(captions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+    use tempfile::TempDir;
+
+    fn solid(color: [u8; 3]) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(32, 32, image::Rgb(color)))
+    }
+
+    #[test]
+    fn test_caption_cache_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CaptionCache::new(temp_dir.path().join("captions"), Path::new("/models/a.gguf"));
+        let red = solid([255, 0, 0]);
+
+        assert_eq!(cache.get(&red, "Describe"), None);
+        cache.put(&red, "Describe", "Flat red.").unwrap();
+        assert_eq!(cache.get(&red, "Describe").as_deref(), Some("Flat red."));
+
+        // Different pixels, prompt or model miss the cache
+        assert_eq!(cache.get(&solid([0, 0, 255]), "Describe"), None);
+        assert_eq!(cache.get(&red, "Describe briefly"), None);
+        let other = CaptionCache::new(temp_dir.path().join("captions"), Path::new("/models/b.gguf"));
+        assert_eq!(other.get(&red, "Describe"), None);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::gguf;
+use crate::model_manager::ModelStatus;
+use crate::settings::{ AppSettings, CustomModel };
+use anyhow::{ Context, Result };
+
+/// Check that a registration is complete and points at a GGUF language
+/// model plus a GGUF vision projector
+pub fn validate_custom_model(model: &CustomModel) -> Result<()> {
+    if model.name.trim().is_empty() {
+        anyhow::bail!("Model name cannot be empty");
+    }
+    if !model.chat_template.contains("{images}") || !model.chat_template.contains("{prompt}") {
+        anyhow::bail!("Chat template must contain {{images}} and {{prompt}} placeholders");
+    }
+    if model.memory_estimate_gb.is_nan() || model.memory_estimate_gb <= 0.0 {
+        anyhow::bail!("Memory estimate must be greater than zero");
+    }
+
+    let header = gguf::read_header(&model.model_path).context("Model file")?;
+    if header.is_vision_projector() {
+        anyhow::bail!(
+            "{} is a vision projector; select it as the mmproj file instead",
+            model.model_path.display()
+        );
+    }
+
+    let mmproj = gguf::read_header(&model.mmproj_path).context("Vision projector file")?;
+    if !mmproj.is_vision_projector() {
+        anyhow::bail!(
+            "{} is not a vision projector (mmproj) file",
+            model.mmproj_path.display()
+        );
+    }
+
+    let architecture = header.architecture().unwrap_or("unknown");
+    log::info!(
+        "Validated custom model '{}': {} (GGUF v{}, {} tensors, context {:?})",
+        model.name,
+        architecture,
+        header.version,
+        header.tensor_count,
+        header.get_u64(&format!("{}.context_length", architecture))
+    );
+
+    Ok(())
+}
+
+/// Validate and add (or replace) a custom model registration
+pub fn register_custom_model(settings: &mut AppSettings, model: CustomModel) -> Result<()> {
+    validate_custom_model(&model)?;
+
+    settings.custom_models.retain(|m| m.name != model.name);
+    settings.custom_models.push(model);
+
+    Ok(())
+}
+
+/// Status of a registered custom model; Ready once both files validate
+pub fn check_custom_model_status(model: &CustomModel) -> ModelStatus {
+    for path in [&model.model_path, &model.mmproj_path] {
+        if !path.exists() {
+            return ModelStatus::Error {
+                message: format!("Missing model file: {}", path.display()),
+            };
+        }
+    }
+
+    match validate_custom_model(model) {
+        Ok(()) => ModelStatus::Ready,
+        Err(e) => ModelStatus::Error { message: format!("{:#}", e) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf::tests::write_test_gguf;
+    use crate::gguf::GgufValue;
+    use crate::settings::DEFAULT_CHAT_TEMPLATE;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn test_model(dir: &Path, model_arch: &str, mmproj_arch: &str) -> CustomModel {
+        let model_path = dir.join("model.gguf");
+        let mmproj_path = dir.join("mmproj.gguf");
+        write_test_gguf(&model_path, &[("general.architecture", GgufValue::String(model_arch.to_string()))]);
+        write_test_gguf(&mmproj_path, &[("general.architecture", GgufValue::String(mmproj_arch.to_string()))]);
+
+        CustomModel {
+            name: "Test VLM".to_string(),
+            model_path,
+            mmproj_path,
+            chat_template: DEFAULT_CHAT_TEMPLATE.to_string(),
+            memory_estimate_gb: 4.0,
+        }
+    }
+
+    #[test]
+    fn test_register_valid_model() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut settings = AppSettings::default();
+
+        register_custom_model(&mut settings, test_model(temp_dir.path(), "llama", "clip")).unwrap();
+        assert_eq!(settings.custom_models.len(), 1);
+
+        // Re-registering the same name replaces it
+        register_custom_model(&mut settings, test_model(temp_dir.path(), "qwen2vl", "clip")).unwrap();
+        assert_eq!(settings.custom_models.len(), 1);
+
+        let status = check_custom_model_status(&settings.custom_models[0]);
+        assert_eq!(status, ModelStatus::Ready);
+    }
+
+    #[test]
+    fn test_rejects_swapped_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let model = test_model(temp_dir.path(), "clip", "llama");
+        assert!(validate_custom_model(&model).is_err());
+    }
+
+    #[test]
+    fn test_rejects_bad_template() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut model = test_model(temp_dir.path(), "llama", "clip");
+        model.chat_template = "USER: {prompt} ASSISTANT:".to_string();
+        assert!(validate_custom_model(&model).is_err());
+    }
+
+    #[test]
+    fn test_status_missing_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let model = test_model(temp_dir.path(), "llama", "clip");
+        std::fs::remove_file(&model.mmproj_path).unwrap();
+
+        assert!(matches!(check_custom_model_status(&model), ModelStatus::Error { .. }));
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{ Context, Result };
+use serde::Serialize;
+use std::path::{ Path, PathBuf };
+use sysinfo::Disks;
+
+/// Fixed headroom for the verification cache, logs and other small writes
+const HEADROOM_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Proportional headroom, since manifest file sizes are approximate
+const HEADROOM_RATIO: f64 = 0.05;
+
+/// Free space compared with what a download still needs
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DiskCheck {
+    /// Bytes left to download, plus headroom
+    pub required_bytes: u64,
+    pub available_bytes: u64,
+    pub sufficient: bool,
+}
+
+/// Space to require for writing `bytes`
+pub fn with_headroom(bytes: u64) -> u64 {
+    if bytes == 0 {
+        return 0;
+    }
+    bytes + HEADROOM_BYTES + ((bytes as f64) * HEADROOM_RATIO) as u64
+}
+
+/// Free space of the mount point that is the longest prefix of `path`
+fn available_on(mounts: &[(PathBuf, u64)], path: &Path) -> Option<u64> {
+    mounts
+        .iter()
+        .filter(|(mount, _)| path.starts_with(mount))
+        .max_by_key(|(mount, _)| mount.components().count())
+        .map(|(_, available)| *available)
+}
+
+/// Free space on the filesystem that holds `path`, which may not exist yet
+pub fn available_space(path: &Path) -> Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .with_context(|| format!("No existing parent of {:?}", path))?;
+    let resolved = std::fs::canonicalize(existing).with_context(|| format!("Failed to resolve {:?}", existing))?;
+    // Windows canonical paths carry a verbatim prefix mount points don't have
+    let resolved = match resolved.to_str().and_then(|path| path.strip_prefix(r"\\?\")) {
+        Some(stripped) => PathBuf::from(stripped),
+        None => resolved,
+    };
+
+    let mounts: Vec<(PathBuf, u64)> = Disks::new_with_refreshed_list()
+        .list()
+        .iter()
+        .map(|disk| (disk.mount_point().to_path_buf(), disk.available_space()))
+        .collect();
+    available_on(&mounts, &resolved).with_context(|| format!("No disk found for {:?}", resolved))
+}
+
+/// Whether writing `download_bytes` under `path` leaves the headroom free
+pub fn check(download_bytes: u64, path: &Path) -> Result<DiskCheck> {
+    let required_bytes = with_headroom(download_bytes);
+    let available_bytes = available_space(path)?;
+    Ok(DiskCheck {
+        required_bytes,
+        available_bytes,
+        sufficient: available_bytes >= required_bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headroom() {
+        assert_eq!(with_headroom(0), 0);
+        let required = with_headroom(6_000_000_000);
+        assert!(required > 6_000_000_000 + HEADROOM_BYTES);
+        assert!(required < 7_000_000_000);
+    }
+
+    #[test]
+    fn test_longest_mount_point_wins() {
+        let mounts = vec![
+            (PathBuf::from("/"), 10),
+            (PathBuf::from("/home"), 20),
+            (PathBuf::from("/home/user/data"), 30),
+        ];
+        assert_eq!(available_on(&mounts, Path::new("/home/user/.cache/models")), Some(20));
+        assert_eq!(available_on(&mounts, Path::new("/home/user/data/models")), Some(30));
+        // Component-wise, so /homework isn't under /home
+        assert_eq!(available_on(&mounts, Path::new("/homework")), Some(10));
+        assert_eq!(available_on(&mounts[1..], Path::new("/var")), None);
+    }
+
+    #[test]
+    fn test_check_for_missing_directory() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let small = check(1, &temp_dir.path().join("not").join("yet")).unwrap();
+        assert_eq!(small.required_bytes, with_headroom(1));
+        assert_eq!(small.sufficient, small.available_bytes >= small.required_bytes);
+        assert!(!check(u64::MAX / 2, temp_dir.path()).unwrap().sufficient);
+    }
+}
@@ -0,0 +1,255 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{ Duration, Instant };
+
+/// Minimum time between progress events while a file downloads
+const EMIT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Window the transfer rate is averaged over
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Payload of `download-progress` events
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DownloadProgress {
+    pub current_file: usize,
+    pub total_files: usize,
+    pub file_name: String,
+    /// Overall progress across all files
+    pub progress_percent: u8,
+    pub file_downloaded_bytes: u64,
+    pub file_total_bytes: u64,
+    pub downloaded_bytes: u64,
+    pub total_bytes: u64,
+    /// Transfer rate averaged over the last few seconds
+    pub bytes_per_second: f64,
+    /// Time left for the whole download at the current rate
+    pub eta_secs: Option<u64>,
+}
+
+struct TrackedFile {
+    name: String,
+    /// Expected size, replaced by the server's size once the file starts
+    size: u64,
+    downloaded: u64,
+}
+
+/// Turns byte counts from a multi-file download into throttled progress
+/// events with a rolling transfer rate and an ETA
+pub struct ProgressTracker {
+    files: Vec<TrackedFile>,
+    current: usize,
+    /// Bytes received over the network, excluding files already on disk
+    transferred: u64,
+    samples: VecDeque<(Instant, u64)>,
+    last_emit: Option<Instant>,
+    sink: Box<dyn FnMut(DownloadProgress) + Send>,
+}
+
+impl ProgressTracker {
+    /// Track `files` (name and expected size), reporting to `sink`
+    pub fn new(files: Vec<(String, u64)>, sink: impl FnMut(DownloadProgress) + Send + 'static) -> Self {
+        Self {
+            files: files
+                .into_iter()
+                .map(|(name, size)| TrackedFile { name, size, downloaded: 0 })
+                .collect(),
+            current: 0,
+            transferred: 0,
+            samples: VecDeque::new(),
+            last_emit: None,
+            sink: Box::new(sink),
+        }
+    }
+
+    /// File `index` starts downloading, `resumed_from` bytes in when an
+    /// earlier attempt left a partial file; `size` is its real size when known
+    pub fn start_file(&mut self, index: usize, size: Option<u64>, resumed_from: u64, now: Instant) {
+        self.current = index.min(self.files.len().saturating_sub(1));
+        if let Some(file) = self.files.get_mut(self.current) {
+            if let Some(size) = size.filter(|size| *size > 0) {
+                file.size = size;
+            }
+            file.downloaded = resumed_from;
+        }
+        self.record_sample(now);
+        self.emit(now);
+    }
+
+    /// `bytes` more of the current file arrived
+    pub fn advance(&mut self, bytes: u64, now: Instant) {
+        if let Some(file) = self.files.get_mut(self.current) {
+            file.downloaded += bytes;
+        }
+        self.transferred += bytes;
+        self.record_sample(now);
+
+        let due = self.last_emit.is_none_or(|last| now.duration_since(last) >= EMIT_INTERVAL);
+        if due {
+            self.emit(now);
+        }
+    }
+
+    /// The current file is complete
+    pub fn finish_file(&mut self, now: Instant) {
+        if let Some(file) = self.files.get_mut(self.current) {
+            file.size = file.size.max(file.downloaded);
+            file.downloaded = file.size;
+        }
+        self.emit(now);
+    }
+
+    /// File `index` was already on disk and needs no transfer
+    pub fn skip_file(&mut self, index: usize, size: u64, now: Instant) {
+        self.current = index.min(self.files.len().saturating_sub(1));
+        if let Some(file) = self.files.get_mut(self.current) {
+            file.size = size;
+            file.downloaded = size;
+        }
+        self.emit(now);
+    }
+
+    /// All files are done
+    pub fn complete(&mut self, now: Instant) {
+        for file in &mut self.files {
+            file.downloaded = file.size;
+        }
+        self.last_emit = Some(now);
+        let mut progress = self.snapshot();
+        progress.current_file = self.files.len();
+        progress.file_name = "Complete".to_string();
+        progress.progress_percent = 100;
+        progress.eta_secs = Some(0);
+        (self.sink)(progress);
+    }
+
+    fn record_sample(&mut self, now: Instant) {
+        self.samples.push_back((now, self.transferred));
+        // Keep one sample older than the window so the rate spans all of it
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= RATE_WINDOW {
+            self.samples.pop_front();
+        }
+    }
+
+    fn bytes_per_second(&self) -> f64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((first_at, first)), Some((last_at, last))) => {
+                let secs = last_at.duration_since(*first_at).as_secs_f64();
+                if secs > 0.0 { ((last - first) as f64) / secs } else { 0.0 }
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn snapshot(&self) -> DownloadProgress {
+        let total_bytes: u64 = self.files
+            .iter()
+            .map(|file| file.size)
+            .sum();
+        let downloaded_bytes: u64 = self.files
+            .iter()
+            .map(|file| file.downloaded.min(file.size))
+            .sum();
+        let file = self.files.get(self.current);
+
+        let bytes_per_second = self.bytes_per_second();
+        let remaining = total_bytes.saturating_sub(downloaded_bytes);
+        let eta_secs = if remaining == 0 {
+            Some(0)
+        } else if bytes_per_second > 0.0 {
+            Some(((remaining as f64) / bytes_per_second).ceil() as u64)
+        } else {
+            None
+        };
+
+        DownloadProgress {
+            current_file: self.current + 1,
+            total_files: self.files.len(),
+            file_name: file.map(|file| file.name.clone()).unwrap_or_default(),
+            progress_percent: (downloaded_bytes * 100).checked_div(total_bytes).map_or(0, |percent| percent.min(100) as u8),
+            file_downloaded_bytes: file.map_or(0, |file| file.downloaded),
+            file_total_bytes: file.map_or(0, |file| file.size),
+            downloaded_bytes,
+            total_bytes,
+            bytes_per_second,
+            eta_secs,
+        }
+    }
+
+    fn emit(&mut self, now: Instant) {
+        self.last_emit = Some(now);
+        let progress = self.snapshot();
+        (self.sink)(progress);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{ Arc, Mutex };
+
+    fn tracker(files: Vec<(String, u64)>) -> (ProgressTracker, Arc<Mutex<Vec<DownloadProgress>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        (ProgressTracker::new(files, move |progress| sink.lock().unwrap().push(progress)), events)
+    }
+
+    #[test]
+    fn test_byte_progress_rate_and_eta() {
+        let (mut tracker, events) = tracker(vec![("model.gguf".to_string(), 1000), ("mmproj.gguf".to_string(), 500)]);
+        let start = Instant::now();
+
+        // The server reports a slightly different size than the manifest
+        tracker.start_file(0, Some(1500), 0, start);
+        for second in 1..=3 {
+            tracker.advance(100, start + Duration::from_secs(second));
+        }
+
+        let last = events.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(last.current_file, 1);
+        assert_eq!(last.file_downloaded_bytes, 300);
+        assert_eq!(last.file_total_bytes, 1500);
+        assert_eq!(last.total_bytes, 2000);
+        assert_eq!(last.progress_percent, 15);
+        assert_eq!(last.bytes_per_second, 100.0);
+        assert_eq!(last.eta_secs, Some(17));
+
+        tracker.finish_file(start + Duration::from_secs(4));
+        tracker.skip_file(1, 500, start + Duration::from_secs(4));
+        tracker.complete(start + Duration::from_secs(4));
+        let last = events.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(last.progress_percent, 100);
+        assert_eq!(last.downloaded_bytes, 2000);
+        assert_eq!(last.file_name, "Complete");
+    }
+
+    #[test]
+    fn test_events_are_throttled() {
+        let (mut tracker, events) = tracker(vec![("model.gguf".to_string(), 1_000_000)]);
+        let start = Instant::now();
+
+        tracker.start_file(0, None, 0, start);
+        // One chunk per millisecond for a second
+        for ms in 1..=1000 {
+            tracker.advance(1000, start + Duration::from_millis(ms));
+        }
+
+        // The start event plus one every 250ms
+        assert_eq!(events.lock().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_rate_uses_recent_window() {
+        let (mut tracker, events) = tracker(vec![("model.gguf".to_string(), 1_000_000)]);
+        let start = Instant::now();
+
+        tracker.start_file(0, None, 0, start);
+        // Fast at first, then 10 bytes per second
+        tracker.advance(100_000, start + Duration::from_secs(1));
+        for second in 2..=20 {
+            tracker.advance(10, start + Duration::from_secs(second));
+        }
+
+        let rate = events.lock().unwrap().last().unwrap().bytes_per_second;
+        assert!((rate - 10.0).abs() < 1.0, "{}", rate);
+    }
+}
@@ -0,0 +1,339 @@
+use crate::image_preprocess::load_image;
+use crate::settings::AppSettings;
+use anyhow::{ Context, Result };
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Marks every fake spec so it can't pass for a real analysis
+pub(crate) const FAKE_STYLE_PREFIX: &str = "[Fake provider]";
+
+const STYLES: &[&str] = &[
+    "Art deco poster",
+    "Soft watercolor illustration",
+    "Gritty comic ink",
+    "Pastel risograph print",
+    "Moody oil painting",
+    "Clean vector flat design",
+];
+const ERAS: &[&str] = &["1920s", "1950s", "1970s", "1990s", "Contemporary"];
+const COLORS: &[&str] = &[
+    "teal",
+    "burnt orange",
+    "cream",
+    "deep navy",
+    "mustard",
+    "coral",
+    "charcoal",
+    "sage green",
+];
+const CHARACTERISTICS: &[&str] = &[
+    "bold outlines",
+    "grainy texture",
+    "limited palette",
+    "strong diagonal composition",
+    "soft gradients",
+    "high contrast lighting",
+];
+/// Batch categories with ten subjects each
+const CATEGORIES: &[(&str, &[&str])] = &[
+    (
+        "Architecture",
+        &[
+            "lighthouse",
+            "cathedral",
+            "bridge",
+            "skyscraper",
+            "windmill",
+            "castle",
+            "train station",
+            "greenhouse",
+            "temple",
+            "farmhouse",
+        ],
+    ),
+    (
+        "Nature",
+        &[
+            "oak tree",
+            "mountain lake",
+            "fern",
+            "desert dunes",
+            "waterfall",
+            "meadow",
+            "coral reef",
+            "canyon",
+            "birch forest",
+            "glacier",
+        ],
+    ),
+    (
+        "Animals",
+        &["fox", "heron", "horse", "owl", "whale", "rabbit", "tiger", "octopus", "sparrow", "deer"],
+    ),
+    (
+        "People",
+        &[
+            "dancer",
+            "fisherman",
+            "reader",
+            "cyclist",
+            "gardener",
+            "violinist",
+            "chef",
+            "astronaut",
+            "child",
+            "old sailor",
+        ],
+    ),
+    (
+        "Objects",
+        &[
+            "teapot",
+            "bicycle",
+            "typewriter",
+            "lantern",
+            "pocket watch",
+            "umbrella",
+            "guitar",
+            "camera",
+            "vase",
+            "armchair",
+        ],
+    ),
+];
+const MODIFIERS: &[&str] = &[
+    "at dawn",
+    "at dusk",
+    "in fog",
+    "in rain",
+    "under moonlight",
+    "in harsh sunlight",
+];
+/// Subject and modifier counts that multiply to 40 images
+const BATCH_SHAPES: &[(usize, usize)] = &[(8, 5), (10, 4)];
+const PRIORITIES: &[&str] = &["high", "medium", "low"];
+
+/// Whether the fake provider may be selected: development builds, or
+/// release builds with `allow_fake_provider` set in the settings file
+pub fn is_enabled(settings: &AppSettings) -> bool {
+    cfg!(debug_assertions) || settings.allow_fake_provider
+}
+
+/// SplitMix64, so the same seed always gives the same spec
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % (n as u64)) as usize
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+
+    /// `count` distinct items, in a seeded order
+    fn sample<T: Copy>(&mut self, items: &[T], count: usize) -> Vec<T> {
+        let mut pool = items.to_vec();
+        let mut picked = Vec::new();
+        while picked.len() < count && !pool.is_empty() {
+            picked.push(pool.remove(self.below(pool.len())));
+        }
+        picked
+    }
+}
+
+/// Seed from the inputs (FNV-1a), used when no sampling seed is fixed
+pub fn default_seed(sref_code: &str, image_count: usize) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in sref_code.bytes().chain((image_count as u64).to_le_bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Field order follows the dataset spec schema, which the grammar enforces
+
+#[derive(Serialize)]
+struct FakeSpec {
+    sref_code: String,
+    style_analysis: StyleAnalysis,
+    training_recommendations: TrainingRecommendations,
+    permutation_batches: Vec<Batch>,
+    prompt_guidelines: PromptGuidelines,
+}
+
+#[derive(Serialize)]
+struct StyleAnalysis {
+    primary_style: String,
+    era_influence: String,
+    color_palette: Vec<&'static str>,
+    key_characteristics: Vec<String>,
+    best_subjects: Vec<&'static str>,
+    avoid_subjects: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct TrainingRecommendations {
+    recommended_dataset_size: usize,
+    optimal_subject_distribution: BTreeMap<String, f64>,
+}
+
+#[derive(Serialize)]
+struct Batch {
+    batch_number: usize,
+    batch_name: String,
+    category: &'static str,
+    image_count: usize,
+    prompt: String,
+    priority: &'static str,
+}
+
+#[derive(Serialize)]
+struct PromptGuidelines {
+    keep_simple: bool,
+    avoid_style_keywords: Vec<&'static str>,
+    recommended_additions: Vec<&'static str>,
+}
+
+/// A schema-valid dataset specification for `sref_code`, varied by `seed`
+pub fn generate_spec(sref_code: &str, image_count: usize, seed: u64) -> Result<String> {
+    let mut rng = Rng(seed);
+
+    let batch_count = 8 + rng.below(3);
+    let mut category_counts: BTreeMap<String, usize> = BTreeMap::new();
+    let permutation_batches = (1..=batch_count)
+        .map(|batch_number| {
+            let (category, subjects) = rng.pick(CATEGORIES);
+            *category_counts.entry(category.to_lowercase()).or_default() += 1;
+
+            let (subject_count, modifier_count) = rng.pick(BATCH_SHAPES);
+            let subjects = rng.sample(subjects, subject_count).join(", ");
+            let modifiers = rng.sample(MODIFIERS, modifier_count).join(", ");
+            Batch {
+                batch_number,
+                batch_name: format!("{} {}", category, batch_number),
+                category,
+                image_count: 40,
+                prompt: format!("{{{}}} with {{{}}} --sref {}", subjects, modifiers, sref_code),
+                priority: PRIORITIES[((batch_number - 1) * PRIORITIES.len()) / batch_count],
+            }
+        })
+        .collect();
+
+    // Whole percentages keep shares in plain decimal form, without
+    // exponents; the last category takes the rounding remainder so the
+    // shares add up to 1
+    let last = category_counts.len().saturating_sub(1);
+    let mut remaining = 100;
+    let optimal_subject_distribution = category_counts
+        .into_iter()
+        .enumerate()
+        .map(|(index, (category, count))| {
+            let percent = if index == last {
+                remaining
+            } else {
+                ((count * 100) as f64 / batch_count as f64).round() as usize
+            };
+            remaining -= percent;
+            (category, percent as f64 / 100.0)
+        })
+        .collect();
+
+    let spec = FakeSpec {
+        sref_code: sref_code.to_string(),
+        style_analysis: StyleAnalysis {
+            primary_style: format!("{} {}", FAKE_STYLE_PREFIX, rng.pick(STYLES)),
+            era_influence: rng.pick(ERAS).to_string(),
+            color_palette: rng.sample(COLORS, 4),
+            key_characteristics: vec![
+                rng.pick(CHARACTERISTICS).to_string(),
+                format!("generated from {} reference images without a model", image_count)
+            ],
+            best_subjects: rng.sample(&["landscapes", "portraits", "architecture", "still life"], 2),
+            avoid_subjects: rng.sample(&["text", "logos", "crowds"], 1),
+        },
+        training_recommendations: TrainingRecommendations {
+            recommended_dataset_size: batch_count * 40,
+            optimal_subject_distribution,
+        },
+        permutation_batches,
+        prompt_guidelines: PromptGuidelines {
+            keep_simple: true,
+            avoid_style_keywords: vec!["style", "aesthetic"],
+            recommended_additions: rng.sample(&["lighting", "camera angle", "time of day", "weather"], 2),
+        },
+    };
+
+    serde_json::to_string_pretty(&spec).context("Failed to serialize fake spec")
+}
+
+/// Check the images like a real analysis would, then generate a spec seeded
+/// by the sampling seed, or by the SREF code and image count
+pub fn analyze_style(image_paths: &[String], sref_code: &str, settings: &AppSettings) -> Result<String> {
+    if !is_enabled(settings) {
+        anyhow::bail!("The fake provider is only available in development builds or when allow_fake_provider is set");
+    }
+
+    for path in image_paths {
+        load_image(Path::new(path)).with_context(|| format!("Failed to load image {}", path))?;
+    }
+
+    let seed = settings.sampling.seed
+        .map(u64::from)
+        .unwrap_or_else(|| default_seed(sref_code, image_paths.len()));
+    log::info!("Generating fake spec for SREF {} with seed {}", sref_code, seed);
+    generate_spec(sref_code, image_paths.len(), seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec_grammar::{ dataset_spec_grammar, Grammar };
+
+    #[test]
+    fn test_spec_matches_schema() {
+        for seed in 0..20 {
+            let spec = generate_spec("987654", 5, seed).unwrap();
+            let grammar = Grammar::parse(&dataset_spec_grammar("987654")).unwrap();
+            assert!(grammar.accepts(&spec), "seed {} produced an invalid spec:\n{}", seed, spec);
+
+            // Every batch expands to exactly 40 images, and the shares of
+            // the categories add up
+            let spec: serde_json::Value = serde_json::from_str(&spec).unwrap();
+            let total: f64 = spec["training_recommendations"]["optimal_subject_distribution"]
+                .as_object()
+                .unwrap()
+                .values()
+                .map(|share| share.as_f64().unwrap())
+                .sum();
+            assert!((total - 1.0).abs() < 1e-9, "shares add up to {}", total);
+            for batch in spec["permutation_batches"].as_array().unwrap() {
+                let prompt = batch["prompt"].as_str().unwrap();
+                let count: usize = prompt
+                    .split('{')
+                    .skip(1)
+                    .map(|block| block.split('}').next().unwrap().split(',').count())
+                    .product();
+                assert_eq!(count, 40, "{}", prompt);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spec_is_deterministic() {
+        let seed = default_seed("987654", 5);
+        assert_eq!(generate_spec("987654", 5, seed).unwrap(), generate_spec("987654", 5, seed).unwrap());
+        assert_ne!(seed, default_seed("987654", 6));
+        assert_ne!(generate_spec("987654", 5, 1).unwrap(), generate_spec("987654", 5, 2).unwrap());
+    }
+}
@@ -0,0 +1,409 @@
+use anyhow::{ Context, Result };
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{ BufReader, Read };
+use std::path::Path;
+
+/// "GGUF" in little-endian byte order
+const GGUF_MAGIC: u32 = 0x4655_4747;
+
+/// Arrays longer than this are skipped rather than kept in memory (tokenizer vocabularies)
+const MAX_STORED_ARRAY_LEN: u64 = 1024;
+
+/// Sanity limit for a single metadata string
+const MAX_STRING_LEN: u64 = 64 * 1024 * 1024;
+
+/// ggml tensors have at most four dimensions
+const MAX_TENSOR_DIMS: u32 = 4;
+
+/// A GGUF metadata value
+#[derive(Debug, Clone, PartialEq)]
+pub enum GgufValue {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    String(String),
+    /// Array length and, for short arrays, the values
+    Array(u64, Vec<GgufValue>),
+}
+
+impl GgufValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            GgufValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            GgufValue::U8(v) => Some(v as u64),
+            GgufValue::U16(v) => Some(v as u64),
+            GgufValue::U32(v) => Some(v as u64),
+            GgufValue::U64(v) => Some(v),
+            GgufValue::I8(v) => u64::try_from(v).ok(),
+            GgufValue::I16(v) => u64::try_from(v).ok(),
+            GgufValue::I32(v) => u64::try_from(v).ok(),
+            GgufValue::I64(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Name, shape and storage type of one tensor
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorInfo {
+    pub name: String,
+    pub dims: Vec<u64>,
+    /// ggml type id
+    pub ggml_type: u32,
+}
+
+impl TensorInfo {
+    pub fn element_count(&self) -> u64 {
+        self.dims.iter().product()
+    }
+
+    /// Bytes of tensor data, or `None` for ggml types this reader doesn't know
+    pub fn size_bytes(&self) -> Option<u64> {
+        let (block_size, type_size) = ggml_type_size(self.ggml_type)?;
+        Some(self.element_count().div_ceil(block_size) * type_size)
+    }
+}
+
+/// (elements per block, bytes per block) for each ggml type id
+fn ggml_type_size(ggml_type: u32) -> Option<(u64, u64)> {
+    Some(match ggml_type {
+        0 => (1, 4), // F32
+        1 => (1, 2), // F16
+        2 => (32, 18), // Q4_0
+        3 => (32, 20), // Q4_1
+        6 => (32, 22), // Q5_0
+        7 => (32, 24), // Q5_1
+        8 => (32, 34), // Q8_0
+        9 => (32, 36), // Q8_1
+        10 => (256, 84), // Q2_K
+        11 => (256, 110), // Q3_K
+        12 => (256, 144), // Q4_K
+        13 => (256, 176), // Q5_K
+        14 => (256, 210), // Q6_K
+        15 => (256, 292), // Q8_K
+        16 => (256, 66), // IQ2_XXS
+        17 => (256, 74), // IQ2_XS
+        18 => (256, 98), // IQ3_XXS
+        19 => (256, 50), // IQ1_S
+        20 => (32, 18), // IQ4_NL
+        21 => (256, 110), // IQ3_S
+        22 => (256, 82), // IQ2_S
+        23 => (256, 136), // IQ4_XS
+        24 => (1, 1), // I8
+        25 => (1, 2), // I16
+        26 => (1, 4), // I32
+        27 => (1, 8), // I64
+        28 => (1, 8), // F64
+        29 => (256, 56), // IQ1_M
+        30 => (1, 2), // BF16
+        _ => {
+            return None;
+        }
+    })
+}
+
+/// Header, key/value metadata and tensor directory of a GGUF file
+#[derive(Debug, Clone)]
+pub struct GgufHeader {
+    pub version: u32,
+    pub tensor_count: u64,
+    pub metadata: HashMap<String, GgufValue>,
+    pub tensors: Vec<TensorInfo>,
+}
+
+impl GgufHeader {
+    /// `general.architecture`, e.g. "qwen3vl" for a language model or "clip" for an mmproj
+    pub fn architecture(&self) -> Option<&str> {
+        self.get_str("general.architecture")
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).and_then(GgufValue::as_str)
+    }
+
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.metadata.get(key).and_then(GgufValue::as_u64)
+    }
+
+    /// Length of an array value (available even when the items were skipped)
+    pub fn array_len(&self, key: &str) -> Option<u64> {
+        match self.metadata.get(key) {
+            Some(GgufValue::Array(len, _)) => Some(*len),
+            _ => None,
+        }
+    }
+
+    /// `{architecture}.{suffix}` as an integer, e.g. `block_count`
+    pub fn arch_u64(&self, suffix: &str) -> Option<u64> {
+        let architecture = self.architecture()?;
+        self.get_u64(&format!("{}.{}", architecture, suffix))
+    }
+
+    /// Total size of all tensor data, or `None` if a tensor type is unknown
+    pub fn tensor_data_bytes(&self) -> Option<u64> {
+        self.tensors.iter().map(TensorInfo::size_bytes).sum()
+    }
+
+    /// Whether this file is a vision projector rather than a language model
+    pub fn is_vision_projector(&self) -> bool {
+        self.architecture() == Some("clip")
+    }
+}
+
+/// Read the header and metadata of a GGUF file
+pub fn read_header(path: &Path) -> Result<GgufHeader> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = GgufReader { inner: BufReader::new(file) };
+
+    parse_header(&mut reader).with_context(|| format!("Invalid GGUF file: {}", path.display()))
+}
+
+fn parse_header<R: Read>(reader: &mut GgufReader<R>) -> Result<GgufHeader> {
+    if reader.u32()? != GGUF_MAGIC {
+        anyhow::bail!("Not a GGUF file (bad magic)");
+    }
+
+    let version = reader.u32()?;
+    if !(2..=3).contains(&version) {
+        anyhow::bail!("Unsupported GGUF version {}", version);
+    }
+
+    let tensor_count = reader.u64()?;
+    let kv_count = reader.u64()?;
+
+    let mut metadata = HashMap::new();
+    for _ in 0..kv_count {
+        let key = reader.string()?;
+        let value_type = reader.u32()?;
+        let value = reader.value(value_type)?;
+        metadata.insert(key, value);
+    }
+
+    let mut tensors = Vec::new();
+    for _ in 0..tensor_count {
+        let name = reader.string()?;
+        let n_dims = reader.u32()?;
+        if n_dims > MAX_TENSOR_DIMS {
+            anyhow::bail!("Tensor {} has {} dimensions", name, n_dims);
+        }
+        let dims = (0..n_dims).map(|_| reader.u64()).collect::<Result<Vec<_>>>()?;
+        let ggml_type = reader.u32()?;
+        let _offset = reader.u64()?;
+        tensors.push(TensorInfo { name, dims, ggml_type });
+    }
+
+    Ok(GgufHeader {
+        version,
+        tensor_count,
+        metadata,
+        tensors,
+    })
+}
+
+struct GgufReader<R> {
+    inner: R,
+}
+
+impl<R: Read> GgufReader<R> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.inner.read_exact(&mut buf).context("Unexpected end of file")?;
+        Ok(buf)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u64()?;
+        if len > MAX_STRING_LEN {
+            anyhow::bail!("Metadata string too long ({} bytes)", len);
+        }
+
+        let mut buf = vec![0u8; len as usize];
+        self.inner.read_exact(&mut buf).context("Unexpected end of file")?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    fn value(&mut self, value_type: u32) -> Result<GgufValue> {
+        Ok(match value_type {
+            0 => GgufValue::U8(u8::from_le_bytes(self.bytes()?)),
+            1 => GgufValue::I8(i8::from_le_bytes(self.bytes()?)),
+            2 => GgufValue::U16(u16::from_le_bytes(self.bytes()?)),
+            3 => GgufValue::I16(i16::from_le_bytes(self.bytes()?)),
+            4 => GgufValue::U32(u32::from_le_bytes(self.bytes()?)),
+            5 => GgufValue::I32(i32::from_le_bytes(self.bytes()?)),
+            6 => GgufValue::F32(f32::from_le_bytes(self.bytes()?)),
+            7 => GgufValue::Bool(self.bytes::<1>()?[0] != 0),
+            8 => GgufValue::String(self.string()?),
+            9 => {
+                let item_type = self.u32()?;
+                let len = self.u64()?;
+                let mut values = Vec::new();
+                for _ in 0..len {
+                    let value = self.value(item_type)?;
+                    if len <= MAX_STORED_ARRAY_LEN {
+                        values.push(value);
+                    }
+                }
+                GgufValue::Array(len, values)
+            }
+            10 => GgufValue::U64(u64::from_le_bytes(self.bytes()?)),
+            11 => GgufValue::I64(i64::from_le_bytes(self.bytes()?)),
+            12 => GgufValue::F64(f64::from_le_bytes(self.bytes()?)),
+            other => anyhow::bail!("Unknown metadata value type {}", other),
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Write a minimal GGUF v3 file with string and u32 metadata
+    pub(crate) fn write_test_gguf(path: &Path, metadata: &[(&str, GgufValue)]) {
+        write_test_gguf_with_tensors(path, metadata, &[]);
+    }
+
+    /// Like `write_test_gguf`, plus a tensor directory (no tensor data);
+    /// u32 arrays are written with their length only
+    pub(crate) fn write_test_gguf_with_tensors(
+        path: &Path,
+        metadata: &[(&str, GgufValue)],
+        tensors: &[TensorInfo]
+    ) {
+        let put_str = |bytes: &mut Vec<u8>, s: &str| {
+            bytes.extend_from_slice(&(s.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(s.as_bytes());
+        };
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&GGUF_MAGIC.to_le_bytes());
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(&(tensors.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(metadata.len() as u64).to_le_bytes());
+
+        for (key, value) in metadata {
+            put_str(&mut bytes, key);
+            match value {
+                GgufValue::String(s) => {
+                    bytes.extend_from_slice(&8u32.to_le_bytes());
+                    put_str(&mut bytes, s);
+                }
+                GgufValue::U32(v) => {
+                    bytes.extend_from_slice(&4u32.to_le_bytes());
+                    bytes.extend_from_slice(&v.to_le_bytes());
+                }
+                GgufValue::Array(len, _) => {
+                    bytes.extend_from_slice(&9u32.to_le_bytes());
+                    bytes.extend_from_slice(&4u32.to_le_bytes());
+                    bytes.extend_from_slice(&len.to_le_bytes());
+                    for i in 0..*len {
+                        bytes.extend_from_slice(&(i as u32).to_le_bytes());
+                    }
+                }
+                other => panic!("Unsupported test value {:?}", other),
+            }
+        }
+
+        for tensor in tensors {
+            put_str(&mut bytes, &tensor.name);
+            bytes.extend_from_slice(&(tensor.dims.len() as u32).to_le_bytes());
+            for dim in &tensor.dims {
+                bytes.extend_from_slice(&dim.to_le_bytes());
+            }
+            bytes.extend_from_slice(&tensor.ggml_type.to_le_bytes());
+            bytes.extend_from_slice(&0u64.to_le_bytes());
+        }
+
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_read_header() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("model.gguf");
+        write_test_gguf(&path, &[
+            ("general.architecture", GgufValue::String("qwen3vl".to_string())),
+            ("qwen3vl.context_length", GgufValue::U32(32768)),
+        ]);
+
+        let header = read_header(&path).unwrap();
+        assert_eq!(header.version, 3);
+        assert_eq!(header.architecture(), Some("qwen3vl"));
+        assert_eq!(header.get_u64("qwen3vl.context_length"), Some(32768));
+        assert!(!header.is_vision_projector());
+    }
+
+    #[test]
+    fn test_tensor_sizes() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("model.gguf");
+        let tensors = [
+            TensorInfo { name: "token_embd.weight".to_string(), dims: vec![2048, 1000], ggml_type: 8 },
+            TensorInfo { name: "output_norm.weight".to_string(), dims: vec![2048], ggml_type: 0 },
+        ];
+        write_test_gguf_with_tensors(
+            &path,
+            &[
+                ("general.architecture", GgufValue::String("qwen3vl".to_string())),
+                ("tokenizer.ggml.tokens", GgufValue::Array(2000, Vec::new())),
+            ],
+            &tensors
+        );
+
+        let header = read_header(&path).unwrap();
+        assert_eq!(header.tensors, tensors);
+        assert_eq!(header.array_len("tokenizer.ggml.tokens"), Some(2000));
+        // Q8_0: 34 bytes per 32 weights; F32: 4 bytes per weight
+        assert_eq!(header.tensor_data_bytes(), Some(2048 * 1000 / 32 * 34 + 2048 * 4));
+    }
+
+    #[test]
+    fn test_unknown_tensor_type() {
+        let tensor = TensorInfo { name: "x".to_string(), dims: vec![32], ggml_type: 999 };
+        assert_eq!(tensor.size_bytes(), None);
+    }
+
+    #[test]
+    fn test_rejects_non_gguf() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("model.gguf");
+        std::fs::write(&path, b"definitely not a model").unwrap();
+
+        assert!(read_header(&path).is_err());
+    }
+
+    #[test]
+    fn test_rejects_truncated_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("model.gguf");
+        write_test_gguf(&path, &[("general.architecture", GgufValue::String("clip".to_string()))]);
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 2]).unwrap();
+
+        assert!(read_header(&path).is_err());
+    }
+}
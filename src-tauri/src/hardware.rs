@@ -0,0 +1,232 @@
+use crate::memory_estimate::BYTES_PER_GB;
+use crate::settings::{ AppSettings, ModelVariant, QuantizationConfig, RuntimeConfig, SamplingConfig };
+use serde::Serialize;
+use sysinfo::System;
+
+/// Machines with less RAM than this get a smaller default context
+const SMALL_MACHINE_GB: f32 = 8.0;
+
+/// What this machine offers for local inference
+#[derive(Debug, Clone, Serialize)]
+pub struct HardwareProfile {
+    pub cpu_brand: String,
+    pub physical_cores: u32,
+    pub logical_cores: u32,
+    /// SIMD extensions llama.cpp can use, e.g. "avx2", "avx512f", "neon"
+    pub cpu_features: Vec<String>,
+    pub total_memory_gb: f32,
+    pub available_memory_gb: f32,
+    /// Compiled llama.cpp backends ("cpu", plus "cuda"/"metal" when enabled)
+    pub backends: Vec<String>,
+    /// Suggested model and runtime settings, if any model fits
+    pub recommendation: Option<Recommendation>,
+}
+
+/// Variant and settings suggested for this machine
+#[derive(Debug, Clone, Serialize)]
+pub struct Recommendation {
+    pub variant: ModelVariant,
+    pub quantization: QuantizationConfig,
+    pub runtime: RuntimeConfig,
+    /// Generation limit that leaves room for images in the recommended context
+    pub max_new_tokens: usize,
+    pub required_memory_gb: f32,
+}
+
+/// Physical CPU cores, falling back to logical cores when unknown
+pub fn physical_core_count() -> u32 {
+    System::new()
+        .physical_core_count()
+        .map(|n| n as u32)
+        .unwrap_or_else(logical_core_count)
+        .max(1)
+}
+
+fn logical_core_count() -> u32 {
+    std::thread
+        ::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(4)
+}
+
+/// Threads to run llama.cpp with; hyperthreads don't help token generation,
+/// and more threads than the CPUs available to the process only contend
+pub fn thread_count(runtime: &RuntimeConfig) -> u32 {
+    runtime.n_threads
+        .filter(|&n| n > 0)
+        .unwrap_or_else(physical_core_count)
+        .min(logical_core_count())
+}
+
+#[cfg(target_arch = "x86_64")]
+fn cpu_features() -> Vec<String> {
+    let mut features = Vec::new();
+    macro_rules! detect {
+        ($($feature:tt),*) => {
+            $(
+                if std::arch::is_x86_feature_detected!($feature) {
+                    features.push($feature.to_string());
+                }
+            )*
+        };
+    }
+    detect!("sse4.2", "avx", "avx2", "fma", "f16c", "avx512f", "avx512bw", "avx512vnni");
+    features
+}
+
+#[cfg(target_arch = "aarch64")]
+fn cpu_features() -> Vec<String> {
+    let mut features = Vec::new();
+    macro_rules! detect {
+        ($($feature:tt),*) => {
+            $(
+                if std::arch::is_aarch64_feature_detected!($feature) {
+                    features.push($feature.to_string());
+                }
+            )*
+        };
+    }
+    detect!("neon", "dotprod", "i8mm", "sve");
+    features
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn cpu_features() -> Vec<String> {
+    Vec::new()
+}
+
+fn compiled_backends() -> Vec<String> {
+    let mut backends = vec!["cpu".to_string()];
+    if cfg!(feature = "cuda") {
+        backends.push("cuda".to_string());
+    }
+    if cfg!(feature = "metal") {
+        backends.push("metal".to_string());
+    }
+    backends
+}
+
+/// Runtime settings suited to the detected hardware. The compute buffers
+/// grow with the batch size, so small machines take smaller batches; a GPU
+/// evaluates images and prompts faster in large logical batches, while CPU
+/// throughput stops improving past a 512-token physical batch.
+pub fn recommended_runtime(profile: &HardwareProfile) -> RuntimeConfig {
+    let has_gpu = profile.backends.iter().any(|b| b != "cpu");
+    let small = profile.total_memory_gb < SMALL_MACHINE_GB;
+    let n_ubatch = if small { 256 } else { 512 };
+
+    RuntimeConfig {
+        n_threads: Some(profile.physical_cores),
+        n_ctx: if small { 4096 } else { 8192 },
+        n_batch: if has_gpu && !small { 2048 } else { n_ubatch },
+        n_ubatch,
+        flash_attention: has_gpu,
+        ..RuntimeConfig::default()
+    }
+}
+
+/// Detect CPU, memory and backends, and recommend a model that fits
+pub fn detect(settings: &AppSettings) -> HardwareProfile {
+    let mut sys = System::new_all();
+    sys.refresh_memory();
+
+    let cpu_brand = sys
+        .cpus()
+        .first()
+        .map(|cpu| cpu.brand().trim().to_string())
+        .unwrap_or_default();
+
+    let mut profile = HardwareProfile {
+        cpu_brand,
+        physical_cores: physical_core_count(),
+        logical_cores: logical_core_count(),
+        cpu_features: cpu_features(),
+        total_memory_gb: (sys.total_memory() as f64 / BYTES_PER_GB) as f32,
+        available_memory_gb: (sys.available_memory() as f64 / BYTES_PER_GB) as f32,
+        backends: compiled_backends(),
+        recommendation: None,
+    };
+
+    let runtime = recommended_runtime(&profile);
+    let max_new_tokens = settings.sampling.max_new_tokens.min((runtime.n_ctx / 2) as usize);
+    let settings = AppSettings {
+        runtime: runtime.clone(),
+        sampling: SamplingConfig { max_new_tokens, ..settings.sampling.clone() },
+        ..settings.clone()
+    };
+    profile.recommendation = crate::offline_analyzer
+        ::fitting_model(&settings, profile.available_memory_gb)
+        .map(|(variant, quantization, required_memory_gb)| Recommendation {
+            variant,
+            quantization,
+            runtime,
+            max_new_tokens,
+            required_memory_gb,
+        });
+
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_count() {
+        let auto = RuntimeConfig::default();
+        assert!(thread_count(&auto) >= 1);
+
+        let fixed = RuntimeConfig { n_threads: Some(3), ..RuntimeConfig::default() };
+        assert_eq!(thread_count(&fixed), logical_core_count().min(3));
+
+        // More threads than CPUs are clamped
+        let oversized = RuntimeConfig { n_threads: Some(10_000), ..RuntimeConfig::default() };
+        assert_eq!(thread_count(&oversized), logical_core_count());
+
+        // Zero means "auto" rather than "no threads"
+        let zero = RuntimeConfig { n_threads: Some(0), ..RuntimeConfig::default() };
+        assert_eq!(thread_count(&zero), physical_core_count().min(logical_core_count()));
+    }
+
+    fn profile(total_memory_gb: f32, backends: &[&str]) -> HardwareProfile {
+        HardwareProfile {
+            cpu_brand: String::new(),
+            physical_cores: 6,
+            logical_cores: 12,
+            cpu_features: Vec::new(),
+            total_memory_gb,
+            available_memory_gb: total_memory_gb / 2.0,
+            backends: backends.iter().map(|b| b.to_string()).collect(),
+            recommendation: None,
+        }
+    }
+
+    #[test]
+    fn test_recommended_runtime() {
+        let small = recommended_runtime(&profile(4.0, &["cpu"]));
+        assert_eq!((small.n_ctx, small.n_batch, small.n_ubatch), (4096, 256, 256));
+        assert_eq!(small.n_threads, Some(6));
+
+        let cpu = recommended_runtime(&profile(32.0, &["cpu"]));
+        assert_eq!((cpu.n_ctx, cpu.n_batch, cpu.n_ubatch), (8192, 512, 512));
+        assert!(!cpu.flash_attention);
+
+        let gpu = recommended_runtime(&profile(32.0, &["cpu", "cuda"]));
+        assert_eq!((gpu.n_batch, gpu.n_ubatch), (2048, 512));
+        assert!(gpu.flash_attention);
+    }
+
+    #[test]
+    fn test_detect() {
+        let profile = detect(&AppSettings::default());
+        assert!(profile.physical_cores >= 1);
+        assert!(profile.logical_cores >= 1);
+        assert!(profile.total_memory_gb > 0.0);
+        assert!(profile.available_memory_gb <= profile.total_memory_gb);
+        assert!(profile.backends.contains(&"cpu".to_string()));
+        if let Some(recommendation) = profile.recommendation {
+            assert!(recommendation.required_memory_gb <= profile.available_memory_gb);
+            assert!(recommendation.max_new_tokens <= (recommendation.runtime.n_ctx / 2) as usize);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{ Context, Result };
+use std::path::{ Path, PathBuf };
+
+// Earlier versions downloaded through hf-hub into the Hugging Face cache and
+// copied each file into the model directory, storing every model twice.
+// Files left there are moved over instead of downloaded again, and clearing
+// the model cache removes them too. The cache is shared with other tools, so
+// only entries for files the manifest names are touched, and a blob stays
+// while another snapshot still points to it.
+
+/// The Hugging Face hub cache hf-hub downloads into
+pub fn cache_root() -> PathBuf {
+    hf_hub::Cache::default().path().clone()
+}
+
+/// Directory of a model repository inside the hub cache
+pub fn repo_dir(cache_root: &Path, hf_repo: &str) -> PathBuf {
+    cache_root.join(hf_hub::Repo::model(hf_repo.to_string()).folder_name())
+}
+
+/// Cached copy of `file` at `revision`, if a complete one exists
+pub fn cached_file(cache_root: &Path, hf_repo: &str, revision: &str, file: &str) -> Option<PathBuf> {
+    hf_hub::Cache
+        ::new(cache_root.to_path_buf())
+        .repo(hf_hub::Repo::with_revision(hf_repo.to_string(), hf_hub::RepoType::Model, revision.to_string()))
+        .get(file)
+}
+
+/// Commit of the snapshot a cached file belongs to
+pub fn snapshot_commit(pointer: &Path) -> Option<String> {
+    pointer
+        .ancestors()
+        .find(|dir| dir.parent().and_then(Path::file_name) == Some("snapshots".as_ref()))
+        .and_then(Path::file_name)
+        .map(|commit| commit.to_string_lossy().into_owned())
+}
+
+/// The `snapshots` directory a snapshot entry is in
+fn snapshots_dir(pointer: &Path) -> Option<&Path> {
+    pointer.ancestors().find(|dir| dir.file_name() == Some("snapshots".as_ref()))
+}
+
+/// Files and links under `dir`, recursively
+fn snapshot_entries(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    for entry in entries.flatten() {
+        // The entry's own type; a link to a file isn't followed
+        if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+            found.extend(snapshot_entries(&entry.path()));
+        } else {
+            found.push(entry.path());
+        }
+    }
+    found
+}
+
+/// Whether a snapshot entry other than `pointer`, at any commit of its
+/// repository, resolves to `blob`
+fn blob_shared(pointer: &Path, blob: &Path) -> bool {
+    let Some(snapshots) = snapshots_dir(pointer) else {
+        return false;
+    };
+    snapshot_entries(snapshots)
+        .iter()
+        .filter(|entry| entry.as_path() != pointer)
+        .any(|entry| std::fs::canonicalize(entry).is_ok_and(|target| target == blob))
+}
+
+/// Remove a snapshot entry, and the blob it points to unless another entry
+/// still does, returning the size freed
+fn remove_entry(pointer: &Path, blob: &Path) -> u64 {
+    let shared = blob_shared(pointer, blob);
+    let size = if shared { 0 } else { std::fs::metadata(blob).map(|meta| meta.len()).unwrap_or(0) };
+    // Snapshot entries are symlinks, or copies where symlinks aren't available
+    let _ = std::fs::remove_file(pointer);
+    if !shared {
+        let _ = std::fs::remove_file(blob);
+    }
+    size
+}
+
+/// Move a cached file to `target`: a rename when both are on the same
+/// filesystem, a copy otherwise. The cache entry is removed either way, and
+/// the file's size is returned as the bytes no longer stored twice. When
+/// another snapshot points to the same blob, the blob is copied and kept,
+/// and nothing is freed.
+pub fn move_from_cache(pointer: &Path, target: &Path) -> Result<u64> {
+    let blob = std::fs::canonicalize(pointer).with_context(|| format!("Failed to resolve {:?}", pointer))?;
+    let size = std::fs::metadata(&blob)?.len();
+
+    if blob_shared(pointer, &blob) {
+        log::info!("Copying {:?}, which another snapshot still uses", blob);
+        std::fs::copy(&blob, target).with_context(|| format!("Failed to copy {:?} to {:?}", blob, target))?;
+        let _ = std::fs::remove_file(pointer);
+        return Ok(0);
+    }
+
+    if let Err(e) = std::fs::rename(&blob, target) {
+        log::info!("Copying {:?} instead of moving it: {}", blob, e);
+        std::fs::copy(&blob, target).with_context(|| format!("Failed to copy {:?} to {:?}", blob, target))?;
+        remove_entry(pointer, &blob);
+    } else {
+        let _ = std::fs::remove_file(pointer);
+    }
+    Ok(size)
+}
+
+/// Remove the cached duplicate of a file the model directory already has
+pub fn remove_duplicate(pointer: &Path) -> Result<u64> {
+    let blob = std::fs::canonicalize(pointer).with_context(|| format!("Failed to resolve {:?}", pointer))?;
+    Ok(remove_entry(pointer, &blob))
+}
+
+/// Delete the snapshot entries of `files` in `hf_repo`, at every commit, and
+/// the blobs nothing else points to, returning the bytes freed. Other files
+/// in the repository, such as ones another tool fetched, are left alone.
+pub fn clear_files(cache_root: &Path, hf_repo: &str, files: &[&str]) -> Result<u64> {
+    let snapshots = repo_dir(cache_root, hf_repo).join("snapshots");
+    let Ok(commits) = std::fs::read_dir(&snapshots) else {
+        return Ok(0);
+    };
+
+    let mut freed = 0;
+    for commit in commits {
+        let commit = commit?.path();
+        for file in files {
+            let pointer = commit.join(file);
+            if pointer.symlink_metadata().is_err() {
+                continue;
+            }
+            match std::fs::canonicalize(&pointer) {
+                Ok(blob) => {
+                    freed += remove_entry(&pointer, &blob);
+                }
+                // A dangling link holds nothing
+                Err(_) => {
+                    let _ = std::fs::remove_file(&pointer);
+                }
+            }
+        }
+        // Only succeeds once nothing else is left at the commit
+        let _ = std::fs::remove_dir(&commit);
+    }
+
+    if freed > 0 {
+        log::info!("Removed {} bytes of {} from the Hugging Face cache", freed, hf_repo);
+    }
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Lay out a file the way hf-hub caches it: a blob, a snapshot symlink
+    /// and a ref naming the commit
+    #[cfg(unix)]
+    fn cache_file(cache_root: &Path, hf_repo: &str, file: &str, content: &[u8]) -> PathBuf {
+        let repo = repo_dir(cache_root, hf_repo);
+        std::fs::create_dir_all(repo.join("blobs")).unwrap();
+        std::fs::create_dir_all(repo.join("refs")).unwrap();
+        std::fs::create_dir_all(repo.join("snapshots").join("abc123")).unwrap();
+        std::fs::write(repo.join("blobs").join(format!("blob-{}", file)), content).unwrap();
+        std::fs::write(repo.join("refs").join("main"), "abc123").unwrap();
+        let pointer = repo.join("snapshots").join("abc123").join(file);
+        std::os::unix::fs::symlink(format!("../../blobs/blob-{}", file), &pointer).unwrap();
+        pointer
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_move_from_cache_leaves_one_copy() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_root = temp_dir.path().join("hub");
+        cache_file(&cache_root, "org/model", "model.gguf", b"weights");
+
+        let pointer = cached_file(&cache_root, "org/model", "main", "model.gguf").unwrap();
+        assert_eq!(snapshot_commit(&pointer).as_deref(), Some("abc123"));
+        let target = temp_dir.path().join("model.gguf");
+        assert_eq!(move_from_cache(&pointer, &target).unwrap(), 7);
+
+        assert_eq!(std::fs::read(&target).unwrap(), b"weights");
+        assert_eq!(cached_file(&cache_root, "org/model", "main", "model.gguf"), None);
+        assert!(!repo_dir(&cache_root, "org/model").join("blobs").join("blob-model.gguf").exists());
+    }
+
+    /// Point `file` at `commit` to the blob another snapshot already uses
+    #[cfg(unix)]
+    fn link_snapshot(cache_root: &Path, hf_repo: &str, commit: &str, file: &str) -> PathBuf {
+        let snapshot = repo_dir(cache_root, hf_repo).join("snapshots").join(commit);
+        std::fs::create_dir_all(&snapshot).unwrap();
+        let pointer = snapshot.join(file);
+        std::os::unix::fs::symlink(format!("../../blobs/blob-{}", file), &pointer).unwrap();
+        pointer
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_move_from_cache_keeps_shared_blob() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_root = temp_dir.path().join("hub");
+        let pointer = cache_file(&cache_root, "org/model", "model.gguf", b"weights");
+        let other = link_snapshot(&cache_root, "org/model", "def456", "model.gguf");
+
+        let target = temp_dir.path().join("model.gguf");
+        assert_eq!(move_from_cache(&pointer, &target).unwrap(), 0);
+
+        assert_eq!(std::fs::read(&target).unwrap(), b"weights");
+        assert!(pointer.symlink_metadata().is_err());
+        assert_eq!(std::fs::read(&other).unwrap(), b"weights");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_duplicate_and_clear_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_root = temp_dir.path().join("hub");
+        let pointer = cache_file(&cache_root, "org/model", "model.gguf", b"weights");
+        cache_file(&cache_root, "org/model", "mmproj.gguf", b"proj");
+        link_snapshot(&cache_root, "org/model", "def456", "mmproj.gguf");
+        cache_file(&cache_root, "org/model", "README.md", b"other tool");
+        cache_file(&cache_root, "other/model", "model.gguf", b"unrelated");
+
+        assert_eq!(remove_duplicate(&pointer).unwrap(), 7);
+        assert_eq!(cached_file(&cache_root, "org/model", "main", "model.gguf"), None);
+
+        // A blob shared by two snapshots is freed once, with its last entry
+        assert_eq!(clear_files(&cache_root, "org/model", &["model.gguf", "mmproj.gguf"]).unwrap(), 4);
+        assert_eq!(cached_file(&cache_root, "org/model", "main", "mmproj.gguf"), None);
+        let repo = repo_dir(&cache_root, "org/model");
+        assert!(!repo.join("blobs").join("blob-mmproj.gguf").exists());
+        assert!(!repo.join("snapshots").join("def456").exists());
+
+        // Files the manifest doesn't name, and other repositories, stay
+        assert!(cached_file(&cache_root, "org/model", "main", "README.md").is_some());
+        assert!(cached_file(&cache_root, "other/model", "main", "model.gguf").is_some());
+    }
+
+    #[test]
+    fn test_repo_dir_layout() {
+        assert_eq!(repo_dir(Path::new("/hub"), "Qwen/Qwen3-VL-2B-Instruct-GGUF"), Path::new("/hub/models--Qwen--Qwen3-VL-2B-Instruct-GGUF"));
+    }
+}
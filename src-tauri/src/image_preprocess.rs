@@ -0,0 +1,324 @@
+use crate::gguf::GgufHeader;
+use anyhow::{ Context, Result };
+use image::codecs::gif::GifDecoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPDecoder;
+use image::imageops::FilterType;
+use image::{ AnimationDecoder, DynamicImage, ImageDecoder, ImageFormat, ImageReader, Rgb, RgbImage };
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Longest edge sent to the Claude API; larger images are downscaled server-side anyway
+pub const MAX_UPLOAD_EDGE: u32 = 1568;
+
+/// JPEG quality for re-encoded uploads
+const UPLOAD_JPEG_QUALITY: u8 = 90;
+
+/// How images are prepared for a vision encoder
+#[derive(Debug, Clone)]
+pub struct PreprocessConfig {
+    /// Vision transformer patch size in pixels
+    pub patch_size: u32,
+    /// Patches merged into one visual token per side (2 for Qwen-VL)
+    pub merge_size: u32,
+    /// Visual token budget shared by all images in one prompt
+    pub max_visual_tokens: u32,
+    /// Color transparent pixels are flattened onto
+    pub background: [u8; 3],
+}
+
+impl Default for PreprocessConfig {
+    /// Qwen3-VL: 16px patches merged 2x2, so one token per 32x32 block
+    fn default() -> Self {
+        Self {
+            patch_size: 16,
+            merge_size: 2,
+            max_visual_tokens: 4096,
+            background: [255, 255, 255],
+        }
+    }
+}
+
+impl PreprocessConfig {
+    /// Patch and merge sizes of the vision encoder in `mmproj`, keeping the
+    /// Qwen3-VL ones where the projector doesn't set them
+    pub fn for_projector(mmproj: &GgufHeader) -> Self {
+        let default = Self::default();
+        let read = |key: &str, fallback: u32| {
+            mmproj
+                .get_u64(key)
+                .and_then(|value| u32::try_from(value).ok())
+                .filter(|&value| value > 0)
+                .unwrap_or(fallback)
+        };
+        Self {
+            patch_size: read("clip.vision.patch_size", default.patch_size),
+            merge_size: read("clip.vision.spatial_merge_size", default.merge_size),
+            ..default
+        }
+    }
+
+    /// Pixels per visual token along each side
+    pub fn token_size(&self) -> u32 {
+        self.patch_size * self.merge_size
+    }
+}
+
+/// Decode an image upright, picking a representative frame from animations
+pub fn load_image(path: &Path) -> Result<DynamicImage> {
+    let reader = ImageReader::open(path)
+        .with_context(|| format!("Failed to open image: {}", path.display()))?
+        .with_guessed_format()
+        .with_context(|| format!("Failed to read image: {}", path.display()))?;
+
+    match reader.format() {
+        Some(ImageFormat::Gif) => {
+            let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+            return representative_frame(decoder);
+        }
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(BufReader::new(File::open(path)?))?;
+            if decoder.has_animation() {
+                return representative_frame(decoder);
+            }
+        }
+        _ => {}
+    }
+
+    let mut decoder = reader
+        .into_decoder()
+        .with_context(|| format!("Unsupported image: {}", path.display()))?;
+    let orientation = decoder.orientation()?;
+    let mut img = DynamicImage::from_decoder(decoder)
+        .with_context(|| format!("Failed to decode image: {}", path.display()))?;
+    img.apply_orientation(orientation);
+
+    Ok(img)
+}
+
+/// Use the middle frame of an animation; the first is often a blank or title card
+fn representative_frame<'a>(decoder: impl AnimationDecoder<'a>) -> Result<DynamicImage> {
+    let mut frames = decoder.into_frames().collect_frames().context("Failed to decode animation")?;
+    if frames.is_empty() {
+        anyhow::bail!("Animation has no frames");
+    }
+
+    let middle = frames.len() / 2;
+    Ok(DynamicImage::ImageRgba8(frames.swap_remove(middle).into_buffer()))
+}
+
+/// Composite any transparency onto `background` and convert to RGB8
+pub fn flatten_alpha(img: &DynamicImage, background: [u8; 3]) -> RgbImage {
+    if !img.color().has_alpha() {
+        return img.to_rgb8();
+    }
+
+    let rgba = img.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let alpha = a as u32;
+        let blend = |fg: u8, bg: u8| ((fg as u32 * alpha + bg as u32 * (255 - alpha) + 127) / 255) as u8;
+        Rgb([blend(r, background[0]), blend(g, background[1]), blend(b, background[2])])
+    })
+}
+
+/// Target size that keeps the aspect ratio, is a multiple of `token_size`
+/// on both sides, and uses at most `max_tokens` visual tokens
+pub fn fit_to_token_grid(width: u32, height: u32, token_size: u32, max_tokens: u32) -> (u32, u32) {
+    let max_tokens = max_tokens.max(1) as f64;
+    let (w, h) = (width.max(1) as f64, height.max(1) as f64);
+    let unit = token_size as f64;
+
+    // Only downscale; rounding to the grid may still grow a side by part of a token
+    let full_tokens = (w / unit).max(1.0) * (h / unit).max(1.0);
+    let scale = (max_tokens / full_tokens).sqrt().min(1.0);
+
+    let mut cols = ((w * scale) / unit).round().max(1.0) as u32;
+    let mut rows = ((h * scale) / unit).round().max(1.0) as u32;
+
+    // Rounding may overshoot the budget; shrink the longer side
+    while cols * rows > max_tokens as u32 && (cols > 1 || rows > 1) {
+        if cols >= rows {
+            cols -= 1;
+        } else {
+            rows -= 1;
+        }
+    }
+
+    (cols * token_size, rows * token_size)
+}
+
+/// Target size fitting within `max_edge` on the longest side (never upscales)
+pub fn fit_within(width: u32, height: u32, max_edge: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest <= max_edge {
+        return (width, height);
+    }
+
+    let scale = max_edge as f64 / longest as f64;
+    (
+        ((width as f64) * scale).round().max(1.0) as u32,
+        ((height as f64) * scale).round().max(1.0) as u32,
+    )
+}
+
+/// Prepare reference images for the local vision encoder.
+///
+/// The token budget is split evenly across images.
+pub fn preprocess_for_model(paths: &[String], config: &PreprocessConfig) -> Result<Vec<RgbImage>> {
+    let per_image_tokens = config.max_visual_tokens / (paths.len().max(1) as u32);
+
+    paths
+        .iter()
+        .map(|path| {
+            let img = load_image(Path::new(path))?;
+            let rgb = flatten_alpha(&img, config.background);
+            let (w, h) = fit_to_token_grid(rgb.width(), rgb.height(), config.token_size(), per_image_tokens);
+            log::debug!("Preprocessed {}: {}x{} -> {}x{}", path, rgb.width(), rgb.height(), w, h);
+            Ok(image::imageops::resize(&rgb, w, h, FilterType::Lanczos3))
+        })
+        .collect()
+}
+
+/// Upright, flattened and downscaled JPEG bytes for the cloud API
+pub fn prepare_for_upload(path: &Path, max_edge: u32) -> Result<Vec<u8>> {
+    let img = load_image(path)?;
+    let rgb = flatten_alpha(&img, [255, 255, 255]);
+    let (w, h) = fit_within(rgb.width(), rgb.height(), max_edge);
+    let resized = if (w, h) == rgb.dimensions() {
+        rgb
+    } else {
+        image::imageops::resize(&rgb, w, h, FilterType::Lanczos3)
+    };
+
+    let mut bytes = Vec::new();
+    JpegEncoder::new_with_quality(&mut bytes, UPLOAD_JPEG_QUALITY)
+        .encode_image(&resized)
+        .context("Failed to encode image")?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf::tests::write_test_gguf;
+    use crate::gguf::{ read_header, GgufValue };
+    use image::{ Frame, Rgba, RgbaImage };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_config_for_projector() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("mmproj.gguf");
+
+        // A 14px encoder without a merge size keeps the 2x2 merge
+        write_test_gguf(&path, &[
+            ("general.architecture", GgufValue::String("clip".to_string())),
+            ("clip.vision.patch_size", GgufValue::U32(14)),
+        ]);
+        let config = PreprocessConfig::for_projector(&read_header(&path).unwrap());
+        assert_eq!((config.patch_size, config.merge_size), (14, 2));
+        assert_eq!(config.token_size(), 28);
+
+        write_test_gguf(&path, &[
+            ("general.architecture", GgufValue::String("clip".to_string())),
+            ("clip.vision.patch_size", GgufValue::U32(16)),
+            ("clip.vision.spatial_merge_size", GgufValue::U32(1)),
+        ]);
+        assert_eq!(PreprocessConfig::for_projector(&read_header(&path).unwrap()).token_size(), 16);
+    }
+
+    #[test]
+    fn test_fit_to_token_grid_keeps_aspect() {
+        let (w, h) = fit_to_token_grid(4000, 2000, 32, 512);
+        assert_eq!(w % 32, 0);
+        assert_eq!(h % 32, 0);
+        assert!((w / 32) * (h / 32) <= 512);
+        assert!(((w as f32) / (h as f32) - 2.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_fit_to_token_grid_rounds_to_patch_grid() {
+        assert_eq!(fit_to_token_grid(100, 60, 32, 4096), (96, 64));
+        assert_eq!(fit_to_token_grid(10, 10, 32, 4096), (32, 32));
+    }
+
+    #[test]
+    fn test_fit_to_token_grid_extreme_aspect() {
+        let (w, h) = fit_to_token_grid(10000, 50, 32, 64);
+        assert_eq!(h, 32);
+        assert!(w / 32 <= 64);
+    }
+
+    #[test]
+    fn test_fit_within() {
+        assert_eq!(fit_within(800, 600, 1568), (800, 600));
+        assert_eq!(fit_within(4000, 2000, 1568), (1568, 784));
+        assert_eq!(fit_within(1000, 3000, 1500), (500, 1500));
+    }
+
+    #[test]
+    fn test_flatten_alpha() {
+        let mut rgba = RgbaImage::new(2, 1);
+        rgba.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+        rgba.put_pixel(1, 0, Rgba([200, 100, 0, 255]));
+
+        let rgb = flatten_alpha(&DynamicImage::ImageRgba8(rgba), [255, 255, 255]);
+        assert_eq!(rgb.get_pixel(0, 0).0, [255, 255, 255]);
+        assert_eq!(rgb.get_pixel(1, 0).0, [200, 100, 0]);
+    }
+
+    #[test]
+    fn test_animated_gif_uses_middle_frame() {
+        use image::codecs::gif::GifEncoder;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("anim.gif");
+        let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+        {
+            let mut encoder = GifEncoder::new(File::create(&path).unwrap());
+            let frames = colors
+                .iter()
+                .map(|&[r, g, b]| Frame::new(RgbaImage::from_pixel(8, 8, Rgba([r, g, b, 255]))));
+            encoder.encode_frames(frames).unwrap();
+        }
+
+        let img = load_image(&path).unwrap().to_rgb8();
+        let [r, g, b] = img.get_pixel(4, 4).0;
+        assert!(g > 200 && r < 50 && b < 50, "expected green frame, got {:?}", [r, g, b]);
+    }
+
+    #[test]
+    fn test_preprocess_for_model_splits_budget() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths: Vec<String> = (0..2)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("img{}.png", i));
+                RgbaImage::from_pixel(1024, 768, Rgba([10, 20, 30, 128])).save(&path).unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+
+        let config = PreprocessConfig { max_visual_tokens: 256, ..PreprocessConfig::default() };
+        let images = preprocess_for_model(&paths, &config).unwrap();
+
+        assert_eq!(images.len(), 2);
+        for img in images {
+            assert_eq!(img.width() % 32, 0);
+            assert_eq!(img.height() % 32, 0);
+            assert!((img.width() / 32) * (img.height() / 32) <= 128);
+        }
+    }
+
+    #[test]
+    fn test_prepare_for_upload_downscales() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("big.png");
+        RgbaImage::from_pixel(3200, 1600, Rgba([0, 0, 0, 255])).save(&path).unwrap();
+
+        let bytes = prepare_for_upload(&path, MAX_UPLOAD_EDGE).unwrap();
+        let decoded = image::load_from_memory(&bytes).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (1568, 784));
+    }
+}
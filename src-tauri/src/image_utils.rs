@@ -1,9 +1,9 @@
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose, Engine as _};
-use std::fs;
 use std::path::Path;
 
-/// Read an image file and encode it as base64
+/// Read an image, normalize and downscale it for upload, and encode it as
+/// base64 JPEG
 pub fn read_and_encode_image(path: &str) -> Result<String> {
     let path_obj = Path::new(path);
 
@@ -12,14 +12,10 @@ pub fn read_and_encode_image(path: &str) -> Result<String> {
         anyhow::bail!("Image file does not exist: {}", path);
     }
 
-    // Read file bytes
-    let bytes = fs::read(path_obj)
-        .with_context(|| format!("Failed to read image file: {}", path))?;
+    let bytes = crate::image_preprocess::prepare_for_upload(path_obj, crate::image_preprocess::MAX_UPLOAD_EDGE)
+        .with_context(|| format!("Failed to prepare image: {}", path))?;
 
-    // Encode to base64
-    let encoded = general_purpose::STANDARD.encode(&bytes);
-
-    Ok(encoded)
+    Ok(general_purpose::STANDARD.encode(&bytes))
 }
 
 /// Determine MIME type from file extension
@@ -0,0 +1,757 @@
+use crate::benchmark::{ run_benchmark, BenchmarkResult };
+use crate::candle_inference::InferenceProgress;
+use crate::model_manager::ModelFiles;
+use crate::model_pool::{ LoadedFrom, ModelPool, ModelPoolEvent, PoolState };
+use crate::offline_analyzer::{ run_job, AnalysisJob, JobOutput, OfflineAnalysisError };
+use crate::settings::{ AppSettings, ModelVariant, RuntimeConfig };
+use serde::de::DeserializeOwned;
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use std::collections::{ HashMap, VecDeque };
+use std::ffi::OsString;
+use std::io::{ BufRead, BufReader, Write };
+use std::path::PathBuf;
+use std::process::{ Child, ChildStdin, ChildStdout, Command, Stdio };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::{ mpsc, Arc, Mutex };
+use std::thread::JoinHandle;
+use std::time::{ Duration, Instant };
+use tauri::Emitter;
+use thiserror::Error;
+
+/// Command-line flag that starts the app binary as an inference worker
+pub const WORKER_ARG: &str = "--inference-worker";
+
+const JSONRPC_VERSION: &str = "2.0";
+
+/// JSON-RPC error code for failures of the requested call
+const CALL_FAILED: i64 = -32000;
+
+/// How often the background task checks for idle models and memory pressure
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Lines of worker stderr kept for crash reports
+const STDERR_TAIL_LINES: usize = 40;
+
+/// How long a worker that closed its output gets to exit before it is killed
+const EXIT_GRACE: Duration = Duration::from_secs(5);
+
+/// Calls the app makes to the worker, one JSON-RPC method each
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub(crate) enum WorkerCall {
+    Analyze(AnalysisJob),
+    Preload {
+        variant: ModelVariant,
+        files: ModelFiles,
+        runtime: RuntimeConfig,
+    },
+    Unload {
+        variant: ModelVariant,
+        reason: String,
+    },
+    Sweep {
+        idle_timeout_secs: u64,
+    },
+    Benchmark {
+        variant: ModelVariant,
+        settings: AppSettings,
+    },
+    /// Sent as a notification: stop the running call at its next progress report
+    Cancel,
+}
+
+impl WorkerCall {
+    /// Model the call runs, so its idle time can be kept current
+    fn variant(&self) -> Option<&ModelVariant> {
+        match self {
+            WorkerCall::Analyze(job) => Some(&job.variant),
+            WorkerCall::Preload { variant, .. } | WorkerCall::Benchmark { variant, .. } => Some(variant),
+            _ => None,
+        }
+    }
+}
+
+/// Request (with an id) or notification from the app
+#[derive(Debug, Serialize, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    #[serde(flatten)]
+    call: WorkerCall,
+}
+
+/// Notifications the worker sends while a call runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+enum WorkerEvent {
+    Progress(InferenceProgress),
+    PoolStatus(ModelPoolEvent),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Notification {
+    jsonrpc: String,
+    #[serde(flatten)]
+    event: WorkerEvent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    /// Serialized `OfflineAnalysisError` for analysis calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Response {
+    jsonrpc: String,
+    id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+/// A line of worker output
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum WorkerMessage {
+    Response(Response),
+    Notification(Notification),
+}
+
+#[derive(Debug, Error)]
+pub enum WorkerError {
+    #[error("Inference worker could not be started: {0}")]
+    Spawn(String),
+
+    #[error("Inference worker crashed ({status}): {stderr}")]
+    Crashed { status: String, stderr: String },
+
+    #[error("{message}")]
+    Remote { message: String, data: Option<Value> },
+}
+
+/// Summary of a model currently held in memory by the worker
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadedModelInfo {
+    pub variant: ModelVariant,
+    pub idle_secs: u64,
+}
+
+/// A model the worker holds, as last reported
+struct LoadedModel {
+    last_used: Instant,
+    loaded_from: Option<LoadedFrom>,
+}
+
+/// Program and arguments that start a worker
+#[derive(Debug, Clone)]
+struct WorkerCommand {
+    program: PathBuf,
+    args: Vec<OsString>,
+}
+
+/// A running worker process and its pipes
+struct WorkerProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    stderr_tail: Arc<Mutex<VecDeque<String>>>,
+    stderr_reader: Option<JoinHandle<()>>,
+    next_id: u64,
+}
+
+impl WorkerProcess {
+    fn spawn(command: &WorkerCommand) -> Result<Self, WorkerError> {
+        let mut child = Command::new(&command.program)
+            .args(&command.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| WorkerError::Spawn(e.to_string()))?;
+
+        let (Some(stdin), Some(stdout), Some(stderr)) = (child.stdin.take(), child.stdout.take(), child.stderr.take()) else {
+            let _ = child.kill();
+            return Err(WorkerError::Spawn("Worker pipes unavailable".to_string()));
+        };
+
+        // Log worker output and keep the tail for crash reports
+        let stderr_tail = Arc::new(Mutex::new(VecDeque::new()));
+        let tail = stderr_tail.clone();
+        let stderr_reader = std::thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                log::debug!("Worker: {}", line);
+                if let Ok(mut tail) = tail.lock() {
+                    if tail.len() == STDERR_TAIL_LINES {
+                        tail.pop_front();
+                    }
+                    tail.push_back(line);
+                }
+            }
+        });
+
+        log::info!("Started inference worker (pid {})", child.id());
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            stderr_tail,
+            stderr_reader: Some(stderr_reader),
+            next_id: 1,
+        })
+    }
+
+    fn send(&mut self, request: &Request) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        self.stdin.write_all(&line)?;
+        self.stdin.flush()
+    }
+
+    /// Send `call` and read until its response, handling notifications on
+    /// the way. An I/O error means the worker is gone.
+    fn exchange(
+        &mut self,
+        call: WorkerCall,
+        progress: &mut dyn FnMut(InferenceProgress) -> bool,
+        on_pool_event: &dyn Fn(ModelPoolEvent)
+    ) -> std::io::Result<Result<Value, RpcError>> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(&(Request { jsonrpc: JSONRPC_VERSION.to_string(), id: Some(id), call }))?;
+
+        let mut cancel_sent = false;
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+
+            match serde_json::from_str::<WorkerMessage>(&line) {
+                Ok(WorkerMessage::Response(response)) if response.id == id => {
+                    return Ok(match response.error {
+                        Some(error) => Err(error),
+                        None => Ok(response.result.unwrap_or(Value::Null)),
+                    });
+                }
+                Ok(WorkerMessage::Response(response)) => {
+                    log::warn!("Ignoring worker response to unknown request {}", response.id);
+                }
+                Ok(WorkerMessage::Notification(notification)) =>
+                    match notification.event {
+                        WorkerEvent::Progress(stage) => {
+                            if !progress(stage) && !cancel_sent {
+                                self.send(
+                                    &(Request {
+                                        jsonrpc: JSONRPC_VERSION.to_string(),
+                                        id: None,
+                                        call: WorkerCall::Cancel,
+                                    })
+                                )?;
+                                cancel_sent = true;
+                            }
+                        }
+                        WorkerEvent::PoolStatus(event) => on_pool_event(event),
+                    }
+                // Native libraries may print to stdout too
+                Err(_) => log::debug!("Worker output: {}", line.trim_end()),
+            }
+        }
+    }
+
+    /// Wait for the worker to exit and describe how it died
+    fn crashed(mut self) -> WorkerError {
+        let started = Instant::now();
+        let status = loop {
+            match self.child.try_wait() {
+                Ok(Some(status)) => break status.to_string(),
+                Ok(None) if started.elapsed() < EXIT_GRACE => std::thread::sleep(Duration::from_millis(50)),
+                Ok(None) => {
+                    let _ = self.child.kill();
+                    break "stopped responding".to_string();
+                }
+                Err(e) => break e.to_string(),
+            }
+        };
+
+        if let Some(reader) = self.stderr_reader.take() {
+            let _ = reader.join();
+        }
+        let stderr = self.stderr_tail
+            .lock()
+            .map(|tail| tail.iter().cloned().collect::<Vec<_>>().join("\n"))
+            .unwrap_or_default();
+
+        log::error!("Inference worker crashed ({})", status);
+        WorkerError::Crashed {
+            status,
+            stderr: if stderr.trim().is_empty() { "no error output".to_string() } else { stderr },
+        }
+    }
+}
+
+impl Drop for WorkerProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Client for the out-of-process inference worker, shared through Tauri
+/// managed state. llama.cpp aborts the process on some asserts and
+/// allocation failures; in the worker that costs the loaded models instead
+/// of the app. The worker is started on first use and again after a crash.
+#[derive(Clone)]
+pub struct InferenceWorker {
+    command: Arc<WorkerCommand>,
+    /// One call at a time; the worker handles calls in order anyway. An
+    /// unload therefore waits for a running analysis, which holds the model
+    /// until it ends; cancel the analysis to release it sooner.
+    process: Arc<Mutex<Option<WorkerProcess>>>,
+    /// Models the worker holds, mirrored from its pool events
+    loaded: Arc<Mutex<HashMap<ModelVariant, LoadedModel>>>,
+    app: Option<tauri::AppHandle>,
+}
+
+impl InferenceWorker {
+    /// Worker running this executable with `WORKER_ARG`
+    pub fn new(app: Option<tauri::AppHandle>) -> Self {
+        let program = std::env::current_exe().unwrap_or_else(|_| PathBuf::from(std::env::args_os().next().unwrap_or_default()));
+        Self::with_command(program, vec![OsString::from(WORKER_ARG)], app)
+    }
+
+    fn with_command(program: PathBuf, args: Vec<OsString>, app: Option<tauri::AppHandle>) -> Self {
+        Self {
+            command: Arc::new(WorkerCommand { program, args }),
+            process: Arc::new(Mutex::new(None)),
+            loaded: Arc::new(Mutex::new(HashMap::new())),
+            app,
+        }
+    }
+
+    /// Whether the worker holds a variant in memory
+    pub fn is_loaded(&self, variant: &ModelVariant) -> bool {
+        self.loaded
+            .lock()
+            .map(|loaded| loaded.contains_key(variant))
+            .unwrap_or(false)
+    }
+
+    /// Whether the worker holds a variant loaded from `files` with the load
+    /// options of `runtime`, so a run with them won't load it again
+    pub fn is_loaded_from(&self, variant: &ModelVariant, files: &ModelFiles, runtime: &RuntimeConfig) -> bool {
+        self.loaded
+            .lock()
+            .map(|loaded| {
+                loaded
+                    .get(variant)
+                    .and_then(|model| model.loaded_from.as_ref())
+                    .is_some_and(|loaded_from| loaded_from.matches(files, runtime))
+            })
+            .unwrap_or(false)
+    }
+
+    /// List loaded models with their idle time
+    pub fn loaded_models(&self) -> Vec<LoadedModelInfo> {
+        self.loaded
+            .lock()
+            .map(|loaded| {
+                loaded
+                    .iter()
+                    .map(|(variant, model)| LoadedModelInfo {
+                        variant: variant.clone(),
+                        idle_secs: model.last_used.elapsed().as_secs(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Run an offline analysis. Blocks until it finishes; call from a
+    /// blocking task. Returning `false` from `progress` cancels.
+    pub(crate) fn analyze(
+        &self,
+        job: AnalysisJob,
+        progress: &mut dyn FnMut(InferenceProgress) -> bool
+    ) -> Result<JobOutput, WorkerError> {
+        self.call(WorkerCall::Analyze(job), progress)
+    }
+
+    /// Load a model ahead of the first analysis
+    pub fn preload(&self, variant: ModelVariant, files: ModelFiles, runtime: RuntimeConfig) -> Result<(), WorkerError> {
+        self.call(WorkerCall::Preload { variant, files, runtime }, &mut |_| true)
+    }
+
+    /// Drop a loaded model, returning whether it was loaded. Blocks until a
+    /// running call, such as an analysis, finishes.
+    pub fn unload(&self, variant: &ModelVariant, reason: &str) -> Result<bool, WorkerError> {
+        if !self.is_loaded(variant) {
+            return Ok(false);
+        }
+        self.call(WorkerCall::Unload { variant: variant.clone(), reason: reason.to_string() }, &mut |_| true)
+    }
+
+    /// Benchmark a model inside the worker
+    pub fn benchmark(&self, variant: ModelVariant, settings: AppSettings) -> Result<BenchmarkResult, WorkerError> {
+        self.call(WorkerCall::Benchmark { variant, settings }, &mut |_| true)
+    }
+
+    /// Enforce the idle timeout and memory limit. Skipped while a call is
+    /// running or when no worker is running.
+    fn sweep(&self, idle_timeout: Duration) {
+        let Ok(mut process) = self.process.try_lock() else {
+            return;
+        };
+        if process.is_none() {
+            return;
+        }
+
+        let call = WorkerCall::Sweep { idle_timeout_secs: idle_timeout.as_secs() };
+        if let Err(e) = self.call_locked::<()>(&mut process, call, &mut |_| true) {
+            log::warn!("Model pool sweep failed: {}", e);
+        }
+    }
+
+    /// Spawn the background task that sweeps the worker's model pool
+    pub fn start_sweeper(&self) {
+        let worker = self.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                tokio::time::sleep(SWEEP_INTERVAL).await;
+                let settings = crate::settings::load_settings().unwrap_or_default();
+                let idle_timeout = Duration::from_secs(settings.model_idle_timeout_secs);
+                let worker = worker.clone();
+                let _ = tokio::task::spawn_blocking(move || worker.sweep(idle_timeout)).await;
+            }
+        });
+    }
+
+    /// Send a call, starting the worker first if it isn't running
+    fn call<T: DeserializeOwned>(
+        &self,
+        call: WorkerCall,
+        progress: &mut dyn FnMut(InferenceProgress) -> bool
+    ) -> Result<T, WorkerError> {
+        let mut process = self.process
+            .lock()
+            .map_err(|_| WorkerError::Spawn("Inference worker lock poisoned".to_string()))?;
+        if process.is_none() {
+            *process = Some(WorkerProcess::spawn(&self.command)?);
+        }
+        self.call_locked(&mut process, call, progress)
+    }
+
+    fn call_locked<T: DeserializeOwned>(
+        &self,
+        process: &mut Option<WorkerProcess>,
+        call: WorkerCall,
+        progress: &mut dyn FnMut(InferenceProgress) -> bool
+    ) -> Result<T, WorkerError> {
+        let Some(worker) = process.as_mut() else {
+            return Err(WorkerError::Spawn("Inference worker is not running".to_string()));
+        };
+
+        let variant = call.variant().cloned();
+        match worker.exchange(call, progress, &|event| self.record(event)) {
+            Ok(Ok(result)) => {
+                if let Some(variant) = variant {
+                    self.touch(&variant);
+                }
+                serde_json::from_value(result).map_err(|e| WorkerError::Remote {
+                    message: format!("Invalid worker response: {}", e),
+                    data: None,
+                })
+            }
+            Ok(Err(error)) =>
+                Err(WorkerError::Remote {
+                    message: error.message,
+                    data: error.data,
+                }),
+            Err(e) => {
+                log::warn!("Lost connection to inference worker: {}", e);
+                let error = match process.take() {
+                    Some(worker) => worker.crashed(),
+                    None => WorkerError::Spawn(e.to_string()),
+                };
+                self.forget_all("inference worker crashed");
+                Err(error)
+            }
+        }
+    }
+
+    /// Mirror a pool event and forward it to the frontend
+    fn record(&self, event: ModelPoolEvent) {
+        if let Ok(mut loaded) = self.loaded.lock() {
+            match event.state {
+                PoolState::Loaded => {
+                    loaded.insert(event.variant.clone(), LoadedModel {
+                        last_used: Instant::now(),
+                        loaded_from: event.loaded_from.clone(),
+                    });
+                }
+                PoolState::Unloaded => {
+                    loaded.remove(&event.variant);
+                }
+                PoolState::Loading => {}
+            }
+        }
+        if let Some(app) = &self.app {
+            let _ = app.emit("model-pool-status", event);
+        }
+    }
+
+    fn touch(&self, variant: &ModelVariant) {
+        if let Ok(mut loaded) = self.loaded.lock() {
+            if let Some(model) = loaded.get_mut(variant) {
+                model.last_used = Instant::now();
+            }
+        }
+    }
+
+    /// Models die with the worker
+    fn forget_all(&self, reason: &str) {
+        let variants: Vec<ModelVariant> = self.loaded
+            .lock()
+            .map(|loaded| loaded.keys().cloned().collect())
+            .unwrap_or_default();
+        for variant in variants {
+            self.record(ModelPoolEvent {
+                variant,
+                state: PoolState::Unloaded,
+                load_time_ms: None,
+                reason: Some(reason.to_string()),
+                loaded_from: None,
+            });
+        }
+    }
+}
+
+/// Write one JSON-RPC message as a line on stdout
+fn send_line<T: Serialize>(stdout: &Mutex<std::io::Stdout>, message: &T) {
+    let Ok(mut line) = serde_json::to_vec(message) else {
+        return;
+    };
+    line.push(b'\n');
+    if let Ok(mut stdout) = stdout.lock() {
+        let _ = stdout.write_all(&line).and_then(|_| stdout.flush());
+    }
+}
+
+fn notification(event: WorkerEvent) -> Notification {
+    Notification { jsonrpc: JSONRPC_VERSION.to_string(), event }
+}
+
+fn call_failed(message: String, data: Option<Value>) -> RpcError {
+    RpcError { code: CALL_FAILED, message, data }
+}
+
+fn to_result<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| call_failed(e.to_string(), None))
+}
+
+/// Run one call against the worker's model pool
+fn handle(call: WorkerCall, pool: &ModelPool, report: &mut dyn FnMut(InferenceProgress) -> bool) -> Result<Value, RpcError> {
+    match call {
+        WorkerCall::Analyze(job) =>
+            run_job(job, pool, report)
+                .map_err(|e: OfflineAnalysisError| call_failed(e.to_string(), serde_json::to_value(&e).ok()))
+                .and_then(to_result),
+        WorkerCall::Preload { variant, files, runtime } =>
+            pool
+                .get_or_load(&variant, &files, &runtime)
+                .map_err(|e| call_failed(format!("{:#}", e), None))
+                .and_then(|_| to_result(())),
+        WorkerCall::Unload { variant, reason } => to_result(pool.unload(&variant, &reason)),
+        WorkerCall::Sweep { idle_timeout_secs } => {
+            pool.sweep(Duration::from_secs(idle_timeout_secs));
+            to_result(())
+        }
+        WorkerCall::Benchmark { variant, settings } =>
+            run_benchmark(&variant, &settings, pool)
+                .map_err(|e| call_failed(format!("{:#}", e), None))
+                .and_then(to_result),
+        WorkerCall::Cancel => to_result(()),
+    }
+}
+
+/// Main loop of the worker process: handle calls from stdin in order until
+/// the app closes it
+pub fn serve() {
+    let stdout = Arc::new(Mutex::new(std::io::stdout()));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (calls, incoming) = mpsc::channel::<(u64, WorkerCall)>();
+
+    // Read on a separate thread so a cancel arrives while a call runs
+    let reader_cancelled = cancelled.clone();
+    let reader_stdout = stdout.clone();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Request>(&line) {
+                Ok(Request { call: WorkerCall::Cancel, .. }) => reader_cancelled.store(true, Ordering::SeqCst),
+                Ok(Request { id: Some(id), call, .. }) => {
+                    if calls.send((id, call)).is_err() {
+                        break;
+                    }
+                }
+                Ok(Request { id: None, .. }) => log::warn!("Ignoring call sent as a notification"),
+                Err(e) => {
+                    log::warn!("Invalid worker request: {}", e);
+                    // Answer if the id can be recovered, so the app doesn't wait forever
+                    let id = serde_json::from_str::<Value>(&line).ok().and_then(|value| value["id"].as_u64());
+                    if let Some(id) = id {
+                        send_line(&reader_stdout, &Response {
+                            jsonrpc: JSONRPC_VERSION.to_string(),
+                            id,
+                            result: None,
+                            error: Some(call_failed(format!("Invalid request: {}", e), None)),
+                        });
+                    }
+                }
+            }
+        }
+    });
+
+    let events = stdout.clone();
+    let pool = ModelPool::with_listener(
+        Arc::new(move |event: &ModelPoolEvent| send_line(&events, &notification(WorkerEvent::PoolStatus(event.clone()))))
+    );
+
+    for (id, call) in incoming {
+        cancelled.store(false, Ordering::SeqCst);
+        let outcome = handle(call, &pool, &mut |stage| {
+            send_line(&stdout, &notification(WorkerEvent::Progress(stage)));
+            !cancelled.load(Ordering::SeqCst)
+        });
+
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        send_line(&stdout, &Response { jsonrpc: JSONRPC_VERSION.to_string(), id, result, error });
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Worker stand-in running a shell script
+    fn scripted_worker(script: &str, arg: &std::path::Path) -> InferenceWorker {
+        InferenceWorker::with_command(
+            PathBuf::from("sh"),
+            vec!["-c".into(), script.into(), "sh".into(), arg.as_os_str().to_owned()],
+            None
+        )
+    }
+
+    fn files(dir: &std::path::Path) -> ModelFiles {
+        ModelFiles { model: dir.join("model.gguf"), mmproj: dir.join("mmproj.gguf") }
+    }
+
+    #[test]
+    fn test_protocol_messages() {
+        let request = Request {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id: Some(7),
+            call: WorkerCall::Sweep { idle_timeout_secs: 600 },
+        };
+        let json: Value = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "jsonrpc": "2.0", "id": 7, "method": "sweep", "params": { "idle_timeout_secs": 600 } })
+        );
+
+        let cancel = serde_json::to_string(&(Request { jsonrpc: JSONRPC_VERSION.to_string(), id: None, call: WorkerCall::Cancel })).unwrap();
+        assert_eq!(cancel, r#"{"jsonrpc":"2.0","method":"cancel"}"#);
+
+        let progress = r#"{"jsonrpc":"2.0","method":"progress","params":{"stage":"prompt_eval","tokens":12}}"#;
+        assert!(
+            matches!(
+                serde_json::from_str::<WorkerMessage>(progress).unwrap(),
+                WorkerMessage::Notification(Notification { event: WorkerEvent::Progress(InferenceProgress::PromptEval { tokens: 12 }), .. })
+            )
+        );
+
+        let response = r#"{"jsonrpc":"2.0","id":7,"result":null}"#;
+        assert!(matches!(serde_json::from_str::<WorkerMessage>(response).unwrap(), WorkerMessage::Response(Response { id: 7, .. })));
+    }
+
+    #[test]
+    fn test_crash_is_reported_and_worker_restarts() {
+        let temp_dir = TempDir::new().unwrap();
+        let marker = temp_dir.path().join("crashed");
+        // First run: load a model, then abort like a failed GGML_ASSERT.
+        // Later runs load the model and answer normally.
+        let script =
+            r#"
+            read request
+            echo '{"jsonrpc":"2.0","method":"pool_status","params":{"variant":"Qwen3VL2B","state":"loaded","load_time_ms":10,"reason":null}}'
+            if [ ! -e "$1" ]; then
+                touch "$1"
+                echo 'GGML_ASSERT(n_tokens_all <= cparams.n_batch) failed' >&2
+                kill -ABRT $$
+            fi
+            echo '{"jsonrpc":"2.0","id":1,"result":null}'
+            cat > /dev/null
+        "#;
+        let worker = scripted_worker(script, &marker);
+        let variant = ModelVariant::Qwen3VL2B;
+
+        match worker.preload(variant.clone(), files(temp_dir.path()), RuntimeConfig::default()) {
+            Err(WorkerError::Crashed { status, stderr }) => {
+                assert!(status.contains("signal"), "{}", status);
+                assert!(stderr.contains("GGML_ASSERT"), "{}", stderr);
+            }
+            other => panic!("Expected a crash, got {:?}", other),
+        }
+        // Its models went with it
+        assert!(!worker.is_loaded(&variant));
+
+        let error = OfflineAnalysisError::from(WorkerError::Crashed { status: "signal: 6".to_string(), stderr: "GGML_ASSERT".to_string() });
+        assert!(matches!(error, OfflineAnalysisError::InferenceFailed(message) if message.contains("GGML_ASSERT")));
+
+        worker.preload(variant.clone(), files(temp_dir.path()), RuntimeConfig::default()).unwrap();
+        assert!(worker.is_loaded(&variant));
+        assert_eq!(worker.loaded_models().len(), 1);
+    }
+
+    #[test]
+    fn test_cancel_is_forwarded() {
+        let temp_dir = TempDir::new().unwrap();
+        // Report progress, then fail the call with `Cancelled` once the
+        // cancel notification arrives
+        let script =
+            r#"
+            read request
+            echo '{"jsonrpc":"2.0","method":"progress","params":{"stage":"loading_model"}}'
+            read cancel
+            case "$cancel" in
+                *'"method":"cancel"'*) echo '{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"Analysis cancelled","data":"Cancelled"}}' ;;
+                *) echo '{"jsonrpc":"2.0","id":1,"result":null}' ;;
+            esac
+            cat > /dev/null
+        "#;
+        let worker = scripted_worker(script, temp_dir.path());
+
+        let mut stages = Vec::new();
+        let result = worker.call::<()>(WorkerCall::Sweep { idle_timeout_secs: 1 }, &mut |stage| {
+            stages.push(stage);
+            false
+        });
+
+        assert_eq!(stages, [InferenceProgress::LoadingModel]);
+        let error = OfflineAnalysisError::from(result.unwrap_err());
+        assert!(matches!(error, OfflineAnalysisError::Cancelled));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::active_downloads::ActiveDownloads;
+use crate::hf_cache;
+use crate::model_manager::{ dir_size, get_model_cache_dir, get_model_path };
+use crate::model_registry::{ find_model, manifest, ManifestModel };
+use crate::model_verification::{ cached_verification, Verification };
+use crate::settings::ModelVariant;
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{ Path, PathBuf };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+/// When a model was last loaded, in Unix seconds, kept in its directory
+const LAST_USED_FILE: &str = ".last_used";
+
+/// A model file on disk
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct InstalledFile {
+    pub name: String,
+    pub size_bytes: u64,
+    /// Last checksum result; `None` until the file has been hashed
+    pub verification: Option<Verification>,
+}
+
+/// A manifest model with files in the model cache
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct InstalledModel {
+    pub variant: ModelVariant,
+    pub name: String,
+    pub path: PathBuf,
+    /// Complete GGUF files, across every downloaded quantization
+    pub files: Vec<InstalledFile>,
+    /// Everything in the model directory, partial downloads included
+    pub size_bytes: u64,
+    /// Unix seconds of the last analysis or preload
+    pub last_used: Option<u64>,
+}
+
+/// Published SHA-256 of one of the model's files, at any quantization
+fn manifest_sha256<'a>(model: &'a ManifestModel, file: &str) -> Option<&'a str> {
+    model.model_files
+        .values()
+        .chain(model.mmproj_files.values())
+        .find(|published| published.file == file)
+        .and_then(|published| published.sha256.as_deref())
+}
+
+fn last_used(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path.join(LAST_USED_FILE)).ok()?.trim().parse().ok()
+}
+
+/// Record that a model is being loaded; custom models aren't tracked
+pub fn mark_used(variant: &ModelVariant, custom_dir: Option<PathBuf>) {
+    if matches!(variant, ModelVariant::Custom(_)) {
+        return;
+    }
+    let Ok(path) = get_model_path(variant.clone(), custom_dir) else {
+        return;
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    if let Err(e) = std::fs::write(path.join(LAST_USED_FILE), now.to_string()) {
+        log::warn!("Failed to record use of {}: {}", variant, e);
+    }
+}
+
+fn installed_model(model: &ManifestModel, path: PathBuf) -> Result<Option<InstalledModel>> {
+    let Ok(entries) = std::fs::read_dir(&path) else {
+        return Ok(None);
+    };
+
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() || !name.ends_with(".gguf") {
+            continue;
+        }
+        files.push(InstalledFile {
+            verification: cached_verification(&path, &name, manifest_sha256(model, &name)),
+            name,
+            size_bytes: metadata.len(),
+        });
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let size_bytes = dir_size(&path)?;
+    if files.is_empty() && size_bytes == 0 {
+        return Ok(None);
+    }
+
+    Ok(
+        Some(InstalledModel {
+            variant: model.variant(),
+            name: model.name.clone(),
+            last_used: last_used(&path),
+            path,
+            files,
+            size_bytes,
+        })
+    )
+}
+
+/// Manifest models with anything in the model cache, in manifest order
+pub fn list_installed_models(custom_dir: Option<PathBuf>) -> Result<Vec<InstalledModel>> {
+    let cache_dir = get_model_cache_dir(custom_dir)?;
+    let mut installed = Vec::new();
+    for model in manifest().models {
+        let path = cache_dir.join(&model.directory);
+        if let Some(model) = installed_model(&model, path)? {
+            installed.push(model);
+        }
+    }
+    Ok(installed)
+}
+
+/// Delete one model's directory, and the entries for its files in the
+/// Hugging Face cache under `hub_cache` that no other model also publishes. Refused
+/// while the model is being downloaded or updated, here or by another
+/// process; `release` runs once nothing can start meanwhile, to let go of
+/// the files. Returns the bytes freed.
+pub fn delete_model(
+    variant: &ModelVariant,
+    custom_dir: Option<PathBuf>,
+    hub_cache: &Path,
+    downloads: &ActiveDownloads,
+    release: impl FnOnce() -> Result<()>
+) -> Result<u64> {
+    if let ModelVariant::Custom(name) = variant {
+        anyhow::bail!("Custom model '{}' isn't stored in the model cache; remove it from the custom models instead", name);
+    }
+
+    let model = find_model(variant)?;
+    let path = get_model_path(variant.clone(), custom_dir)?;
+
+    // Holding the download lock keeps a download from starting mid-delete
+    let _guard = path
+        .exists()
+        .then(|| downloads.begin(variant, &path))
+        .transpose()?;
+    release()?;
+
+    let mut freed = 0;
+    if path.exists() {
+        freed += dir_size(&path)?;
+        std::fs::remove_dir_all(&path)?;
+    }
+
+    let manifest = manifest();
+    let shared: Vec<&str> = manifest.models
+        .iter()
+        .filter(|other| other.id != model.id && other.hf_repo == model.hf_repo)
+        .flat_map(ManifestModel::published_files)
+        .collect();
+    let files: Vec<&str> = model
+        .published_files()
+        .into_iter()
+        .filter(|file| !shared.contains(file))
+        .collect();
+    freed += hf_cache::clear_files(hub_cache, &model.hf_repo, &files)?;
+
+    log::info!("Deleted model {}, freed {} bytes", variant, freed);
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn install(cache_dir: &Path, variant: &ModelVariant, files: &[(&str, usize)]) -> PathBuf {
+        let path = get_model_path(variant.clone(), Some(cache_dir.to_path_buf())).unwrap();
+        std::fs::create_dir_all(&path).unwrap();
+        for (file, size) in files {
+            std::fs::write(path.join(file), vec![0u8; *size]).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_list_installed_models() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_dir = Some(temp_dir.path().to_path_buf());
+        assert!(list_installed_models(cache_dir.clone()).unwrap().is_empty());
+
+        install(temp_dir.path(), &ModelVariant::Qwen3VL2B, &[("b.gguf", 30), ("a.gguf", 20), ("c.gguf.part", 5)]);
+        mark_used(&ModelVariant::Qwen3VL2B, cache_dir.clone());
+
+        let installed = list_installed_models(cache_dir).unwrap();
+        assert_eq!(installed.len(), 1);
+        let model = &installed[0];
+        assert_eq!(model.variant, ModelVariant::Qwen3VL2B);
+        let names: Vec<&str> = model.files
+            .iter()
+            .map(|file| file.name.as_str())
+            .collect();
+        assert_eq!(names, ["a.gguf", "b.gguf"]);
+        assert_eq!(model.files[0].verification, None);
+        assert!(model.size_bytes >= 55);
+        assert!(model.last_used.is_some());
+    }
+
+    #[test]
+    fn test_delete_one_model_keeps_others() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_dir = Some(temp_dir.path().join("models"));
+        let hub_cache = temp_dir.path().join("hub");
+        install(&temp_dir.path().join("models"), &ModelVariant::Qwen3VL2B, &[("model.gguf", 10)]);
+        let kept = install(&temp_dir.path().join("models"), &ModelVariant::Qwen3VL4B, &[("model.gguf", 10)]);
+
+        let downloads = ActiveDownloads::default();
+        let freed = delete_model(&ModelVariant::Qwen3VL2B, cache_dir.clone(), &hub_cache, &downloads, || Ok(())).unwrap();
+        assert!(freed >= 10);
+        assert!(kept.join("model.gguf").exists());
+
+        let installed = list_installed_models(cache_dir.clone()).unwrap();
+        assert_eq!(installed.len(), 1);
+        assert_eq!(installed[0].variant, ModelVariant::Qwen3VL4B);
+
+        assert!(delete_model(&ModelVariant::Custom("mine".to_string()), cache_dir, &hub_cache, &downloads, || Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_delete_refused_while_downloading() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_dir = Some(temp_dir.path().join("models"));
+        let hub_cache = temp_dir.path().join("hub");
+        let path = install(&temp_dir.path().join("models"), &ModelVariant::Qwen3VL2B, &[("model.gguf", 10)]);
+
+        let downloads = ActiveDownloads::default();
+        let guard = downloads.begin(&ModelVariant::Qwen3VL2B, &path).unwrap();
+        let result = delete_model(&ModelVariant::Qwen3VL2B, cache_dir.clone(), &hub_cache, &downloads, || {
+            panic!("Released a model being downloaded")
+        });
+        assert!(result.unwrap_err().to_string().contains("already being downloaded"));
+        assert!(path.join("model.gguf").exists());
+
+        // Once the download ends the model can go
+        drop(guard);
+        assert!(delete_model(&ModelVariant::Qwen3VL2B, cache_dir, &hub_cache, &downloads, || Ok(())).is_ok());
+        assert!(!path.exists());
+    }
+}
@@ -1,31 +1,93 @@
+mod active_downloads;
+mod benchmark;
 mod candle_inference;
+mod captioning;
 mod claude;
+mod custom_models;
+mod disk_space;
+mod download_progress;
+mod fake_provider;
 mod file_ops;
+mod gguf;
+mod hardware;
+mod hf_cache;
+mod image_preprocess;
 mod image_utils;
+mod inference_worker;
+mod installed_models;
+mod memory_estimate;
+mod model_bundle;
 mod model_manager;
+mod model_pool;
+mod model_registry;
+mod model_revision;
+mod model_updates;
+mod model_verification;
 mod offline_analyzer;
+mod resumable_download;
 mod settings;
+mod spec_grammar;
 
 use serde::Serialize;
-use tauri::command;
+use tauri::{command, Emitter, Manager, State};
+
+pub use inference_worker::WORKER_ARG as INFERENCE_WORKER_ARG;
 
 #[derive(Serialize)]
 struct AnalysisResult {
     data: String,
     mode_used: String,
     fallback_used: bool,
+    /// Placeholder output from the fake provider, not a real analysis
+    fake: bool,
+    /// Model and sampling settings for offline runs
+    inference_metadata: Option<offline_analyzer::InferenceMetadata>,
+}
+
+/// The fake provider's result when it is the selected mode. It is only ever
+/// chosen explicitly, never as a fallback.
+fn fake_analysis(
+    image_paths: &[String],
+    sref_code: &str,
+    settings: &settings::AppSettings,
+) -> Option<Result<AnalysisResult, String>> {
+    if settings.analysis_mode != settings::AnalysisMode::Fake {
+        return None;
+    }
+    Some(
+        fake_provider::analyze_style(image_paths, sref_code, settings)
+            .map(|data| AnalysisResult {
+                data,
+                mode_used: "fake".to_string(),
+                fallback_used: false,
+                fake: true,
+                inference_metadata: None,
+            })
+            .map_err(|e| format!("Fake provider error: {:#}", e)),
+    )
 }
 
 #[command]
-async fn analyze_style(image_paths: Vec<String>, sref_code: String) -> Result<AnalysisResult, String> {
+async fn analyze_style(
+    app: tauri::AppHandle,
+    worker: State<'_, inference_worker::InferenceWorker>,
+    cancellation: State<'_, offline_analyzer::AnalysisCancellation>,
+    image_paths: Vec<String>,
+    sref_code: String,
+) -> Result<AnalysisResult, String> {
     let settings = settings::load_settings().unwrap_or_default();
+    cancellation.reset();
+
+    if let Some(result) = fake_analysis(&image_paths, &sref_code, &settings) {
+        return result;
+    }
 
     // Determine which mode to use
     let use_api = match settings.analysis_mode {
         settings::AnalysisMode::CloudAPI => {
             std::env::var("CLAUDE_API_KEY").is_ok() || std::env::var("ANTHROPIC_API_KEY").is_ok()
         }
-        settings::AnalysisMode::Offline => false,
+        settings::AnalysisMode::Offline | settings::AnalysisMode::Fake => false,
         settings::AnalysisMode::Auto => {
             std::env::var("CLAUDE_API_KEY").is_ok() || std::env::var("ANTHROPIC_API_KEY").is_ok()
         }
@@ -37,11 +99,12 @@ async fn analyze_style(image_paths: Vec<String>, sref_code: String) -> Result<An
         let image_data: Vec<(String, String)> = image_paths
             .iter()
             .map(|path| {
+                image_utils::get_mime_type(path)
+                    .map_err(|e| format!("Invalid image format {}: {}", path, e))?;
+                // Uploads are re-encoded as JPEG after orientation and downscaling
                 let base64_data = image_utils::read_and_encode_image(path)
                     .map_err(|e| format!("Failed to read image {}: {}", path, e))?;
-                let mime_type = image_utils::get_mime_type(path)
-                    .map_err(|e| format!("Invalid image format {}: {}", path, e))?;
-                Ok((base64_data, mime_type))
+                Ok((base64_data, "image/jpeg".to_string()))
             })
             .collect::<Result<Vec<_>, String>>()?;
 
@@ -51,6 +114,8 @@ async fn analyze_style(image_paths: Vec<String>, sref_code: String) -> Result<An
                     data: result,
                     mode_used: "cloud".to_string(),
                     fallback_used: false,
+                    fake: false,
+                    inference_metadata: None,
                 });
             }
             Err(e) if settings.auto_fallback => {
@@ -64,16 +129,32 @@ async fn analyze_style(image_paths: Vec<String>, sref_code: String) -> Result<An
     }
 
     // Use offline mode (either primary or fallback)
-    match offline_analyzer::analyze_style(image_paths, &sref_code, &settings).await {
+    match offline_analyzer::analyze_style(
+        image_paths,
+        &sref_code,
+        &settings,
+        &worker,
+        Some(app),
+        &cancellation,
+    )
+    .await
+    {
         Ok(result) => Ok(AnalysisResult {
-            data: result,
+            data: result.data,
             mode_used: "offline".to_string(),
             fallback_used: use_api, // true if we tried API first
+            fake: false,
+            inference_metadata: Some(result.metadata),
         }),
         Err(e) => Err(format!("Offline analysis error: {}", e)),
     }
 }
 
+#[command]
+fn cancel_analysis(cancellation: State<'_, offline_analyzer::AnalysisCancellation>) {
+    cancellation.cancel();
+}
+
 #[command]
 fn save_project(path: String, data: String) -> Result<(), String> {
     file_ops::save_project(&path, &data)
@@ -111,25 +192,155 @@ fn get_settings() -> Result<settings::AppSettings, String> {
 
 #[command]
 fn update_settings(settings: settings::AppSettings) -> Result<(), String> {
+    offline_analyzer::visual_token_budget(&settings.runtime, &settings.sampling)
+        .map_err(|e| e.to_string())?;
     settings::save_settings(&settings)
         .map_err(|e| format!("Failed to save settings: {}", e))
 }
 
+/// Async so that hashing new model files doesn't block the UI thread
 #[command]
-fn get_model_status(variant: settings::ModelVariant) -> model_manager::ModelStatus {
+async fn get_model_status(
+    variant: settings::ModelVariant,
+    quantization: Option<settings::QuantizationConfig>,
+) -> model_manager::ModelStatus {
+    let settings = settings::load_settings().unwrap_or_default();
+    tokio::task::spawn_blocking(move || match quantization {
+        // Unsaved selection from the settings screen
+        Some(quantization) if !matches!(variant, settings::ModelVariant::Custom(_)) => {
+            model_manager::check_model_status(variant, settings.model_cache_dir, quantization)
+        }
+        _ => model_manager::check_variant_status(&variant, &settings),
+    })
+    .await
+    .unwrap_or_else(|e| model_manager::ModelStatus::Error {
+        message: format!("Status check failed: {}", e),
+    })
+}
+
+#[command]
+async fn verify_model(
+    variant: settings::ModelVariant,
+    quantization: Option<settings::QuantizationConfig>,
+) -> Result<model_manager::ModelStatus, String> {
+    let settings = settings::load_settings().unwrap_or_default();
+    let quantization = quantization.unwrap_or_else(|| settings.quantization_for(&variant));
+    model_manager::verify_model(variant, settings.model_cache_dir, quantization, &settings.hub)
+        .await
+        .map_err(|e| format!("Failed to verify model: {}", e))
+}
+
+#[command]
+fn list_registry_models() -> Vec<model_registry::ManifestModel> {
+    model_registry::manifest().models
+}
+
+#[command]
+fn get_model_requirements(
+    variant: settings::ModelVariant,
+    quantization: settings::QuantizationConfig,
+) -> Result<model_manager::ModelRequirements, String> {
+    model_manager::ModelConfig::from_variant(variant, quantization)
+        .map(|config| model_manager::ModelRequirements::from(&config))
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+fn check_download_space(
+    variant: settings::ModelVariant,
+    quantization: Option<settings::QuantizationConfig>,
+) -> Result<disk_space::DiskCheck, String> {
     let settings = settings::load_settings().unwrap_or_default();
-    model_manager::check_model_status(variant, settings.model_cache_dir)
+    let quantization = quantization.unwrap_or_else(|| settings.quantization_for(&variant));
+    model_manager::check_download_space(variant, settings.model_cache_dir, quantization)
+        .map_err(|e| format!("Failed to check disk space: {}", e))
 }
 
 #[command]
 async fn download_model(
     app: tauri::AppHandle,
+    downloads: State<'_, active_downloads::ActiveDownloads>,
     variant: settings::ModelVariant,
-) -> Result<(), String> {
+    quantization: Option<settings::QuantizationConfig>,
+) -> Result<model_manager::DownloadReport, String> {
     let settings = settings::load_settings().unwrap_or_default();
-    model_manager::download_model(variant, settings.model_cache_dir, app)
-        .await
-        .map_err(|e| format!("Failed to download model: {}", e))
+    let quantization = quantization.unwrap_or_else(|| settings.quantization_for(&variant));
+    model_manager::download_model(
+        variant,
+        settings.model_cache_dir,
+        quantization,
+        &settings.hub,
+        &downloads,
+        app,
+    )
+    .await
+    .map_err(|e| format!("Failed to download model: {}", e))
+}
+
+/// Returns false when no download of the variant is running
+#[command]
+fn cancel_download(
+    downloads: State<'_, active_downloads::ActiveDownloads>,
+    variant: settings::ModelVariant,
+) -> bool {
+    downloads.cancel(&variant)
+}
+
+/// Install a model from a folder or .tar/.zip bundle on machines that can't
+/// reach Hugging Face
+#[command]
+async fn import_model(
+    worker: State<'_, inference_worker::InferenceWorker>,
+    downloads: State<'_, active_downloads::ActiveDownloads>,
+    variant: settings::ModelVariant,
+    quantization: Option<settings::QuantizationConfig>,
+    source_path: String,
+    move_files: Option<bool>,
+) -> Result<model_manager::ModelStatus, String> {
+    let settings = settings::load_settings().unwrap_or_default();
+    let quantization = quantization.unwrap_or_else(|| settings.quantization_for(&variant));
+    let worker = worker.inner().clone();
+    let downloads = downloads.inner().clone();
+    tokio::task::spawn_blocking(move || {
+        // The worker may hold the files open while they are replaced
+        if worker.is_loaded(&variant) {
+            worker
+                .unload(&variant, "model import")
+                .map_err(|e| format!("Failed to unload model: {}", e))?;
+        }
+        model_bundle::import_model(
+            &variant,
+            settings.model_cache_dir,
+            quantization,
+            std::path::Path::new(&source_path),
+            move_files.unwrap_or(false),
+            &downloads,
+        )
+        .map_err(|e| format!("Failed to import model: {}", e))
+    })
+    .await
+    .map_err(|e| format!("Import task failed: {}", e))?
+}
+
+#[command]
+async fn export_model(
+    variant: settings::ModelVariant,
+    quantization: Option<settings::QuantizationConfig>,
+    destination_path: String,
+) -> Result<u64, String> {
+    let settings = settings::load_settings().unwrap_or_default();
+    let quantization = quantization.unwrap_or_else(|| settings.quantization_for(&variant));
+    tokio::task::spawn_blocking(move || {
+        model_bundle::export_model(
+            &variant,
+            settings.model_cache_dir,
+            quantization,
+            std::path::Path::new(&destination_path),
+        )
+        .map_err(|e| format!("Failed to export model: {:#}", e))
+    })
+    .await
+    .map_err(|e| format!("Export task failed: {}", e))?
 }
 
 #[command]
@@ -139,14 +350,223 @@ fn clear_model_cache() -> Result<u64, String> {
         .map_err(|e| format!("Failed to clear cache: {}", e))
 }
 
+#[command]
+fn list_installed_models() -> Result<Vec<installed_models::InstalledModel>, String> {
+    let settings = settings::load_settings().unwrap_or_default();
+    installed_models::list_installed_models(settings.model_cache_dir)
+        .map_err(|e| format!("Failed to list models: {:#}", e))
+}
+
+/// Unload the model first so its files aren't held open while deleting
+#[command]
+async fn delete_model(
+    worker: State<'_, inference_worker::InferenceWorker>,
+    downloads: State<'_, active_downloads::ActiveDownloads>,
+    variant: settings::ModelVariant,
+) -> Result<u64, String> {
+    let settings = settings::load_settings().unwrap_or_default();
+    let worker = worker.inner().clone();
+    let downloads = downloads.inner().clone();
+    tokio::task::spawn_blocking(move || {
+        let unload = || {
+            if worker.is_loaded(&variant) {
+                worker
+                    .unload(&variant, "model deleted")
+                    .map_err(|e| anyhow::anyhow!("Failed to unload model: {}", e))?;
+            }
+            Ok(())
+        };
+        installed_models::delete_model(
+            &variant,
+            settings.model_cache_dir,
+            &hf_cache::cache_root(),
+            &downloads,
+            unload,
+        )
+        .map_err(|e| format!("Failed to delete model: {:#}", e))
+    })
+    .await
+    .map_err(|e| format!("Delete task failed: {}", e))?
+}
+
+/// Installed models with newer files on Hugging Face
+#[command]
+async fn check_model_updates() -> Result<Vec<model_updates::ModelUpdate>, String> {
+    let settings = settings::load_settings().unwrap_or_default();
+    model_updates::check_model_updates(settings.model_cache_dir, &settings.hub)
+        .await
+        .map_err(|e| format!("Failed to check for model updates: {:#}", e))
+}
+
+/// Replace outdated files of a model; `None` when it was already up to date
+#[command]
+async fn update_model(
+    app: tauri::AppHandle,
+    worker: State<'_, inference_worker::InferenceWorker>,
+    downloads: State<'_, active_downloads::ActiveDownloads>,
+    variant: settings::ModelVariant,
+) -> Result<Option<model_updates::ModelUpdate>, String> {
+    let settings = settings::load_settings().unwrap_or_default();
+    let worker = worker.inner().clone();
+    let unload_variant = variant.clone();
+    // The worker may hold the files open while they are replaced; only unload
+    // once the new files are staged
+    let unload = async move {
+        tokio::task::spawn_blocking(move || {
+            if worker.is_loaded(&unload_variant) {
+                worker
+                    .unload(&unload_variant, "model update")
+                    .map_err(|e| anyhow::anyhow!("Failed to unload model: {}", e))?;
+            }
+            Ok(())
+        })
+        .await?
+    };
+
+    model_updates::update_model(
+        &variant,
+        settings.model_cache_dir,
+        &settings.hub,
+        &downloads,
+        move |progress| {
+            let _ = app.emit("download-progress", progress);
+        },
+        unload,
+    )
+    .await
+    .map_err(|e| format!("Failed to update model: {}", e))
+}
+
+#[command]
+fn register_custom_model(model: settings::CustomModel) -> Result<settings::AppSettings, String> {
+    let mut settings = settings::load_settings().unwrap_or_default();
+    custom_models::register_custom_model(&mut settings, model)
+        .map_err(|e| format!("Invalid custom model: {:#}", e))?;
+    settings::save_settings(&settings)
+        .map_err(|e| format!("Failed to save settings: {}", e))?;
+    Ok(settings)
+}
+
+#[command]
+async fn remove_custom_model(
+    worker: State<'_, inference_worker::InferenceWorker>,
+    name: String,
+) -> Result<settings::AppSettings, String> {
+    let mut settings = settings::load_settings().unwrap_or_default();
+    let variant = settings::ModelVariant::Custom(name.clone());
+    let unloading = variant.clone();
+    let worker = worker.inner().clone();
+    tokio::task::spawn_blocking(move || worker.unload(&unloading, "custom model removed"))
+        .await
+        .map_err(|e| format!("Unload task failed: {}", e))?
+        .map_err(|e| format!("Failed to unload model: {}", e))?;
+
+    settings.custom_models.retain(|m| m.name != name);
+    if settings.offline_model_variant == variant {
+        settings.offline_model_variant = settings::ModelVariant::Qwen3VL2B;
+    }
+    settings::save_settings(&settings)
+        .map_err(|e| format!("Failed to save settings: {}", e))?;
+    Ok(settings)
+}
+
+#[command]
+async fn preload_model(
+    worker: State<'_, inference_worker::InferenceWorker>,
+    variant: settings::ModelVariant,
+) -> Result<(), String> {
+    let settings = settings::load_settings().unwrap_or_default();
+    let model_files = model_manager::resolve_model_files(&variant, &settings)
+        .map_err(|e| format!("Failed to preload model: {}", e))?;
+    installed_models::mark_used(&variant, settings.model_cache_dir.clone());
+
+    let worker = worker.inner().clone();
+    let runtime = settings.runtime;
+    tokio::task::spawn_blocking(move || worker.preload(variant, model_files, runtime))
+        .await
+        .map_err(|e| format!("Preload task failed: {}", e))?
+        .map_err(|e| format!("Failed to preload model: {}", e))
+}
+
+#[command]
+async fn unload_model(
+    worker: State<'_, inference_worker::InferenceWorker>,
+    variant: settings::ModelVariant,
+) -> Result<bool, String> {
+    let worker = worker.inner().clone();
+    tokio::task::spawn_blocking(move || worker.unload(&variant, "requested"))
+        .await
+        .map_err(|e| format!("Unload task failed: {}", e))?
+        .map_err(|e| format!("Failed to unload model: {}", e))
+}
+
+#[command]
+async fn benchmark_model(
+    worker: State<'_, inference_worker::InferenceWorker>,
+    variant: settings::ModelVariant,
+) -> Result<benchmark::BenchmarkResult, String> {
+    let settings = settings::load_settings().unwrap_or_default();
+    let worker = worker.inner().clone();
+    tokio::task::spawn_blocking(move || worker.benchmark(variant, settings))
+        .await
+        .map_err(|e| format!("Benchmark task failed: {}", e))?
+        .map_err(|e| format!("Benchmark failed: {}", e))
+}
+
+#[command]
+fn get_benchmark(
+    variant: settings::ModelVariant,
+    quantization: Option<settings::QuantizationConfig>,
+) -> Result<Option<benchmark::BenchmarkResult>, String> {
+    let settings = settings::load_settings().unwrap_or_default();
+    let files = match quantization {
+        // Unsaved selection from the settings screen
+        Some(quantization) if !matches!(variant, settings::ModelVariant::Custom(_)) => {
+            model_manager::builtin_model_files(&variant, settings.model_cache_dir.clone(), quantization)
+        }
+        _ => model_manager::resolve_model_files(&variant, &settings),
+    }
+    .map_err(|e| e.to_string())?;
+
+    benchmark::load_result(&files).map_err(|e| e.to_string())
+}
+
+#[command]
+fn get_hardware_profile() -> hardware::HardwareProfile {
+    let settings = settings::load_settings().unwrap_or_default();
+    hardware::detect(&settings)
+}
+
+#[command]
+fn get_loaded_models(
+    worker: State<'_, inference_worker::InferenceWorker>,
+) -> Vec<inference_worker::LoadedModelInfo> {
+    worker.loaded_models()
+}
+
+/// Entry point of the inference worker process (see `INFERENCE_WORKER_ARG`)
+pub fn run_inference_worker() {
+    env_logger::init();
+    inference_worker::serve();
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     env_logger::init();
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .manage(offline_analyzer::AnalysisCancellation::default())
+        .manage(active_downloads::ActiveDownloads::default())
+        .setup(|app| {
+            let worker = inference_worker::InferenceWorker::new(Some(app.handle().clone()));
+            worker.start_sweeper();
+            app.manage(worker);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             analyze_style,
+            cancel_analysis,
             save_project,
             load_project,
             export_json,
@@ -155,9 +575,73 @@ pub fn run() {
             get_settings,
             update_settings,
             get_model_status,
+            list_registry_models,
+            get_model_requirements,
+            check_download_space,
             download_model,
-            clear_model_cache
+            cancel_download,
+            verify_model,
+            import_model,
+            export_model,
+            clear_model_cache,
+            list_installed_models,
+            delete_model,
+            check_model_updates,
+            update_model,
+            register_custom_model,
+            remove_custom_model,
+            preload_model,
+            unload_model,
+            get_loaded_models,
+            get_hardware_profile,
+            benchmark_model,
+            get_benchmark
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_fake_mode_short_circuits_analysis() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths: Vec<String> = (0..3)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("ref{}.png", i));
+                RgbImage::from_pixel(16, 16, Rgb([i * 80, 40, 200]))
+                    .save(&path)
+                    .unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+        let mut settings = settings::AppSettings {
+            analysis_mode: settings::AnalysisMode::Fake,
+            allow_fake_provider: true,
+            ..settings::AppSettings::default()
+        };
+
+        let result = fake_analysis(&paths, "123456", &settings).unwrap().unwrap();
+        assert_eq!(result.mode_used, "fake");
+        assert!(result.fake);
+        assert!(!result.fallback_used);
+        assert!(result.inference_metadata.is_none());
+        let spec: serde_json::Value = serde_json::from_str(&result.data).unwrap();
+        assert_eq!(spec["sref_code"], "123456");
+        assert!(spec["style_analysis"]["primary_style"]
+            .as_str()
+            .unwrap()
+            .starts_with(fake_provider::FAKE_STYLE_PREFIX));
+
+        let missing = vec![temp_dir.path().join("missing.png").to_string_lossy().to_string()];
+        assert!(fake_analysis(&missing, "123456", &settings).unwrap().is_err());
+
+        // Other modes go on to the real providers
+        settings.analysis_mode = settings::AnalysisMode::Auto;
+        assert!(fake_analysis(&paths, "123456", &settings).is_none());
+    }
+}
@@ -2,5 +2,10 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // The app binary doubles as the out-of-process inference worker
+    if std::env::args().any(|arg| arg == tauri_app_lib::INFERENCE_WORKER_ARG) {
+        tauri_app_lib::run_inference_worker();
+        return;
+    }
     tauri_app_lib::run()
 }
@@ -0,0 +1,190 @@
+use crate::gguf::{ self, GgufHeader };
+use anyhow::{ Context, Result };
+use serde::Serialize;
+use std::path::Path;
+
+/// Bytes per GB, matching how available memory is reported
+pub const BYTES_PER_GB: f64 = 1_073_741_824.0;
+
+/// KV cache entries are stored as F16
+const KV_BYTES_PER_ELEMENT: u64 = 2;
+
+/// Scratch buffer each CPU worker thread allocates
+const PER_THREAD_SCRATCH_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Fixed allocations (backend, tokenizer, allocator slack) not visible in the header
+const BASE_OVERHEAD_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Inference settings that change memory use
+#[derive(Debug, Clone, Copy)]
+pub struct RuntimeShape {
+    /// Context window in tokens
+    pub n_ctx: u32,
+    /// Logical batch size
+    pub n_batch: u32,
+    /// CPU threads
+    pub n_threads: u32,
+    /// Visual tokens for all images in one prompt
+    pub max_visual_tokens: u32,
+}
+
+/// Expected resident memory of a loaded model, by component
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct MemoryEstimate {
+    pub weights_bytes: u64,
+    pub kv_cache_bytes: u64,
+    pub compute_bytes: u64,
+}
+
+impl MemoryEstimate {
+    pub fn total_bytes(&self) -> u64 {
+        self.weights_bytes + self.kv_cache_bytes + self.compute_bytes
+    }
+
+    pub fn total_gb(&self) -> f32 {
+        (self.total_bytes() as f64 / BYTES_PER_GB) as f32
+    }
+}
+
+/// Tensor data size, falling back to the file size for unknown tensor types
+fn weights_bytes(header: &GgufHeader, path: &Path) -> Result<u64> {
+    match header.tensor_data_bytes() {
+        Some(bytes) => Ok(bytes),
+        None => Ok(std::fs::metadata(path)?.len()),
+    }
+}
+
+/// Estimate memory from the model and vision projector headers
+pub fn estimate_from_headers(
+    model: &GgufHeader,
+    model_weights: u64,
+    mmproj: &GgufHeader,
+    mmproj_weights: u64,
+    shape: RuntimeShape
+) -> Result<MemoryEstimate> {
+    let n_layer = model.arch_u64("block_count").context("Model has no block_count")?;
+    let n_embd = model.arch_u64("embedding_length").context("Model has no embedding_length")?;
+    let n_head = model.arch_u64("attention.head_count").context("Model has no head_count")?;
+    let n_head_kv = model.arch_u64("attention.head_count_kv").unwrap_or(n_head);
+    let head_dim = n_embd / n_head.max(1);
+    let key_length = model.arch_u64("attention.key_length").unwrap_or(head_dim);
+    let value_length = model.arch_u64("attention.value_length").unwrap_or(head_dim);
+    let n_vocab = model.array_len("tokenizer.ggml.tokens").unwrap_or(0);
+
+    // llama.cpp caps the context at what the model was trained for
+    let n_ctx = match model.arch_u64("context_length") {
+        Some(trained) => (shape.n_ctx as u64).min(trained),
+        None => shape.n_ctx as u64,
+    };
+    let n_batch = (shape.n_batch as u64).min(n_ctx);
+
+    let kv_cache_bytes =
+        n_layer * n_ctx * n_head_kv * (key_length + value_length) * KV_BYTES_PER_ELEMENT;
+
+    // F32 logits for a batch plus attention scores for a batch against the context
+    let logits_bytes = n_vocab * n_batch * 4;
+    let attention_bytes = n_head * n_batch * n_ctx * 4;
+
+    // Vision encoder activations: every patch of every image at once
+    let vision_embd = mmproj.get_u64("clip.vision.embedding_length").unwrap_or(1024);
+    let merge = mmproj.get_u64("clip.vision.spatial_merge_size").unwrap_or(2);
+    let n_patches = (shape.max_visual_tokens as u64) * merge * merge;
+    let vision_bytes = n_patches * vision_embd * 4 * 4;
+
+    let compute_bytes =
+        logits_bytes +
+        attention_bytes +
+        vision_bytes +
+        (shape.n_threads as u64) * PER_THREAD_SCRATCH_BYTES +
+        BASE_OVERHEAD_BYTES;
+
+    Ok(MemoryEstimate {
+        weights_bytes: model_weights + mmproj_weights,
+        kv_cache_bytes,
+        compute_bytes,
+    })
+}
+
+/// Estimate memory for an installed model from its GGUF files
+pub fn estimate_from_files(
+    model_path: &Path,
+    mmproj_path: &Path,
+    shape: RuntimeShape
+) -> Result<MemoryEstimate> {
+    let model = gguf::read_header(model_path)?;
+    let mmproj = gguf::read_header(mmproj_path)?;
+
+    estimate_from_headers(
+        &model,
+        weights_bytes(&model, model_path)?,
+        &mmproj,
+        weights_bytes(&mmproj, mmproj_path)?,
+        shape
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf::tests::write_test_gguf_with_tensors;
+    use crate::gguf::{ GgufValue, TensorInfo };
+    use tempfile::TempDir;
+
+    fn shape(n_ctx: u32) -> RuntimeShape {
+        RuntimeShape { n_ctx, n_batch: 512, n_threads: 8, max_visual_tokens: 4096 }
+    }
+
+    fn write_model(dir: &Path) -> (std::path::PathBuf, std::path::PathBuf) {
+        let model_path = dir.join("model.gguf");
+        let mmproj_path = dir.join("mmproj.gguf");
+
+        write_test_gguf_with_tensors(
+            &model_path,
+            &[
+                ("general.architecture", GgufValue::String("qwen3vl".to_string())),
+                ("qwen3vl.block_count", GgufValue::U32(28)),
+                ("qwen3vl.embedding_length", GgufValue::U32(2048)),
+                ("qwen3vl.attention.head_count", GgufValue::U32(16)),
+                ("qwen3vl.attention.head_count_kv", GgufValue::U32(8)),
+                ("qwen3vl.attention.key_length", GgufValue::U32(128)),
+                ("qwen3vl.attention.value_length", GgufValue::U32(128)),
+                ("qwen3vl.context_length", GgufValue::U32(32768)),
+            ],
+            &[TensorInfo { name: "blk.0.attn_q.weight".to_string(), dims: vec![2048, 2048], ggml_type: 8 }]
+        );
+        write_test_gguf_with_tensors(
+            &mmproj_path,
+            &[("general.architecture", GgufValue::String("clip".to_string()))],
+            &[TensorInfo { name: "v.patch_embd.weight".to_string(), dims: vec![1024, 1024], ggml_type: 1 }]
+        );
+
+        (model_path, mmproj_path)
+    }
+
+    #[test]
+    fn test_kv_cache_from_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        let (model_path, mmproj_path) = write_model(temp_dir.path());
+
+        let estimate = estimate_from_files(&model_path, &mmproj_path, shape(8192)).unwrap();
+
+        // 28 layers * 8192 tokens * 8 KV heads * (128 + 128) dims * 2 bytes
+        assert_eq!(estimate.kv_cache_bytes, 28 * 8192 * 8 * 256 * 2);
+        assert_eq!(estimate.weights_bytes, 2048 * 2048 / 32 * 34 + 1024 * 1024 * 2);
+    }
+
+    #[test]
+    fn test_estimate_grows_with_context() {
+        let temp_dir = TempDir::new().unwrap();
+        let (model_path, mmproj_path) = write_model(temp_dir.path());
+
+        let small = estimate_from_files(&model_path, &mmproj_path, shape(4096)).unwrap();
+        let large = estimate_from_files(&model_path, &mmproj_path, shape(16384)).unwrap();
+        assert!(large.total_bytes() > small.total_bytes());
+
+        // Context beyond the trained length is capped
+        let capped = estimate_from_files(&model_path, &mmproj_path, shape(1 << 20)).unwrap();
+        let trained = estimate_from_files(&model_path, &mmproj_path, shape(32768)).unwrap();
+        assert_eq!(capped, trained);
+    }
+}
@@ -0,0 +1,529 @@
+use crate::active_downloads::ActiveDownloads;
+use crate::disk_space;
+use crate::model_manager::{
+    check_model_status,
+    get_model_path,
+    remaining_download_bytes,
+    ModelConfig,
+    ModelError,
+    ModelStatus,
+};
+use crate::model_revision::{ self, FileRevision };
+use crate::model_verification::{ self, Verification };
+use crate::resumable_download::part_path;
+use crate::settings::{ ModelVariant, QuantizationConfig };
+use anyhow::{ Context, Result };
+use serde::{ Deserialize, Serialize };
+use std::fs::File;
+use std::io::Read;
+use std::path::{ Path, PathBuf };
+
+// Bundles let machines without access to Hugging Face install models: an
+// online machine exports the files of a downloaded model into a .tar or .zip
+// archive, and the others import that archive or a folder holding the files.
+
+/// Lists each file of a bundle with its hash, so imports can check files
+/// the manifest publishes no hash for
+const BUNDLE_MANIFEST: &str = "bundle.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BundleFile {
+    pub name: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BundleManifest {
+    pub model_id: String,
+    pub hf_repo: String,
+    pub revision: String,
+    /// Commit the files were downloaded at, when it was recorded
+    #[serde(default)]
+    pub commit: Option<String>,
+    pub files: Vec<BundleFile>,
+}
+
+impl BundleManifest {
+    fn sha256(&self, file: &str) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|bundled| bundled.name == file)
+            .map(|bundled| bundled.sha256.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArchiveKind {
+    Tar,
+    Zip,
+}
+
+fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    match extension.as_str() {
+        "tar" => Some(ArchiveKind::Tar),
+        "zip" => Some(ArchiveKind::Zip),
+        _ => None,
+    }
+}
+
+/// A model file written next to its destination as a `.part` file
+struct Staged {
+    file: String,
+    part: PathBuf,
+    /// Set when the file was moved out of an import folder, to put it back
+    /// if the import fails
+    moved_from: Option<PathBuf>,
+    /// Set when a move had to copy, to remove the original once imported
+    copied_from: Option<PathBuf>,
+}
+
+/// Files of an import gathered into the model directory
+struct Staging {
+    model_path: PathBuf,
+    wanted: Vec<String>,
+    staged: Vec<Staged>,
+    bundle: Option<BundleManifest>,
+}
+
+impl Staging {
+    fn new(model_path: &Path, config: &ModelConfig) -> Self {
+        Self {
+            model_path: model_path.to_path_buf(),
+            wanted: config.files.clone(),
+            staged: Vec::new(),
+            bundle: None,
+        }
+    }
+
+    fn is_wanted(&self, name: &str) -> bool {
+        self.wanted.iter().any(|file| file == name) && !self.staged.iter().any(|staged| staged.file == name)
+    }
+
+    /// Take an archive entry; anything other than the model's files and
+    /// the bundle manifest is skipped
+    fn accept(&mut self, name: &str, reader: &mut dyn Read) -> Result<()> {
+        if name == BUNDLE_MANIFEST {
+            let mut content = String::new();
+            reader.read_to_string(&mut content)?;
+            self.bundle = Some(serde_json::from_str(&content).context("Invalid bundle manifest")?);
+        } else if self.is_wanted(name) {
+            let part = part_path(&self.model_path.join(name));
+            let mut out = File::create(&part).with_context(|| format!("Failed to create {:?}", part))?;
+            std::io::copy(reader, &mut out).with_context(|| format!("Failed to extract {}", name))?;
+            self.staged.push(Staged { file: name.to_string(), part, moved_from: None, copied_from: None });
+        }
+        Ok(())
+    }
+
+    fn take_folder(&mut self, folder: &Path, move_files: bool) -> Result<()> {
+        let bundle = folder.join(BUNDLE_MANIFEST);
+        if bundle.is_file() {
+            self.accept(BUNDLE_MANIFEST, &mut File::open(&bundle)?)?;
+        }
+        for file in self.wanted.clone() {
+            let source = folder.join(&file);
+            if !source.is_file() {
+                continue;
+            }
+            let part = part_path(&self.model_path.join(&file));
+            let mut staged = Staged { file, part, moved_from: None, copied_from: None };
+            if move_files && std::fs::rename(&source, &staged.part).is_ok() {
+                staged.moved_from = Some(source);
+            } else {
+                std::fs::copy(&source, &staged.part).with_context(|| format!("Failed to copy {:?}", source))?;
+                if move_files {
+                    staged.copied_from = Some(source);
+                }
+            }
+            self.staged.push(staged);
+        }
+        Ok(())
+    }
+
+    fn take_tar(&mut self, archive: &Path) -> Result<()> {
+        let mut archive = tar::Archive::new(File::open(archive)?);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path()?.into_owned();
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                self.accept(name, &mut entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn take_zip(&mut self, archive: &Path) -> Result<()> {
+        let mut archive = zip::ZipArchive::new(File::open(archive)?).context("Invalid zip archive")?;
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index)?;
+            if !entry.is_file() {
+                continue;
+            }
+            let name = entry
+                .enclosed_name()
+                .and_then(|path| path.file_name().map(|name| name.to_string_lossy().to_string()));
+            if let Some(name) = name {
+                self.accept(&name, &mut entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Undo the staging after a failed import
+    fn discard(&self) {
+        for staged in &self.staged {
+            match &staged.moved_from {
+                Some(source) => {
+                    if let Err(e) = std::fs::rename(&staged.part, source) {
+                        log::warn!("Failed to move {:?} back to {:?}: {}", staged.part, source, e);
+                    }
+                }
+                None => {
+                    let _ = std::fs::remove_file(&staged.part);
+                }
+            }
+        }
+    }
+
+    /// Check every staged file, then move them all into place
+    fn commit(&self, config: &ModelConfig) -> Result<()> {
+        let mut hashes = Vec::new();
+        for staged in &self.staged {
+            let index = config.files
+                .iter()
+                .position(|file| *file == staged.file)
+                .context("Staged file isn't part of the model")?;
+            // Hashes recorded for the files being replaced don't apply
+            let expected = config.file_sha256[index]
+                .clone()
+                .or_else(|| self.bundle.as_ref().and_then(|bundle| bundle.sha256(&staged.file)).map(str::to_string));
+
+            log::info!("Computing SHA-256 of {:?}", staged.part);
+            let actual = model_verification::sha256_file(&staged.part)?;
+            match model_verification::compare(expected.as_deref(), &actual) {
+                Verification::Mismatch { expected, actual } => {
+                    anyhow::bail!("{} has SHA-256 {}, expected {}", staged.file, actual, expected);
+                }
+                Verification::Unverified => log::warn!("No SHA-256 known for {}, importing it unverified", staged.file),
+                Verification::Verified => {}
+            }
+            hashes.push(actual);
+        }
+
+        for (staged, actual) in self.staged.iter().zip(hashes) {
+            let target = self.model_path.join(&staged.file);
+            std::fs::rename(&staged.part, &target).with_context(|| format!("Failed to move {:?} into place", staged.part))?;
+            // A bundle only vouches for its own copy, not for what the hub
+            // published
+            model_verification::forget_expected(&self.model_path, &staged.file)?;
+            model_verification::record_hash(&self.model_path, &staged.file, &actual)?;
+            if let Some(bundle) = &self.bundle {
+                let revision = FileRevision {
+                    hf_repo: bundle.hf_repo.clone(),
+                    revision: bundle.revision.clone(),
+                    commit: bundle.commit.clone(),
+                };
+                model_revision::record(&self.model_path, &staged.file, &revision)?;
+            }
+            if let Some(source) = &staged.copied_from {
+                if let Err(e) = std::fs::remove_file(source) {
+                    log::warn!("Imported {:?} but failed to remove it: {}", source, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn stage_and_commit(
+    staging: &mut Staging,
+    variant: &ModelVariant,
+    config: &ModelConfig,
+    source: &Path,
+    kind: Option<ArchiveKind>,
+    move_files: bool
+) -> Result<()> {
+    match kind {
+        None => staging.take_folder(source, move_files)?,
+        Some(ArchiveKind::Tar) => staging.take_tar(source)?,
+        Some(ArchiveKind::Zip) => staging.take_zip(source)?,
+    }
+
+    if let Some(bundle) = &staging.bundle {
+        let expected_id = variant.registry_id().unwrap_or_default();
+        if bundle.model_id != expected_id {
+            anyhow::bail!("The bundle holds {}, not {}", bundle.model_id, expected_id);
+        }
+    }
+    let missing: Vec<&str> = config.files
+        .iter()
+        .filter(|file| !staging.staged.iter().any(|staged| staged.file == **file))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        anyhow::bail!("{:?} doesn't contain {}", source, missing.join(", "));
+    }
+
+    staging.commit(config)
+}
+
+/// Install a model from a folder or a `.tar`/`.zip` archive holding its
+/// files, checking their names and hashes against the model config. Nothing
+/// is replaced unless every file checks out. `move_files` moves files out of
+/// a folder instead of copying them.
+pub fn import_model(
+    variant: &ModelVariant,
+    custom_dir: Option<PathBuf>,
+    quantization: QuantizationConfig,
+    source: &Path,
+    move_files: bool,
+    downloads: &ActiveDownloads
+) -> std::result::Result<ModelStatus, ModelError> {
+    let model_path = get_model_path(variant.clone(), custom_dir.clone())?;
+    let config = ModelConfig::from_variant(variant.clone(), quantization)?;
+
+    // `None` for a folder
+    let kind = match source.is_dir() {
+        true => None,
+        false =>
+            Some(
+                archive_kind(source).with_context(||
+                    format!("{:?} is neither a folder nor a .tar or .zip archive", source)
+                )?
+            ),
+    };
+
+    // Moving within a filesystem doesn't need space
+    if !(move_files && kind.is_none()) {
+        match disk_space::check(remaining_download_bytes(&config, &model_path), &model_path) {
+            Ok(space) if !space.sufficient => {
+                return Err(ModelError::InsufficientDisk {
+                    required: space.required_bytes,
+                    available: space.available_bytes,
+                });
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Skipping disk space check: {:#}", e),
+        }
+    }
+
+    std::fs::create_dir_all(&model_path)?;
+    let guard = downloads.begin(variant, &model_path)?;
+
+    log::info!("Importing {} from {:?}", variant, source);
+    let mut staging = Staging::new(&model_path, &config);
+    let result = stage_and_commit(&mut staging, variant, &config, source, kind, move_files);
+    if let Err(e) = result {
+        staging.discard();
+        return Err(anyhow::anyhow!("Failed to import {}: {:#}", variant, e).into());
+    }
+
+    drop(guard);
+    log::info!("Imported {} into {:?}", variant, model_path);
+    Ok(check_model_status(variant.clone(), custom_dir, quantization))
+}
+
+fn write_tar(destination: &Path, model_path: &Path, bundle: &BundleManifest) -> Result<()> {
+    let mut builder = tar::Builder::new(File::create(destination)?);
+    let manifest = serde_json::to_vec_pretty(bundle)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, BUNDLE_MANIFEST, manifest.as_slice())?;
+    for file in &bundle.files {
+        builder.append_path_with_name(model_path.join(&file.name), &file.name)?;
+    }
+    builder.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+fn write_zip(destination: &Path, model_path: &Path, bundle: &BundleManifest) -> Result<()> {
+    use std::io::Write;
+
+    let mut writer = zip::ZipWriter::new(File::create(destination)?);
+    // GGUF weights barely compress, and files over 4 GB need zip64
+    let options = zip::write::SimpleFileOptions
+        ::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true);
+    writer.start_file(BUNDLE_MANIFEST, options)?;
+    writer.write_all(&serde_json::to_vec_pretty(bundle)?)?;
+    for file in &bundle.files {
+        writer.start_file(file.name.as_str(), options)?;
+        std::io::copy(&mut File::open(model_path.join(&file.name))?, &mut writer)?;
+    }
+    writer.finish()?.sync_all()?;
+    Ok(())
+}
+
+/// Pack a downloaded model into a `.tar` or `.zip` archive for
+/// `import_model` on other machines, returning the archive size
+pub fn export_model(
+    variant: &ModelVariant,
+    custom_dir: Option<PathBuf>,
+    quantization: QuantizationConfig,
+    destination: &Path
+) -> Result<u64> {
+    let kind = archive_kind(destination).context("Models export to a .tar or .zip file")?;
+    match check_model_status(variant.clone(), custom_dir.clone(), quantization) {
+        ModelStatus::Ready => {}
+        ModelStatus::Corrupt { file } => anyhow::bail!("{} failed its checksum; repair the model before exporting it", file),
+        _ => anyhow::bail!("{} isn't downloaded at this quantization", variant),
+    }
+
+    let model_path = get_model_path(variant.clone(), custom_dir)?;
+    let config = ModelConfig::from_variant(variant.clone(), quantization)?;
+    let files = config.files
+        .iter()
+        .map(|file| {
+            Ok(BundleFile {
+                name: file.clone(),
+                size_bytes: std::fs::metadata(model_path.join(file))?.len(),
+                sha256: model_verification::file_sha256(&model_path, file)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // Where the files came from, which may be a pinned revision rather than the manifest's
+    let recorded = model_revision::recorded(&model_path, &config.files[0]);
+    let bundle = BundleManifest {
+        model_id: variant.registry_id().unwrap_or_default().to_string(),
+        hf_repo: config.hf_repo.clone(),
+        revision: recorded
+            .as_ref()
+            .map(|recorded| recorded.revision.clone())
+            .unwrap_or_else(|| config.revision.clone()),
+        commit: recorded.and_then(|recorded| recorded.commit),
+        files,
+    };
+
+    // Written under another name so an interrupted export isn't mistaken for a bundle
+    let part = part_path(destination);
+    let written = match kind {
+        ArchiveKind::Tar => write_tar(&part, &model_path, &bundle),
+        ArchiveKind::Zip => write_zip(&part, &model_path, &bundle),
+    };
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&part);
+        return Err(e.context(format!("Failed to write {:?}", destination)));
+    }
+    std::fs::rename(&part, destination).with_context(|| format!("Failed to move {:?} into place", part))?;
+
+    log::info!("Exported {} to {:?}", variant, destination);
+    Ok(std::fs::metadata(destination)?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn install(cache_dir: &Path) -> (PathBuf, Vec<String>) {
+        let path = get_model_path(ModelVariant::Qwen3VL2B, Some(cache_dir.to_path_buf())).unwrap();
+        std::fs::create_dir_all(&path).unwrap();
+        let files = ModelConfig::from_variant(ModelVariant::Qwen3VL2B, QuantizationConfig::default()).unwrap().files;
+        for (index, file) in files.iter().enumerate() {
+            std::fs::write(path.join(file), vec![index as u8 + 1; 1000]).unwrap();
+        }
+        (path, files)
+    }
+
+    #[test]
+    fn test_export_and_import_archives() {
+        let temp_dir = TempDir::new().unwrap();
+        let online = temp_dir.path().join("online");
+        let (online_path, files) = install(&online);
+        let revision = FileRevision {
+            hf_repo: "Qwen/Qwen3-VL-2B-Instruct-GGUF".to_string(),
+            revision: "main".to_string(),
+            commit: Some("abc123".to_string()),
+        };
+        model_revision::record(&online_path, &files[0], &revision).unwrap();
+
+        for name in ["model.tar", "model.zip"] {
+            let archive = temp_dir.path().join(name);
+            let size = export_model(&ModelVariant::Qwen3VL2B, Some(online.clone()), QuantizationConfig::default(), &archive).unwrap();
+            assert!(size >= 2000);
+
+            let offline = temp_dir.path().join(format!("offline-{}", name));
+            // A hash recorded for an earlier copy doesn't hold up the import
+            let path = get_model_path(ModelVariant::Qwen3VL2B, Some(offline.clone())).unwrap();
+            std::fs::create_dir_all(&path).unwrap();
+            model_verification::record_expected(&path, &files[0], &"0".repeat(64)).unwrap();
+            let status = import_model(
+                &ModelVariant::Qwen3VL2B,
+                Some(offline.clone()),
+                QuantizationConfig::default(),
+                &archive,
+                false,
+                &ActiveDownloads::default()
+            ).unwrap();
+            assert_eq!(status, ModelStatus::Ready);
+
+            assert_eq!(std::fs::read(path.join(&files[1])).unwrap(), vec![2u8; 1000]);
+            // Bundle hashes only check the import; revisions are kept for
+            // update checks
+            assert!(!model_verification::has_expected(&path, &files[0], None));
+            assert_eq!(model_revision::recorded(&path, &files[0]).unwrap().commit.as_deref(), Some("abc123"));
+        }
+
+        // Only bundles of the same model are accepted
+        let archive = temp_dir.path().join("model.tar");
+        let result = import_model(
+            &ModelVariant::Qwen3VL4B,
+            Some(temp_dir.path().join("other")),
+            QuantizationConfig::default(),
+            &archive,
+            false,
+            &ActiveDownloads::default()
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_import_folder_checks_every_file_first() {
+        let temp_dir = TempDir::new().unwrap();
+        let (source, files) = install(&temp_dir.path().join("source"));
+        let cache_dir = Some(temp_dir.path().join("cache"));
+        let import = |move_files| {
+            import_model(
+                &ModelVariant::Qwen3VL2B,
+                cache_dir.clone(),
+                QuantizationConfig::default(),
+                &source,
+                move_files,
+                &ActiveDownloads::default()
+            )
+        };
+
+        // A bundle manifest whose hash the projector doesn't match
+        let bundle = BundleManifest {
+            model_id: "Qwen3VL2B".to_string(),
+            hf_repo: "Qwen/Qwen3-VL-2B-Instruct-GGUF".to_string(),
+            revision: "main".to_string(),
+            commit: None,
+            files: vec![BundleFile { name: files[1].clone(), size_bytes: 1000, sha256: "0".repeat(64) }],
+        };
+        std::fs::write(source.join(BUNDLE_MANIFEST), serde_json::to_string(&bundle).unwrap()).unwrap();
+        assert!(import(true).is_err());
+        // Moved files are put back, and nothing is left behind
+        assert!(source.join(&files[0]).exists());
+        assert!(source.join(&files[1]).exists());
+        let target = get_model_path(ModelVariant::Qwen3VL2B, cache_dir.clone()).unwrap();
+        assert!(!target.join(&files[0]).exists());
+        assert!(!part_path(&target.join(&files[0])).exists());
+
+        std::fs::remove_file(source.join(BUNDLE_MANIFEST)).unwrap();
+        assert_eq!(import(true).unwrap(), ModelStatus::Ready);
+        assert!(!source.join(&files[0]).exists());
+
+        // Missing files are named
+        let err = import(false).unwrap_err().to_string();
+        assert!(err.contains(&files[0]) && err.contains(&files[1]));
+    }
+}
@@ -1,26 +1,57 @@
-use crate::settings::ModelVariant;
+use crate::active_downloads::{ self, ActiveDownloads };
+use crate::disk_space::{ self, DiskCheck };
+use crate::download_progress::ProgressTracker;
+use crate::hf_cache;
+use crate::memory_estimate::BYTES_PER_GB;
+use crate::model_registry::find_model;
+use crate::model_revision::{ self, FileRevision };
+use crate::model_verification::{ self, HubFile, Verification };
+use crate::resumable_download::{ discard_part, download_file, hub_file_url, part_path, Cancelled };
+use crate::settings::{ AppSettings, HubConfig, ModelVariant, QuantizationConfig };
 use anyhow::{ Context, Result };
 use serde::{ Deserialize, Serialize };
 use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::{ Duration, Instant };
 use tauri::Emitter;
 use thiserror::Error;
 
-#[derive(Clone, Serialize)]
-struct DownloadProgress {
-    current_file: usize,
-    total_files: usize,
-    file_name: String,
-    progress_percent: u8,
+const DEFAULT_HF_ENDPOINT: &str = "https://huggingface.co";
+
+/// Attempts per file before a download gives up; progress is kept either way
+pub(crate) const DOWNLOAD_ATTEMPTS: usize = 3;
+pub(crate) const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Outcome of a model download
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DownloadReport {
+    /// Duplicate bytes removed from the Hugging Face cache, where earlier
+    /// versions kept a second copy of each file
+    pub bytes_saved: u64,
 }
 
 /// Errors that can occur during model operations
 #[derive(Debug, Error)]
 pub enum ModelError {
     #[error("Model download failed: {0}")] DownloadFailed(String),
+    #[error(
+        "Not enough disk space: the download needs {:.1} GB but only {:.1} GB is free",
+        gb(.required),
+        gb(.available)
+    )] InsufficientDisk {
+        required: u64,
+        available: u64,
+    },
+    #[error("{0} is already being downloaded")] AlreadyDownloading(String),
+    #[error("Download cancelled")] Cancelled,
     #[error("IO error: {0}")] IoError(#[from] std::io::Error),
     #[error("Error: {0}")] Other(#[from] anyhow::Error),
 }
 
+fn gb(bytes: &u64) -> f64 {
+    (*bytes as f64) / BYTES_PER_GB
+}
+
 /// Status of a model on the system
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "status", rename_all = "snake_case")]
@@ -30,64 +61,204 @@ pub enum ModelStatus {
         progress_percent: u8,
     },
     Ready,
+    /// A file is present but its SHA-256 doesn't match the published hash
+    Corrupt {
+        file: String,
+    },
     Error {
         message: String,
     },
 }
 
-/// Configuration for a specific Qwen2-VL model variant
+/// Hub endpoint and token downloads use
+pub struct HubSource {
+    pub endpoint: String,
+    pub token: Option<String>,
+}
+
+impl HubSource {
+    /// The hub settings, falling back to `HF_ENDPOINT` and the token saved
+    /// by `huggingface-cli login` like other Hugging Face tools
+    pub fn resolve(hub: &HubConfig) -> Self {
+        let configured = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        Self {
+            endpoint: configured(&hub.endpoint)
+                .or_else(|| std::env::var("HF_ENDPOINT").ok())
+                .unwrap_or_else(|| DEFAULT_HF_ENDPOINT.to_string()),
+            token: configured(&hub.token).or_else(|| hf_hub::Cache::default().token()),
+        }
+    }
+
+    pub fn file_url(&self, config: &ModelConfig, revision: &str, file: &str) -> String {
+        hub_file_url(&self.endpoint, &config.hf_repo, revision, file)
+    }
+}
+
+/// Download details of a manifest model at a given quantization
 pub struct ModelConfig {
     pub variant: ModelVariant,
     pub hf_repo: String,
+    /// Branch, tag or commit the files are downloaded from
+    pub revision: String,
+    /// Language model file followed by the vision projector file
     pub files: Vec<String>,
+    /// Approximate size of each file, in the same order
+    pub file_sizes: Vec<u64>,
+    /// SHA-256 of each file from the manifest, when published there
+    pub file_sha256: Vec<Option<String>>,
     pub total_size_bytes: u64,
+    /// Extra memory for the KV cache and compute buffers, in GB
+    pub runtime_overhead_gb: f32,
 }
 
 impl ModelConfig {
-    /// Create a ModelConfig for the specified variant
-    pub fn from_variant(variant: ModelVariant) -> Self {
-        match variant {
-            ModelVariant::Qwen3VL2B =>
-                Self {
-                    variant,
-                    hf_repo: "Qwen/Qwen3-VL-2B-Instruct-GGUF".to_string(),
-                    files: vec![
-                        // Main model file (Q8_0 quantization for quality)
-                        // Qwen3VL-2B-Instruct-F16.gguf
-                        // Qwen3VL-2B-Instruct-Q4_K_M.gguf
-                        // Qwen3VL-2B-Instruct-Q8_0.gguf
-                        "Qwen3VL-2B-Instruct-Q8_0.gguf".to_string(),
-                        // Vision projection file (F16 for quality)
-                        // mmproj-Qwen3VL-2B-Instruct-F16.gguf
-                        // mmproj-Qwen3VL-2B-Instruct-Q8_0.gguf
-                        "mmproj-Qwen3VL-2B-Instruct-Q8_0.gguf".to_string()
-                    ],
-                    total_size_bytes: 1_900_000_000, // ~1.9 GB
-                },
-            ModelVariant::Qwen3VL4B =>
-                Self {
-                    variant,
-                    hf_repo: "Qwen/Qwen3-VL-4B-Instruct-GGUF".to_string(),
-                    files: vec![
-                        "Qwen3VL-4B-Instruct-Q8_0.gguf".to_string(),
-                        "mmproj-Qwen3VL-4B-Instruct-Q8_0.gguf".to_string()
-                    ],
-                    total_size_bytes: 3_300_000_000, // ~3.3 GB
-                },
-            ModelVariant::Qwen3VL8B =>
-                Self {
-                    variant,
-                    hf_repo: "Qwen/Qwen3-VL-8B-Instruct-GGUF".to_string(),
-                    files: vec![
-                        "Qwen3VL-8B-Instruct-Q8_0.gguf".to_string(),
-                        "mmproj-Qwen3VL-8B-Instruct-Q8_0.gguf".to_string()
-                    ],
-                    total_size_bytes: 6_100_000_000, // ~6.1 GB
-                },
+    /// Create a ModelConfig for the specified variant and quantization from
+    /// the model manifest; custom models have none
+    pub fn from_variant(variant: ModelVariant, quantization: QuantizationConfig) -> Result<Self> {
+        let entry = find_model(&variant)?;
+        let files = entry.files(quantization)?;
+
+        Ok(Self {
+            variant,
+            hf_repo: entry.hf_repo.clone(),
+            revision: entry.revision.clone(),
+            files: files
+                .iter()
+                .map(|f| f.file.clone())
+                .collect(),
+            file_sizes: files
+                .iter()
+                .map(|f| f.size_bytes)
+                .collect(),
+            file_sha256: files
+                .iter()
+                .map(|f| f.sha256.clone())
+                .collect(),
+            total_size_bytes: files
+                .iter()
+                .map(|f| f.size_bytes)
+                .sum(),
+            runtime_overhead_gb: entry.runtime_overhead_gb,
+        })
+    }
+
+    /// Download the revision pinned in the hub settings instead of the
+    /// manifest's, when one is set. The manifest hashes are of the manifest
+    /// revision, so another one goes by the hashes the hub publishes.
+    pub fn pinned(mut self, hub: &HubConfig) -> Self {
+        if let Some(revision) = hub.revision_for(&self.variant) {
+            if revision != self.revision {
+                self.revision = revision.to_string();
+                self.file_sha256.fill(None);
+            }
+        }
+        self
+    }
+
+    /// RAM needed to load both files and run inference, in GB
+    pub fn required_memory_gb(&self) -> f32 {
+        (self.total_size_bytes as f64 / BYTES_PER_GB) as f32 + self.runtime_overhead_gb
+    }
+}
+
+/// Download size and memory needs of a variant at a given quantization
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelRequirements {
+    pub download_size_bytes: u64,
+    pub required_memory_gb: f32,
+}
+
+impl From<&ModelConfig> for ModelRequirements {
+    fn from(config: &ModelConfig) -> Self {
+        Self {
+            download_size_bytes: config.total_size_bytes,
+            required_memory_gb: config.required_memory_gb(),
         }
     }
 }
 
+/// Bytes a download still has to write into `model_path`: files that are
+/// not there yet, less what their partial downloads already hold
+pub(crate) fn remaining_download_bytes(config: &ModelConfig, model_path: &Path) -> u64 {
+    config.files
+        .iter()
+        .zip(&config.file_sizes)
+        .map(|(file, size)| {
+            let target = model_path.join(file);
+            if target.exists() {
+                return 0;
+            }
+            let partial = std::fs::metadata(part_path(&target)).map(|meta| meta.len()).unwrap_or(0);
+            size.saturating_sub(partial)
+        })
+        .sum()
+}
+
+/// How much of a model's download is on disk, from 0 to 100
+fn downloaded_percent(config: &ModelConfig, model_path: &Path) -> u8 {
+    if config.total_size_bytes == 0 {
+        return 0;
+    }
+    let remaining = remaining_download_bytes(config, model_path);
+    let done = config.total_size_bytes.saturating_sub(remaining);
+    ((done as f64 / config.total_size_bytes as f64) * 100.0).min(100.0) as u8
+}
+
+/// Compare free space where a model would be stored with what its download
+/// still needs, so the Settings page can warn before the user starts it
+pub fn check_download_space(
+    variant: ModelVariant,
+    custom_dir: Option<PathBuf>,
+    quantization: QuantizationConfig
+) -> std::result::Result<DiskCheck, ModelError> {
+    let model_path = get_model_path(variant.clone(), custom_dir)?;
+    let config = ModelConfig::from_variant(variant, quantization)?;
+    Ok(disk_space::check(remaining_download_bytes(&config, &model_path), &model_path)?)
+}
+
+/// Resolved model and vision projector files for a variant
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ModelFiles {
+    pub model: PathBuf,
+    pub mmproj: PathBuf,
+}
+
+/// Locate the GGUF files for a built-in or custom variant
+pub fn resolve_model_files(variant: &ModelVariant, settings: &AppSettings) -> Result<ModelFiles> {
+    if let ModelVariant::Custom(name) = variant {
+        let model = settings
+            .custom_model(name)
+            .with_context(|| format!("Custom model '{}' is not registered", name))?;
+        return Ok(ModelFiles {
+            model: model.model_path.clone(),
+            mmproj: model.mmproj_path.clone(),
+        });
+    }
+
+    builtin_model_files(variant, settings.model_cache_dir.clone(), settings.quantization_for(variant))
+}
+
+/// Locate the GGUF files of a built-in variant at a given quantization
+pub fn builtin_model_files(
+    variant: &ModelVariant,
+    custom_dir: Option<PathBuf>,
+    quantization: QuantizationConfig
+) -> Result<ModelFiles> {
+    let model_path = get_model_path(variant.clone(), custom_dir)?;
+    let config = ModelConfig::from_variant(variant.clone(), quantization)?;
+
+    Ok(ModelFiles {
+        model: model_path.join(&config.files[0]),
+        mmproj: model_path.join(&config.files[1]),
+    })
+}
+
 /// Get the model cache directory, creating it if it doesn't exist
 pub fn get_model_cache_dir(custom_dir: Option<PathBuf>) -> Result<PathBuf> {
     if let Some(dir) = custom_dir {
@@ -107,17 +278,20 @@ pub fn get_model_cache_dir(custom_dir: Option<PathBuf>) -> Result<PathBuf> {
 
 /// Get the full path for a specific model variant
 pub fn get_model_path(variant: ModelVariant, custom_dir: Option<PathBuf>) -> Result<PathBuf> {
+    if let ModelVariant::Custom(name) = &variant {
+        anyhow::bail!("Custom model '{}' has no managed directory", name);
+    }
+    let entry = find_model(&variant)?;
     let cache_dir = get_model_cache_dir(custom_dir)?;
-    let variant_name = match variant {
-        ModelVariant::Qwen3VL2B => "qwen3-vl-2b",
-        ModelVariant::Qwen3VL4B => "qwen3-vl-4b",
-        ModelVariant::Qwen3VL8B => "qwen3-vl-8b",
-    };
-    Ok(cache_dir.join(variant_name))
+    Ok(cache_dir.join(entry.directory))
 }
 
-/// Check the status of a model variant on the system
-pub fn check_model_status(variant: ModelVariant, custom_dir: Option<PathBuf>) -> ModelStatus {
+/// Check the status of a model variant at a given quantization on the system
+pub fn check_model_status(
+    variant: ModelVariant,
+    custom_dir: Option<PathBuf>,
+    quantization: QuantizationConfig
+) -> ModelStatus {
     let model_path = match get_model_path(variant.clone(), custom_dir) {
         Ok(path) => path,
         Err(e) => {
@@ -133,7 +307,26 @@ pub fn check_model_status(variant: ModelVariant, custom_dir: Option<PathBuf>) ->
     }
 
     // Check if all required files exist
-    let config = ModelConfig::from_variant(variant);
+    let config = match ModelConfig::from_variant(variant, quantization) {
+        Ok(config) => config,
+        Err(e) => {
+            return ModelStatus::Error { message: e.to_string() };
+        }
+    };
+
+    // Files are incomplete while a download, possibly from another window or
+    // app instance, is still writing them
+    if active_downloads::is_locked(&model_path) {
+        return ModelStatus::Downloading {
+            progress_percent: downloaded_percent(&config, &model_path),
+        };
+    }
+
+    // Nothing downloaded yet at this quantization (other levels may be present)
+    if config.files.iter().all(|file| !model_path.join(file).exists()) {
+        return ModelStatus::NotDownloaded;
+    }
+
     for file in &config.files {
         let file_path = model_path.join(file);
         if !file_path.exists() {
@@ -143,90 +336,321 @@ pub fn check_model_status(variant: ModelVariant, custom_dir: Option<PathBuf>) ->
         }
     }
 
+    // Hashes are cached, so only new or changed files are read in full
+    for (file, sha256) in config.files.iter().zip(&config.file_sha256) {
+        match model_verification::verify_file(&model_path, file, sha256.as_deref()) {
+            Ok(Verification::Mismatch { expected, actual }) => {
+                log::warn!("{} has SHA-256 {}, expected {}", file, actual, expected);
+                return ModelStatus::Corrupt { file: file.clone() };
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return ModelStatus::Error {
+                    message: format!("Failed to verify {}: {:#}", file, e),
+                };
+            }
+        }
+    }
+
     ModelStatus::Ready
 }
 
-/// Download a model from Hugging Face
+/// Check every file of a model again, ignoring cached hashes, and fetch the
+/// published hashes the manifest lacks
+pub async fn verify_model(
+    variant: ModelVariant,
+    custom_dir: Option<PathBuf>,
+    quantization: QuantizationConfig,
+    hub: &HubConfig
+) -> std::result::Result<ModelStatus, ModelError> {
+    let model_path = get_model_path(variant.clone(), custom_dir.clone())?;
+    let config = ModelConfig::from_variant(variant.clone(), quantization)?.pinned(hub);
+
+    if model_path.exists() {
+        let source = HubSource::resolve(hub);
+        for (file, sha256) in config.files.iter().zip(&config.file_sha256) {
+            if !model_verification::has_expected(&model_path, file, sha256.as_deref()) {
+                // The commit the file was downloaded at, where it was recorded
+                let revision = model_revision
+                    ::recorded(&model_path, file)
+                    .and_then(|recorded| recorded.commit)
+                    .unwrap_or_else(|| config.revision.clone());
+                let url = source.file_url(&config, &revision, file);
+                if let Some(hub_file) = lookup_hub_file(&url, source.token.as_deref()).await {
+                    record_hub_sha256(&model_path, file, &hub_file);
+                }
+            }
+            model_verification::forget_hash(&model_path, file)?;
+        }
+    }
+
+    tokio::task
+        ::spawn_blocking(move || check_model_status(variant, custom_dir, quantization)).await
+        .map_err(|e| ModelError::Other(anyhow::anyhow!("Verification task failed: {}", e)))
+}
+
+/// Look a file up on the hub. Failures are only logged: without its
+/// published hash a file can still be downloaded and used, just not verified.
+async fn lookup_hub_file(url: &str, token: Option<&str>) -> Option<HubFile> {
+    match model_verification::fetch_hub_file(url, token).await {
+        Ok(hub_file) => Some(hub_file),
+        Err(e) => {
+            log::warn!("Failed to look up {}: {:#}", url, e);
+            None
+        }
+    }
+}
+
+/// Record the hash the hub publishes for `file`
+fn record_hub_sha256(model_path: &Path, file: &str, hub_file: &HubFile) {
+    match &hub_file.sha256 {
+        Some(sha256) => {
+            if let Err(e) = model_verification::record_expected(model_path, file, sha256) {
+                log::warn!("Failed to record SHA-256 of {}: {:#}", file, e);
+            }
+        }
+        None => log::info!("No SHA-256 published for {}", file),
+    }
+}
+
+/// Remember the revision `file` was downloaded at, for update checks
+fn record_revision(model_path: &Path, file: &str, revision: &FileRevision) {
+    if let Err(e) = model_revision::record(model_path, file, revision) {
+        log::warn!("Failed to record revision of {}: {:#}", file, e);
+    }
+}
+
+/// Hash a downloaded file off the async runtime
+async fn verify_downloaded(model_path: &Path, file: &str, sha256: Option<&str>) -> Result<Verification> {
+    let (model_path, file, sha256) = (model_path.to_path_buf(), file.to_string(), sha256.map(str::to_string));
+    tokio::task::spawn_blocking(move || model_verification::verify_file(&model_path, &file, sha256.as_deref())).await?
+}
+
+/// Check the status of any variant, including user-registered models
+pub fn check_variant_status(variant: &ModelVariant, settings: &AppSettings) -> ModelStatus {
+    match variant {
+        ModelVariant::Custom(name) =>
+            match settings.custom_model(name) {
+                Some(model) => crate::custom_models::check_custom_model_status(model),
+                None =>
+                    ModelStatus::Error {
+                        message: format!("Custom model '{}' is not registered", name),
+                    },
+            }
+        _ =>
+            check_model_status(
+                variant.clone(),
+                settings.model_cache_dir.clone(),
+                settings.quantization_for(variant)
+            ),
+    }
+}
+
+/// Download a model from Hugging Face. Only one download of a variant runs
+/// at a time; cancelling it through `downloads` removes its partial files.
 pub async fn download_model(
     variant: ModelVariant,
     custom_dir: Option<PathBuf>,
+    quantization: QuantizationConfig,
+    hub: &HubConfig,
+    downloads: &ActiveDownloads,
     app: tauri::AppHandle
-) -> std::result::Result<(), ModelError> {
+) -> std::result::Result<DownloadReport, ModelError> {
     let model_path = get_model_path(variant.clone(), custom_dir.clone())?;
-    let config = ModelConfig::from_variant(variant.clone());
+    let config = ModelConfig::from_variant(variant.clone(), quantization)?.pinned(hub);
+
+    // Fail before writing anything rather than halfway through a file
+    match disk_space::check(remaining_download_bytes(&config, &model_path), &model_path) {
+        Ok(space) if !space.sufficient => {
+            return Err(ModelError::InsufficientDisk {
+                required: space.required_bytes,
+                available: space.available_bytes,
+            });
+        }
+        Ok(_) => {}
+        Err(e) => log::warn!("Skipping disk space check: {:#}", e),
+    }
 
     // Create model directory
     std::fs::create_dir_all(&model_path)?;
+    let guard = downloads.begin(&variant, &model_path)?;
+
+    let result = download_files(&config, &model_path, &HubSource::resolve(hub), guard.cancelled(), app).await;
+    if let Err(ModelError::Cancelled) = result {
+        for file in &config.files {
+            discard_part(&model_path.join(file));
+        }
+        log::info!("Download of {} cancelled", variant);
+    }
+    result
+}
 
-    log::info!("Downloading model {:?} from {} to {:?}", variant, config.hf_repo, model_path);
+/// Fetch each file of `config` missing from `model_path`
+async fn download_files(
+    config: &ModelConfig,
+    model_path: &Path,
+    source: &HubSource,
+    cancelled: &AtomicBool,
+    app: tauri::AppHandle
+) -> std::result::Result<DownloadReport, ModelError> {
+    let variant = &config.variant;
 
     let total_files = config.files.len();
+    let client = reqwest::Client::new();
+    let token = source.token.as_deref();
+
+    // Resolve the revision to a commit once, so every file comes from the
+    // same version of the repository even if a branch moves meanwhile
+    let commit = lookup_hub_file(&source.file_url(config, &config.revision, &config.files[0]), token).await.and_then(
+        |hub_file| hub_file.commit
+    );
+    let revision = commit.as_deref().unwrap_or(&config.revision);
+    let file_revision = FileRevision {
+        hf_repo: config.hf_repo.clone(),
+        revision: config.revision.clone(),
+        commit: commit.clone(),
+    };
 
-    // Run the synchronous download in a blocking task to avoid blocking the async runtime
-    tokio::task
-        ::spawn_blocking(move || {
-            // Initialize HF Hub API with proper configuration
-            let api = hf_hub::api::sync::ApiBuilder
-                ::new()
-                .with_progress(true)
-                .build()
+    log::info!(
+        "Downloading model {} from {}/{}@{} to {:?}",
+        variant,
+        source.endpoint,
+        config.hf_repo,
+        revision,
+        model_path
+    );
+
+    let hub_cache = hf_cache::cache_root();
+    let mut report = DownloadReport::default();
+
+    let mut tracker = ProgressTracker::new(
+        config.files.iter().cloned().zip(config.file_sizes.iter().copied()).collect(),
+        move |progress| {
+            let _ = app.emit("download-progress", progress);
+        }
+    );
+
+    // Download each required file
+    for (index, file) in config.files.iter().enumerate() {
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(ModelError::Cancelled);
+        }
+        let target_path = model_path.join(file);
+        let sha256 = config.file_sha256[index].as_deref();
+        let url = source.file_url(config, revision, file);
+
+        // A file from another revision, such as one pinned since, is
+        // replaced, and the hash published for it no longer applies
+        let recorded = model_revision::recorded(model_path, file);
+        if recorded.as_ref().is_some_and(|recorded| !recorded.same_version(&file_revision)) {
+            model_verification::forget_expected(model_path, file)?;
+            if target_path.exists() {
+                log::info!("{} is from another revision, downloading it again", file);
+                std::fs::remove_file(&target_path)?;
+            }
+        }
+        if !model_verification::has_expected(model_path, file, sha256) {
+            if let Some(hub_file) = lookup_hub_file(&url, token).await {
+                record_hub_sha256(model_path, file, &hub_file);
+            }
+        }
+
+        // Files only appear under their final name once complete, but may
+        // have been damaged since
+        if target_path.exists() {
+            if let Verification::Mismatch { .. } = verify_downloaded(model_path, file, sha256).await? {
+                log::warn!("{} is corrupt, downloading it again", file);
+                std::fs::remove_file(&target_path)?;
+            } else {
+                log::info!("Already downloaded: {}", file);
+                // A copy left in the hub cache by an earlier version is redundant
+                if let Some(pointer) = hf_cache::cached_file(&hub_cache, &config.hf_repo, &config.revision, file) {
+                    match hf_cache::remove_duplicate(&pointer) {
+                        Ok(bytes) => {
+                            report.bytes_saved += bytes;
+                        }
+                        Err(e) => log::warn!("Failed to remove cached duplicate of {}: {:#}", file, e),
+                    }
+                }
+                // Keep the recorded commit when the hub didn't name one
+                if recorded.is_none() || commit.is_some() {
+                    record_revision(model_path, file, &file_revision);
+                }
+                tracker.skip_file(index, std::fs::metadata(&target_path)?.len(), Instant::now());
+                continue;
+            }
+        }
+
+        // The cache may hold another commit of a branch than the one resolved
+        let cached = hf_cache
+            ::cached_file(&hub_cache, &config.hf_repo, &config.revision, file)
+            .filter(|pointer| commit.is_none() || hf_cache::snapshot_commit(pointer) == commit);
+        if let Some(pointer) = cached {
+            let cached_revision = FileRevision { commit: hf_cache::snapshot_commit(&pointer), ..file_revision.clone() };
+            log::info!("Moving {} out of the Hugging Face cache", file);
+            report.bytes_saved += hf_cache
+                ::move_from_cache(&pointer, &target_path)
                 .map_err(|e|
-                    ModelError::DownloadFailed(format!("Failed to initialize HF Hub API: {}", e))
+                    ModelError::DownloadFailed(
+                        format!("Failed to move {} to model directory: {:#}", file, e)
+                    )
                 )?;
+            if let Verification::Mismatch { .. } = verify_downloaded(model_path, file, sha256).await? {
+                log::warn!("Cached copy of {} is corrupt, downloading it instead", file);
+                std::fs::remove_file(&target_path)?;
+            } else {
+                record_revision(model_path, file, &cached_revision);
+                tracker.skip_file(index, std::fs::metadata(&target_path)?.len(), Instant::now());
+                continue;
+            }
+        }
 
-            let repo = api.model(config.hf_repo.clone());
-
-            // Download each required file
-            for (index, file) in config.files.iter().enumerate() {
-                let current_file = index + 1;
-                log::info!("Downloading file {}/{}: {}", current_file, total_files, file);
-
-                // Emit progress event at start of file
-                let _ = app.emit("download-progress", DownloadProgress {
-                    current_file,
-                    total_files,
-                    file_name: file.clone(),
-                    progress_percent: (((current_file as f32) / (total_files as f32)) *
-                        100.0) as u8,
-                });
-
-                let downloaded_path = repo
-                    .get(file)
-                    .map_err(|e|
-                        ModelError::DownloadFailed(format!("Failed to download {}: {}", file, e))
-                    )?;
-
-                // Copy the downloaded file to our model directory
-                let target_path = model_path.join(file);
-                std::fs
-                    ::copy(&downloaded_path, &target_path)
-                    .map_err(|e|
-                        ModelError::DownloadFailed(
-                            format!("Failed to copy {} to model directory: {}", file, e)
-                        )
-                    )?;
-
-                log::info!("Successfully downloaded: {}", file);
+        log::info!("Downloading file {}/{}: {}", index + 1, total_files, file);
+
+        // Each retry resumes from the partial file the last one left behind
+        let mut attempt = 1;
+        loop {
+            let result = async {
+                download_file(&client, &url, token, &target_path, &mut tracker, index, cancelled).await?;
+                if let Verification::Mismatch { expected, actual } = verify_downloaded(model_path, file, sha256).await? {
+                    std::fs::remove_file(&target_path)?;
+                    anyhow::bail!("Downloaded {} has SHA-256 {}, expected {}", file, actual, expected);
+                }
+                Ok(())
+            }.await;
+            match result {
+                Ok(()) => {
+                    break;
+                }
+                Err(e) if e.is::<Cancelled>() => {
+                    return Err(ModelError::Cancelled);
+                }
+                Err(e) if attempt < DOWNLOAD_ATTEMPTS => {
+                    log::warn!("Download attempt {} of {} failed: {:#}", attempt, file, e);
+                    attempt += 1;
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+                Err(e) => {
+                    return Err(ModelError::DownloadFailed(format!("Failed to download {}: {:#}", file, e)));
+                }
             }
+        }
 
-            // Emit 100% completion
-            let _ = app.emit("download-progress", DownloadProgress {
-                current_file: total_files,
-                total_files,
-                file_name: "Complete".to_string(),
-                progress_percent: 100,
-            });
+        record_revision(model_path, file, &file_revision);
+        log::info!("Successfully downloaded: {}", file);
+    }
 
-            log::info!("Model download complete: {:?}", variant);
-            Ok::<(), ModelError>(())
-        }).await
-        .map_err(|e| ModelError::DownloadFailed(format!("Download task failed: {}", e)))??;
+    tracker.complete(Instant::now());
 
-    Ok(())
+    if report.bytes_saved > 0 {
+        log::info!("Freed {} bytes of duplicate files from the Hugging Face cache", report.bytes_saved);
+    }
+    log::info!("Model download complete: {}", variant);
+    Ok(report)
 }
 
 /// Helper function to calculate directory size recursively
-fn dir_size(path: &Path) -> std::io::Result<u64> {
+pub(crate) fn dir_size(path: &Path) -> std::io::Result<u64> {
     let mut total = 0;
 
     if path.is_dir() {
@@ -247,16 +671,23 @@ fn dir_size(path: &Path) -> std::io::Result<u64> {
     Ok(total)
 }
 
-/// Clear the model cache and return the number of bytes freed
+/// Clear the model cache, and the Hugging Face cache entries of manifest
+/// models left by earlier versions, and return the number of bytes freed
 pub fn clear_model_cache(custom_dir: Option<PathBuf>) -> std::result::Result<u64, ModelError> {
     let cache_dir = get_model_cache_dir(custom_dir)?;
 
+    let hub_cache = hf_cache::cache_root();
+    let mut bytes_freed = 0;
+    for model in crate::model_registry::manifest().models {
+        bytes_freed += hf_cache::clear_files(&hub_cache, &model.hf_repo, &model.published_files())?;
+    }
+
     if !cache_dir.exists() {
-        return Ok(0);
+        return Ok(bytes_freed);
     }
 
     // Calculate size before removal
-    let bytes_freed = dir_size(&cache_dir)?;
+    bytes_freed += dir_size(&cache_dir)?;
 
     // Remove the entire cache directory
     std::fs::remove_dir_all(&cache_dir)?;
@@ -269,15 +700,68 @@ pub fn clear_model_cache(custom_dir: Option<PathBuf>) -> std::result::Result<u64
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::settings::Quantization;
 
     #[test]
     fn test_model_config_2b() {
-        let config = ModelConfig::from_variant(ModelVariant::Qwen3VL2B);
+        let config = ModelConfig::from_variant(
+            ModelVariant::Qwen3VL2B,
+            QuantizationConfig::default()
+        ).unwrap();
         assert_eq!(config.hf_repo, "Qwen/Qwen3-VL-2B-Instruct-GGUF");
+        assert_eq!(config.revision, "main");
         assert_eq!(config.files.len(), 2); // GGUF model + mmproj
+        assert_eq!(config.files[0], "Qwen3VL-2B-Instruct-Q8_0.gguf");
+        assert_eq!(config.files[1], "mmproj-Qwen3VL-2B-Instruct-Q8_0.gguf");
         assert!(config.total_size_bytes > 0);
     }
 
+    #[test]
+    fn test_quantization_changes_files_and_memory() {
+        let q4 = QuantizationConfig { model: Quantization::Q4KM, mmproj: Quantization::Q8_0 };
+        let f16 = QuantizationConfig { model: Quantization::F16, mmproj: Quantization::F16 };
+
+        let small = ModelConfig::from_variant(ModelVariant::Qwen3VL4B, q4).unwrap();
+        let large = ModelConfig::from_variant(ModelVariant::Qwen3VL4B, f16).unwrap();
+
+        assert_eq!(small.files[0], "Qwen3VL-4B-Instruct-Q4_K_M.gguf");
+        assert_eq!(large.files[1], "mmproj-Qwen3VL-4B-Instruct-F16.gguf");
+        assert!(small.total_size_bytes < large.total_size_bytes);
+
+        // 4B at Q4_K_M must fit an 8 GB laptop
+        assert!(small.required_memory_gb() < 6.0);
+
+        let q4_mmproj = QuantizationConfig { model: Quantization::Q8_0, mmproj: Quantization::Q4KM };
+        assert!(ModelConfig::from_variant(ModelVariant::Qwen3VL4B, q4_mmproj).is_err());
+    }
+
+    #[test]
+    fn test_hub_settings_override_manifest() {
+        let mut hub = HubConfig {
+            endpoint: Some("https://mirror.internal/hf/ ".to_string()),
+            token: Some("hf_secret".to_string()),
+            ..HubConfig::default()
+        };
+        hub.revisions.insert("Qwen3VL2B".to_string(), "0123abcd".to_string());
+
+        let source = HubSource::resolve(&hub);
+        let mut config = ModelConfig::from_variant(ModelVariant::Qwen3VL2B, QuantizationConfig::default()).unwrap();
+        config.file_sha256[0] = Some("aa".repeat(32));
+        let config = config.pinned(&hub);
+        assert_eq!(config.revision, "0123abcd");
+        // The manifest hashes are of another revision
+        assert!(config.file_sha256.iter().all(Option::is_none));
+        assert_eq!(source.token.as_deref(), Some("hf_secret"));
+        assert_eq!(
+            source.file_url(&config, &config.revision, &config.files[0]),
+            format!("https://mirror.internal/hf/Qwen/Qwen3-VL-2B-Instruct-GGUF/resolve/0123abcd/{}", config.files[0])
+        );
+
+        // Models without a pin keep the manifest revision
+        let other = ModelConfig::from_variant(ModelVariant::Qwen3VL4B, QuantizationConfig::default()).unwrap().pinned(&hub);
+        assert_eq!(other.revision, "main");
+    }
+
     #[test]
     fn test_model_path_generation() {
         let path = get_model_path(ModelVariant::Qwen3VL2B, None).unwrap();
@@ -292,7 +776,128 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let custom_dir = Some(temp_dir.path().to_path_buf());
 
-        let status = check_model_status(ModelVariant::Qwen3VL2B, custom_dir);
+        let status = check_model_status(ModelVariant::Qwen3VL2B, custom_dir, QuantizationConfig::default());
         assert_eq!(status, ModelStatus::NotDownloaded);
     }
+
+    #[test]
+    fn test_remaining_download_bytes() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = ModelConfig::from_variant(ModelVariant::Qwen3VL2B, QuantizationConfig::default()).unwrap();
+        assert_eq!(remaining_download_bytes(&config, temp_dir.path()), config.total_size_bytes);
+
+        // The projector is complete and the model is 1000 bytes in
+        std::fs::write(temp_dir.path().join(&config.files[1]), b"gguf").unwrap();
+        std::fs::write(part_path(&temp_dir.path().join(&config.files[0])), vec![0u8; 1000]).unwrap();
+        assert_eq!(remaining_download_bytes(&config, temp_dir.path()), config.file_sizes[0] - 1000);
+    }
+
+    #[test]
+    fn test_model_status_corrupt_file() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let custom_dir = Some(temp_dir.path().to_path_buf());
+        let quantization = QuantizationConfig::default();
+
+        let model_path = get_model_path(ModelVariant::Qwen3VL2B, custom_dir.clone()).unwrap();
+        std::fs::create_dir_all(&model_path).unwrap();
+        let files = ModelConfig::from_variant(ModelVariant::Qwen3VL2B, quantization).unwrap().files;
+        for file in &files {
+            std::fs::write(model_path.join(file), b"gguf").unwrap();
+        }
+        // As if the hub published a hash for different contents
+        model_verification::record_expected(&model_path, &files[1], &"0".repeat(64)).unwrap();
+
+        assert_eq!(
+            check_model_status(ModelVariant::Qwen3VL2B, custom_dir, quantization),
+            ModelStatus::Corrupt { file: files[1].clone() }
+        );
+    }
+
+    #[test]
+    fn test_model_status_while_downloading() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let custom_dir = Some(temp_dir.path().to_path_buf());
+        let quantization = QuantizationConfig::default();
+        let config = ModelConfig::from_variant(ModelVariant::Qwen3VL2B, quantization).unwrap();
+
+        let model_path = get_model_path(ModelVariant::Qwen3VL2B, custom_dir.clone()).unwrap();
+        std::fs::create_dir_all(&model_path).unwrap();
+        std::fs::write(model_path.join(&config.files[1]), b"gguf").unwrap();
+
+        let guard = ActiveDownloads::default().begin(&ModelVariant::Qwen3VL2B, &model_path).unwrap();
+        assert!(matches!(
+            check_model_status(ModelVariant::Qwen3VL2B, custom_dir.clone(), quantization),
+            ModelStatus::Downloading { progress_percent } if progress_percent < 100
+        ));
+
+        // Without the lock the projector alone is an incomplete download
+        drop(guard);
+        assert!(matches!(
+            check_model_status(ModelVariant::Qwen3VL2B, custom_dir, quantization),
+            ModelStatus::Error { .. }
+        ));
+    }
+
+    #[test]
+    fn test_model_status_other_quantization_downloaded() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let custom_dir = Some(temp_dir.path().to_path_buf());
+        let q8 = QuantizationConfig::default();
+        let q4 = QuantizationConfig { model: Quantization::Q4KM, ..q8 };
+
+        let model_path = get_model_path(ModelVariant::Qwen3VL2B, custom_dir.clone()).unwrap();
+        std::fs::create_dir_all(&model_path).unwrap();
+        for file in ModelConfig::from_variant(ModelVariant::Qwen3VL2B, q8).unwrap().files {
+            std::fs::write(model_path.join(file), b"gguf").unwrap();
+        }
+
+        assert_eq!(check_model_status(ModelVariant::Qwen3VL2B, custom_dir.clone(), q8), ModelStatus::Ready);
+        // The Q8_0 projector is shared, but the Q4_K_M model is missing
+        assert!(matches!(
+            check_model_status(ModelVariant::Qwen3VL2B, custom_dir, q4),
+            ModelStatus::Error { .. }
+        ));
+    }
+
+    #[test]
+    fn test_custom_variant_has_no_download() {
+        let variant = ModelVariant::Custom("local".to_string());
+        assert!(ModelConfig::from_variant(variant.clone(), QuantizationConfig::default()).is_err());
+        assert!(get_model_path(variant.clone(), None).is_err());
+        assert!(get_model_path(ModelVariant::Registry("unknown".to_string()), None).is_err());
+
+        let status = check_variant_status(&variant, &AppSettings::default());
+        assert!(matches!(status, ModelStatus::Error { .. }));
+    }
+
+    #[test]
+    fn test_resolve_builtin_files() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let settings = AppSettings {
+            model_cache_dir: Some(temp_dir.path().to_path_buf()),
+            ..AppSettings::default()
+        };
+
+        let files = resolve_model_files(&ModelVariant::Qwen3VL4B, &settings).unwrap();
+        assert!(files.model.ends_with("qwen3-vl-4b/Qwen3VL-4B-Instruct-Q8_0.gguf"));
+        assert!(files.mmproj.ends_with("qwen3-vl-4b/mmproj-Qwen3VL-4B-Instruct-Q8_0.gguf"));
+
+        let mut settings = settings;
+        settings.model_quantization.insert("Qwen3VL4B".to_string(), QuantizationConfig {
+            model: Quantization::Q4KM,
+            ..QuantizationConfig::default()
+        });
+        let files = resolve_model_files(&ModelVariant::Qwen3VL4B, &settings).unwrap();
+        assert!(files.model.ends_with("qwen3-vl-4b/Qwen3VL-4B-Instruct-Q4_K_M.gguf"));
+    }
 }
@@ -0,0 +1,261 @@
+use crate::candle_inference::Qwen2VLInference;
+use crate::model_manager::ModelFiles;
+use crate::settings::{ ModelVariant, RuntimeConfig };
+use anyhow::Result;
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+
+/// Unload models when available system memory drops below this (GB)
+const LOW_MEMORY_THRESHOLD_GB: f32 = 1.0;
+
+/// Lifecycle state reported in `model-pool-status` events
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolState {
+    Loading,
+    Loaded,
+    Unloaded,
+}
+
+/// Files and options a pooled model was loaded with
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LoadedFrom {
+    pub files: ModelFiles,
+    pub runtime: RuntimeConfig,
+}
+
+impl LoadedFrom {
+    /// Whether the loaded model can serve a run with `files` and `runtime`.
+    /// Context options (n_ctx, batch sizes, flash attention) apply per run,
+    /// so only the load options have to agree.
+    pub fn matches(&self, files: &ModelFiles, runtime: &RuntimeConfig) -> bool {
+        self.files == *files &&
+            self.runtime.use_mlock == runtime.use_mlock &&
+            self.runtime.n_threads == runtime.n_threads
+    }
+}
+
+/// Payload of `model-pool-status` events
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ModelPoolEvent {
+    pub variant: ModelVariant,
+    pub state: PoolState,
+    pub load_time_ms: Option<u64>,
+    pub reason: Option<String>,
+    /// Set on `Loaded`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loaded_from: Option<LoadedFrom>,
+}
+
+/// Receives every load and unload of the pool
+pub type PoolListener = Arc<dyn Fn(&ModelPoolEvent) + Send + Sync>;
+
+struct PooledModel {
+    inference: Arc<Mutex<Qwen2VLInference>>,
+    loaded_from: LoadedFrom,
+    last_used: Instant,
+}
+
+/// Loaded offline models, held by the inference worker process
+#[derive(Clone, Default)]
+pub struct ModelPool {
+    models: Arc<Mutex<HashMap<ModelVariant, PooledModel>>>,
+    /// Serializes model loads so the same variant is never loaded twice
+    load_lock: Arc<Mutex<()>>,
+    listener: Option<PoolListener>,
+}
+
+impl ModelPool {
+    /// Pool that reports loads and unloads to `listener`
+    pub fn with_listener(listener: PoolListener) -> Self {
+        Self {
+            listener: Some(listener),
+            ..Self::default()
+        }
+    }
+
+    /// Whether a variant is currently loaded
+    pub fn is_loaded(&self, variant: &ModelVariant) -> bool {
+        self.models.lock().map(|models| models.contains_key(variant)).unwrap_or(false)
+    }
+
+    /// Return the loaded model for a variant, loading it first if necessary.
+    /// A copy loaded from other files or with other load options (mlock,
+    /// threads) is replaced.
+    ///
+    /// Blocks while loading; call from a blocking task.
+    pub fn get_or_load(
+        &self,
+        variant: &ModelVariant,
+        files: &ModelFiles,
+        runtime: &RuntimeConfig
+    ) -> Result<Arc<Mutex<Qwen2VLInference>>> {
+        if let Some(inference) = self.touch(variant, files, runtime) {
+            return Ok(inference);
+        }
+
+        let _guard = self.load_lock.lock().map_err(|_| anyhow::anyhow!("Model load lock poisoned"))?;
+
+        // Another caller may have finished loading while we waited
+        if let Some(inference) = self.touch(variant, files, runtime) {
+            return Ok(inference);
+        }
+        self.unload(variant, "model files or load options changed");
+
+        self.emit_status(variant, PoolState::Loading, None, None, None);
+        let started = Instant::now();
+
+        let inference = match Qwen2VLInference::from_files(&files.model, &files.mmproj, variant.clone(), runtime) {
+            Ok(inference) => Arc::new(Mutex::new(inference)),
+            Err(e) => {
+                self.emit_status(variant, PoolState::Unloaded, None, Some(e.to_string()), None);
+                return Err(e);
+            }
+        };
+
+        let load_time = started.elapsed();
+        let loaded_from = LoadedFrom { files: files.clone(), runtime: runtime.clone() };
+        log::info!("Loaded {:?} into model pool in {:.1}s", variant, load_time.as_secs_f32());
+
+        self.models
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Model pool lock poisoned"))?
+            .insert(variant.clone(), PooledModel {
+                inference: inference.clone(),
+                loaded_from: loaded_from.clone(),
+                last_used: Instant::now(),
+            });
+
+        self.emit_status(variant, PoolState::Loaded, Some(load_time.as_millis() as u64), None, Some(loaded_from));
+
+        Ok(inference)
+    }
+
+    /// Drop a loaded variant, returning whether it was loaded
+    pub fn unload(&self, variant: &ModelVariant, reason: &str) -> bool {
+        let removed = self.models
+            .lock()
+            .map(|mut models| models.remove(variant).is_some())
+            .unwrap_or(false);
+
+        if removed {
+            log::info!("Unloaded {:?} from model pool ({})", variant, reason);
+            self.emit_status(variant, PoolState::Unloaded, None, Some(reason.to_string()), None);
+        }
+
+        removed
+    }
+
+    /// Unload models idle longer than `idle_timeout`, and the least recently
+    /// used model when the system is low on memory
+    pub fn sweep(&self, idle_timeout: Duration) {
+        let idle: Vec<ModelVariant> = match self.models.lock() {
+            Ok(models) =>
+                models
+                    .iter()
+                    .filter(|(_, pooled)| pooled.last_used.elapsed() >= idle_timeout)
+                    .map(|(variant, _)| variant.clone())
+                    .collect(),
+            Err(_) => return,
+        };
+
+        for variant in idle {
+            self.unload(&variant, "idle timeout");
+        }
+
+        if crate::offline_analyzer::get_available_memory_gb() < LOW_MEMORY_THRESHOLD_GB {
+            let oldest = self.models.lock().ok().and_then(|models| {
+                models
+                    .iter()
+                    .min_by_key(|(_, pooled)| pooled.last_used)
+                    .map(|(variant, _)| variant.clone())
+            });
+
+            if let Some(variant) = oldest {
+                self.unload(&variant, "low memory");
+            }
+        }
+    }
+
+    /// Mark a variant as used and return it if loaded from `files` with the
+    /// same load options
+    fn touch(&self, variant: &ModelVariant, files: &ModelFiles, runtime: &RuntimeConfig) -> Option<Arc<Mutex<Qwen2VLInference>>> {
+        let mut models = self.models.lock().ok()?;
+        let pooled = models.get_mut(variant).filter(|pooled| pooled.loaded_from.matches(files, runtime))?;
+        pooled.last_used = Instant::now();
+        Some(pooled.inference.clone())
+    }
+
+    fn emit_status(
+        &self,
+        variant: &ModelVariant,
+        state: PoolState,
+        load_time_ms: Option<u64>,
+        reason: Option<String>,
+        loaded_from: Option<LoadedFrom>
+    ) {
+        if let Some(listener) = &self.listener {
+            listener(
+                &(ModelPoolEvent {
+                    variant: variant.clone(),
+                    state,
+                    load_time_ms,
+                    reason,
+                    loaded_from,
+                })
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_pool() {
+        let pool = ModelPool::default();
+        assert!(!pool.is_loaded(&ModelVariant::Qwen3VL2B));
+        assert!(!pool.unload(&ModelVariant::Qwen3VL2B, "test"));
+    }
+
+    #[test]
+    fn test_load_missing_model_fails() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let pool = ModelPool::with_listener(
+            Arc::new(move |event: &ModelPoolEvent| recorded.lock().unwrap().push(event.state.clone()))
+        );
+        let temp_dir = tempfile::TempDir::new().unwrap();
+
+        let files = ModelFiles {
+            model: temp_dir.path().join("model.gguf"),
+            mmproj: temp_dir.path().join("mmproj.gguf"),
+        };
+
+        let result = pool.get_or_load(&ModelVariant::Qwen3VL2B, &files, &RuntimeConfig::default());
+        assert!(result.is_err());
+        assert!(!pool.is_loaded(&ModelVariant::Qwen3VL2B));
+        assert_eq!(*events.lock().unwrap(), [PoolState::Loading, PoolState::Unloaded]);
+    }
+
+    #[test]
+    fn test_loaded_from_matches_load_options() {
+        let files = ModelFiles {
+            model: "q4/model.gguf".into(),
+            mmproj: "q4/mmproj.gguf".into(),
+        };
+        let loaded_from = LoadedFrom { files: files.clone(), runtime: RuntimeConfig::default() };
+
+        // Context options don't need a reload
+        let longer_context = RuntimeConfig { n_ctx: 16384, flash_attention: true, ..RuntimeConfig::default() };
+        assert!(loaded_from.matches(&files, &longer_context));
+
+        let mlock = RuntimeConfig { use_mlock: true, ..RuntimeConfig::default() };
+        assert!(!loaded_from.matches(&files, &mlock));
+        let other_quantization = ModelFiles { model: "q8/model.gguf".into(), ..files };
+        assert!(!loaded_from.matches(&other_quantization, &RuntimeConfig::default()));
+    }
+}
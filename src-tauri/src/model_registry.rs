@@ -0,0 +1,264 @@
+use crate::settings::{ get_config_dir, ModelVariant, Quantization, QuantizationConfig };
+use anyhow::{ Context, Result };
+use serde::{ Deserialize, Serialize };
+use std::collections::BTreeMap;
+use std::path::{ Component, Path };
+
+/// Manifest shipped with the app
+const BUNDLED_MANIFEST: &str = include_str!("../assets/models.json");
+
+/// User manifest in the config directory; entries replace bundled ones by id
+const USER_MANIFEST_FILE: &str = "models.json";
+
+/// One downloadable GGUF file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ManifestFile {
+    /// File name in the repo, also used on disk
+    pub file: String,
+    /// Approximate download size
+    pub size_bytes: u64,
+    /// Expected SHA-256 (hex), when published
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// A downloadable vision model: where it lives, what it needs, how to prompt it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ManifestModel {
+    /// Stable id, stored in settings as the model variant
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Short label shown on the model card
+    #[serde(default)]
+    pub badge: Option<String>,
+    pub hf_repo: String,
+    /// Branch, tag or commit to download
+    #[serde(default = "default_revision")]
+    pub revision: String,
+    /// Directory under the model cache
+    pub directory: String,
+    /// Prompt template with `{images}` and `{prompt}` placeholders;
+    /// Qwen's chat format when absent
+    #[serde(default)]
+    pub chat_template: Option<String>,
+    /// Extra memory for the KV cache and compute buffers, in GB
+    pub runtime_overhead_gb: f32,
+    /// Handles many images in one prompt poorly; the `Auto` offline strategy
+    /// captions each image first
+    #[serde(default)]
+    pub caption_first: bool,
+    /// Language model file for each published quantization
+    pub model_files: BTreeMap<Quantization, ManifestFile>,
+    /// Vision projector file for each published quantization
+    pub mmproj_files: BTreeMap<Quantization, ManifestFile>,
+}
+
+fn default_revision() -> String {
+    "main".to_string()
+}
+
+impl ManifestModel {
+    pub fn variant(&self) -> ModelVariant {
+        ModelVariant::from_registry_id(&self.id)
+    }
+
+    /// Model and vision projector files at a quantization
+    pub fn files(&self, quantization: QuantizationConfig) -> Result<[&ManifestFile; 2]> {
+        let model = self.model_files
+            .get(&quantization.model)
+            .with_context(|| {
+                format!(
+                    "{} is not published at {} quantization",
+                    self.name,
+                    quantization.model.file_suffix()
+                )
+            })?;
+        let mmproj = self.mmproj_files
+            .get(&quantization.mmproj)
+            .with_context(|| {
+                format!(
+                    "No {} vision projector is published for {}",
+                    quantization.mmproj.file_suffix(),
+                    self.name
+                )
+            })?;
+        Ok([model, mmproj])
+    }
+
+    /// Names of the files published at every quantization
+    pub fn published_files(&self) -> Vec<&str> {
+        self.model_files
+            .values()
+            .chain(self.mmproj_files.values())
+            .map(|file| file.file.as_str())
+            .collect()
+    }
+
+    /// Rough model size, for ordering models from largest to smallest
+    pub fn largest_file_bytes(&self) -> u64 {
+        self.model_files
+            .values()
+            .map(|file| file.size_bytes)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.id.is_empty() || self.hf_repo.is_empty() {
+            anyhow::bail!("Model entries need an id and a hf_repo");
+        }
+        // The directory is joined onto the model cache, so it must stay inside it
+        let components: Vec<Component> = Path::new(&self.directory).components().collect();
+        if !matches!(components.as_slice(), [Component::Normal(_)]) {
+            anyhow::bail!("Model '{}' has an invalid directory '{}'", self.id, self.directory);
+        }
+        if self.model_files.is_empty() || self.mmproj_files.is_empty() {
+            anyhow::bail!("Model '{}' lists no files", self.id);
+        }
+        if let Some(template) = &self.chat_template {
+            if !template.contains("{images}") || !template.contains("{prompt}") {
+                anyhow::bail!("Chat template of '{}' needs {{images}} and {{prompt}}", self.id);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// All downloadable models
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ModelManifest {
+    pub models: Vec<ManifestModel>,
+}
+
+impl ModelManifest {
+    fn parse(json: &str) -> Result<Self> {
+        let manifest: ModelManifest = serde_json::from_str(json).context("Failed to parse model manifest")?;
+        for model in &manifest.models {
+            model.validate()?;
+        }
+        Ok(manifest)
+    }
+
+    /// Replace entries with the same id and append new ones
+    fn merge(&mut self, overrides: ModelManifest) {
+        for model in overrides.models {
+            match self.models.iter_mut().find(|m| m.id == model.id) {
+                Some(existing) => {
+                    *existing = model;
+                }
+                None => self.models.push(model),
+            }
+        }
+    }
+
+    pub fn find(&self, id: &str) -> Option<&ManifestModel> {
+        self.models.iter().find(|m| m.id == id)
+    }
+}
+
+/// Bundled manifest with a user manifest file merged on top. A user file
+/// that can't be read is logged and ignored.
+fn load_manifest(user_manifest: Option<&Path>) -> ModelManifest {
+    let mut manifest = ModelManifest::parse(BUNDLED_MANIFEST).expect("Bundled model manifest is valid");
+
+    if let Some(path) = user_manifest.filter(|path| path.exists()) {
+        let overrides = std::fs
+            ::read_to_string(path)
+            .context("Failed to read user model manifest")
+            .and_then(|content| ModelManifest::parse(&content));
+        match overrides {
+            Ok(overrides) => manifest.merge(overrides),
+            Err(e) => log::warn!("Ignoring model manifest {:?}: {:#}", path, e),
+        }
+    }
+
+    manifest
+}
+
+/// The model manifest in effect
+pub fn manifest() -> ModelManifest {
+    let user_manifest = get_config_dir()
+        .map(|dir| dir.join(USER_MANIFEST_FILE))
+        .ok();
+    load_manifest(user_manifest.as_deref())
+}
+
+/// Manifest entry of a downloadable variant; custom models have none
+pub fn find_model(variant: &ModelVariant) -> Result<ManifestModel> {
+    match variant.registry_id() {
+        Some(id) =>
+            manifest()
+                .find(id)
+                .cloned()
+                .with_context(|| format!("Model '{}' is not in the model manifest", id)),
+        None => anyhow::bail!("Custom model '{}' is registered locally and has no download", variant),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_bundled_manifest() {
+        let manifest = load_manifest(None);
+        let ids: Vec<&str> = manifest.models
+            .iter()
+            .map(|m| m.id.as_str())
+            .collect();
+        assert_eq!(ids, ["Qwen3VL2B", "Qwen3VL4B", "Qwen3VL8B"]);
+
+        let model = manifest.find("Qwen3VL4B").unwrap();
+        assert_eq!(model.variant(), ModelVariant::Qwen3VL4B);
+        assert_eq!(model.directory, "qwen3-vl-4b");
+
+        let [file, mmproj] = model.files(QuantizationConfig::default()).unwrap();
+        assert_eq!(file.file, "Qwen3VL-4B-Instruct-Q8_0.gguf");
+        assert_eq!(mmproj.file, "mmproj-Qwen3VL-4B-Instruct-Q8_0.gguf");
+
+        // No Q4_K_M vision projector is published
+        let q4_mmproj = QuantizationConfig { model: Quantization::Q8_0, mmproj: Quantization::Q4KM };
+        assert!(model.files(q4_mmproj).is_err());
+    }
+
+    #[test]
+    fn test_user_manifest_overrides_and_extends() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(USER_MANIFEST_FILE);
+        let mut user = ModelManifest {
+            models: vec![load_manifest(None).find("Qwen3VL2B").unwrap().clone()],
+        };
+        user.models[0].revision = "abc123".to_string();
+        let mut added = user.models[0].clone();
+        added.id = "SmolVLM2".to_string();
+        added.directory = "smolvlm2".to_string();
+        user.models.push(added);
+        std::fs::write(&path, serde_json::to_string(&user).unwrap()).unwrap();
+
+        let manifest = load_manifest(Some(&path));
+        assert_eq!(manifest.models.len(), 4);
+        assert_eq!(manifest.find("Qwen3VL2B").unwrap().revision, "abc123");
+        assert_eq!(
+            manifest.find("SmolVLM2").unwrap().variant(),
+            ModelVariant::Registry("SmolVLM2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_user_manifest_is_ignored() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(USER_MANIFEST_FILE);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert_eq!(load_manifest(Some(&path)), load_manifest(None));
+
+        // Directories must stay inside the model cache
+        let mut user = load_manifest(None);
+        user.models[0].directory = "../elsewhere".to_string();
+        std::fs::write(&path, serde_json::to_string(&user).unwrap()).unwrap();
+        assert_eq!(load_manifest(Some(&path)), load_manifest(None));
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::{ Context, Result };
+use serde::{ Deserialize, Serialize };
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Where each file in a model directory came from, kept next to the files
+const REVISION_FILE: &str = ".revision.json";
+
+/// Repository version a model file was downloaded at
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileRevision {
+    pub hf_repo: String,
+    /// Branch, tag or commit that was asked for
+    pub revision: String,
+    /// Commit the revision resolved to; `None` when the hub didn't say
+    pub commit: Option<String>,
+}
+
+impl FileRevision {
+    /// Whether both name the same version of the repository: the same
+    /// commit where both are known, else the same revision
+    pub fn same_version(&self, other: &FileRevision) -> bool {
+        self.hf_repo == other.hf_repo &&
+            (match (&self.commit, &other.commit) {
+                (Some(commit), Some(other_commit)) => commit == other_commit,
+                _ => self.revision == other.revision,
+            })
+    }
+}
+
+/// Recorded revisions of every file in `dir`, by file name
+pub fn load(dir: &Path) -> BTreeMap<String, FileRevision> {
+    std::fs
+        ::read_to_string(dir.join(REVISION_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Recorded revision of `file` in `dir`
+pub fn recorded(dir: &Path, file: &str) -> Option<FileRevision> {
+    load(dir).remove(file)
+}
+
+/// Remember where `file` in `dir` came from
+pub fn record(dir: &Path, file: &str, revision: &FileRevision) -> Result<()> {
+    let mut revisions = load(dir);
+    revisions.insert(file.to_string(), revision.clone());
+    let content = serde_json::to_string_pretty(&revisions)?;
+    std::fs::write(dir.join(REVISION_FILE), content).context("Failed to save model revisions")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_record_per_file() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(recorded(temp_dir.path(), "model.gguf"), None);
+
+        let old = FileRevision {
+            hf_repo: "org/model".to_string(),
+            revision: "main".to_string(),
+            commit: Some("aaa".to_string()),
+        };
+        let new = FileRevision { commit: Some("bbb".to_string()), ..old.clone() };
+        record(temp_dir.path(), "model.gguf", &old).unwrap();
+        record(temp_dir.path(), "mmproj.gguf", &old).unwrap();
+        record(temp_dir.path(), "model.gguf", &new).unwrap();
+        assert!(!old.same_version(&new));
+        assert!(old.same_version(&FileRevision { commit: None, ..old.clone() }));
+        assert!(!old.same_version(&FileRevision { revision: "v2".to_string(), commit: None, ..old.clone() }));
+
+        assert_eq!(recorded(temp_dir.path(), "model.gguf"), Some(new));
+        assert_eq!(recorded(temp_dir.path(), "mmproj.gguf"), Some(old));
+        assert_eq!(load(temp_dir.path()).len(), 2);
+    }
+}
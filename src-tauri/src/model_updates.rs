@@ -0,0 +1,404 @@
+use crate::active_downloads::{ self, ActiveDownloads };
+use crate::disk_space;
+use crate::download_progress::{ DownloadProgress, ProgressTracker };
+use crate::installed_models::list_installed_models;
+use crate::model_manager::{ get_model_path, HubSource, ModelError, DOWNLOAD_ATTEMPTS, RETRY_DELAY };
+use crate::model_registry::{ find_model, ManifestFile, ManifestModel };
+use crate::model_revision::{ self, FileRevision };
+use crate::model_verification::{ self, HubFile, Verification };
+use crate::resumable_download::{ download_file, hub_file_url, Cancelled };
+use crate::settings::{ HubConfig, ModelVariant };
+use anyhow::{ Context, Result };
+use serde::Serialize;
+use std::future::Future;
+use std::path::{ Path, PathBuf };
+use std::time::Instant;
+
+/// New versions of files are downloaded here and only replace the working
+/// copy once every one of them is verified
+const UPDATE_DIR: &str = ".update";
+
+/// A model file whose published version differs from the local one
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FileUpdate {
+    pub name: String,
+    /// Size of the published version
+    pub size_bytes: u64,
+    /// SHA-256 of the published version, when the hub gives one
+    pub sha256: Option<String>,
+}
+
+/// Newer files available for an installed model
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ModelUpdate {
+    pub variant: ModelVariant,
+    pub name: String,
+    /// Branch, tag or commit the update comes from
+    pub revision: String,
+    /// Commit the local files were downloaded at, when recorded
+    pub local_commit: Option<String>,
+    /// Commit `revision` points at now
+    pub remote_commit: Option<String>,
+    pub files: Vec<FileUpdate>,
+    /// Bytes to download to apply the update
+    pub update_bytes: u64,
+}
+
+fn manifest_file<'a>(model: &'a ManifestModel, file: &str) -> Option<&'a ManifestFile> {
+    model.model_files
+        .values()
+        .chain(model.mmproj_files.values())
+        .find(|published| published.file == file)
+}
+
+/// Whether the published file differs from the local one: by hash, or by
+/// the recorded commit where the hub publishes no hash
+fn is_outdated(local_sha256: &str, local: Option<&FileRevision>, remote: &HubFile) -> bool {
+    match &remote.sha256 {
+        Some(remote_sha256) => !remote_sha256.eq_ignore_ascii_case(local_sha256),
+        None =>
+            match (local.and_then(|local| local.commit.as_deref()), remote.commit.as_deref()) {
+                (Some(local), Some(remote)) => local != remote,
+                _ => false,
+            }
+    }
+}
+
+/// Hash a local file off the async runtime; usually cached since download
+async fn local_sha256(model_path: &Path, file: &str) -> Result<String> {
+    let (model_path, file) = (model_path.to_path_buf(), file.to_string());
+    tokio::task::spawn_blocking(move || model_verification::file_sha256(&model_path, &file)).await?
+}
+
+/// Compare the manifest files in `model_path` with the ones published now.
+/// The revision checked is the pinned one, else the one the files were
+/// downloaded at, else the manifest's.
+async fn find_update(
+    model: &ManifestModel,
+    model_path: &Path,
+    files: &[String],
+    source: &HubSource,
+    hub: &HubConfig
+) -> Result<Option<ModelUpdate>> {
+    let variant = model.variant();
+    let recorded = model_revision::load(model_path);
+    let revision = hub
+        .revision_for(&variant)
+        .map(str::to_string)
+        .or_else(|| files.iter().find_map(|file| recorded.get(file)).map(|recorded| recorded.revision.clone()))
+        .unwrap_or_else(|| model.revision.clone());
+
+    let mut update = ModelUpdate {
+        variant,
+        name: model.name.clone(),
+        local_commit: files.iter().find_map(|file| recorded.get(file)?.commit.clone()),
+        remote_commit: None,
+        revision,
+        files: Vec::new(),
+        update_bytes: 0,
+    };
+    for file in files {
+        let url = hub_file_url(&source.endpoint, &model.hf_repo, &update.revision, file);
+        let remote = model_verification::fetch_hub_file(&url, source.token.as_deref()).await?;
+        update.remote_commit = update.remote_commit.or(remote.commit.clone());
+
+        let local = local_sha256(model_path, file).await?;
+        if is_outdated(&local, recorded.get(file), &remote) {
+            let size_bytes = remote.size
+                .or_else(|| manifest_file(model, file).map(|published| published.size_bytes))
+                .unwrap_or(0);
+            update.files.push(FileUpdate { name: file.clone(), size_bytes, sha256: remote.sha256 });
+            update.update_bytes += size_bytes;
+        }
+    }
+
+    Ok((!update.files.is_empty()).then_some(update))
+}
+
+/// Complete manifest files of an installed model
+fn update_candidates(model: &ManifestModel, files: impl IntoIterator<Item = String>) -> Vec<String> {
+    files
+        .into_iter()
+        .filter(|file| manifest_file(model, file).is_some())
+        .collect()
+}
+
+/// Installed models with newer files on the hub, skipping any being downloaded
+pub async fn check_model_updates(custom_dir: Option<PathBuf>, hub: &HubConfig) -> Result<Vec<ModelUpdate>> {
+    let source = HubSource::resolve(hub);
+    let mut updates = Vec::new();
+    for installed in list_installed_models(custom_dir)? {
+        if active_downloads::is_locked(&installed.path) {
+            continue;
+        }
+        let model = find_model(&installed.variant)?;
+        let files = update_candidates(&model, installed.files.into_iter().map(|file| file.name));
+        let update = find_update(&model, &installed.path, &files, &source, hub).await.with_context(||
+            format!("Failed to check {} for updates", model.name)
+        )?;
+        updates.extend(update);
+    }
+    Ok(updates)
+}
+
+/// Download `update` into the staging directory, checking each file
+async fn stage_update(
+    update: &ModelUpdate,
+    model: &ManifestModel,
+    staging: &Path,
+    source: &HubSource,
+    tracker: &mut ProgressTracker,
+    cancelled: &std::sync::atomic::AtomicBool
+) -> std::result::Result<Vec<String>, ModelError> {
+    let client = reqwest::Client::new();
+    let revision = update.remote_commit.as_deref().unwrap_or(&update.revision);
+    let mut hashes = Vec::new();
+
+    for (index, file) in update.files.iter().enumerate() {
+        let url = hub_file_url(&source.endpoint, &model.hf_repo, revision, &file.name);
+        let target = staging.join(&file.name);
+
+        let mut attempt = 1;
+        let sha256 = loop {
+            let result = async {
+                download_file(&client, &url, source.token.as_deref(), &target, tracker, index, cancelled).await?;
+                let actual = local_sha256(staging, &file.name).await?;
+                if let Verification::Mismatch { expected, actual } = model_verification::compare(file.sha256.as_deref(), &actual) {
+                    std::fs::remove_file(&target)?;
+                    anyhow::bail!("Downloaded {} has SHA-256 {}, expected {}", file.name, actual, expected);
+                }
+                Ok(actual)
+            }.await;
+            match result {
+                Ok(sha256) => {
+                    break sha256;
+                }
+                Err(e) if e.is::<Cancelled>() => {
+                    return Err(ModelError::Cancelled);
+                }
+                Err(e) if attempt < DOWNLOAD_ATTEMPTS => {
+                    log::warn!("Update attempt {} of {} failed: {:#}", attempt, file.name, e);
+                    attempt += 1;
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+                Err(e) => {
+                    return Err(ModelError::DownloadFailed(format!("Failed to download {}: {:#}", file.name, e)));
+                }
+            }
+        };
+        hashes.push(sha256);
+    }
+    Ok(hashes)
+}
+
+/// Download the newer files of `variant` and swap them in. The working copy
+/// stays in place until every new file is downloaded and verified;
+/// `before_swap` then runs, to release the old files, and each file is
+/// replaced with a rename. Returns the update applied, or `None` when the
+/// model is up to date.
+pub async fn update_model(
+    variant: &ModelVariant,
+    custom_dir: Option<PathBuf>,
+    hub: &HubConfig,
+    downloads: &ActiveDownloads,
+    progress: impl FnMut(DownloadProgress) + Send + 'static,
+    before_swap: impl Future<Output = Result<()>>
+) -> std::result::Result<Option<ModelUpdate>, ModelError> {
+    let model = find_model(variant)?;
+    let model_path = get_model_path(variant.clone(), custom_dir.clone())?;
+    let source = HubSource::resolve(hub);
+    let guard = downloads.begin(variant, &model_path)?;
+
+    let installed = list_installed_models(custom_dir)?
+        .into_iter()
+        .find(|installed| installed.variant == *variant)
+        .map(|installed| installed.files.into_iter().map(|file| file.name).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let files = update_candidates(&model, installed);
+    let Some(update) = find_update(&model, &model_path, &files, &source, hub).await? else {
+        return Ok(None);
+    };
+
+    // Old and new files both exist until the swap
+    match disk_space::check(update.update_bytes, &model_path) {
+        Ok(space) if !space.sufficient => {
+            return Err(ModelError::InsufficientDisk {
+                required: space.required_bytes,
+                available: space.available_bytes,
+            });
+        }
+        Ok(_) => {}
+        Err(e) => log::warn!("Skipping disk space check: {:#}", e),
+    }
+
+    log::info!("Updating {} to {}@{:?}", variant, update.revision, update.remote_commit);
+    let staging = model_path.join(UPDATE_DIR);
+    std::fs::create_dir_all(&staging)?;
+    let mut tracker = ProgressTracker::new(
+        update.files
+            .iter()
+            .map(|file| (file.name.clone(), file.size_bytes))
+            .collect(),
+        progress
+    );
+
+    let hashes = match stage_update(&update, &model, &staging, &source, &mut tracker, guard.cancelled()).await {
+        Ok(hashes) => hashes,
+        Err(e) => {
+            // A cancelled update starts over; a failed one resumes next time
+            if let ModelError::Cancelled = e {
+                let _ = std::fs::remove_dir_all(&staging);
+            }
+            return Err(e);
+        }
+    };
+
+    before_swap.await?;
+
+    let revision = FileRevision {
+        hf_repo: model.hf_repo.clone(),
+        revision: update.revision.clone(),
+        commit: update.remote_commit.clone(),
+    };
+    for (file, sha256) in update.files.iter().zip(hashes) {
+        std::fs
+            ::rename(staging.join(&file.name), model_path.join(&file.name))
+            .with_context(|| format!("Failed to replace {}", file.name))?;
+        // Only a hash the hub published is an expected hash
+        match &file.sha256 {
+            Some(published) => model_verification::record_expected(&model_path, &file.name, published)?,
+            None => model_verification::forget_expected(&model_path, &file.name)?,
+        }
+        model_verification::record_hash(&model_path, &file.name, &sha256)?;
+        model_revision::record(&model_path, &file.name, &revision)?;
+    }
+    let _ = std::fs::remove_dir_all(&staging);
+    tracker.complete(Instant::now());
+
+    log::info!("Updated {}: {} files, {} bytes", variant, update.files.len(), update.update_bytes);
+    Ok(Some(update))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_manager::ModelConfig;
+    use crate::settings::QuantizationConfig;
+    use std::io::{ BufRead, BufReader, Write };
+    use std::net::TcpListener;
+    use tempfile::TempDir;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn test_is_outdated() {
+        let local = FileRevision {
+            hf_repo: "org/model".to_string(),
+            revision: "main".to_string(),
+            commit: Some("aaa".to_string()),
+        };
+        let same = HubFile { sha256: Some(HELLO_SHA256.to_uppercase()), commit: Some("bbb".to_string()), size: None };
+        // Another commit that didn't touch the file
+        assert!(!is_outdated(HELLO_SHA256, Some(&local), &same));
+        let changed = HubFile { sha256: Some("0".repeat(64)), ..same.clone() };
+        assert!(is_outdated(HELLO_SHA256, Some(&local), &changed));
+
+        // Without published hashes only commits are compared
+        let unhashed = HubFile { sha256: None, ..same };
+        assert!(is_outdated(HELLO_SHA256, Some(&local), &unhashed));
+        assert!(!is_outdated(HELLO_SHA256, None, &unhashed));
+    }
+
+    /// Serve `body` as every file, announcing `sha256` and commit `c2`
+    fn serve(body: &'static [u8], sha256: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                if request.starts_with("HEAD") {
+                    let head = format!(
+                        "HTTP/1.1 302 Found\r\nx-linked-etag: \"{}\"\r\nx-linked-size: {}\r\nx-repo-commit: c2\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        sha256,
+                        body.len()
+                    );
+                    stream.write_all(head.as_bytes()).unwrap();
+                } else {
+                    let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+                    stream.write_all(head.as_bytes()).unwrap();
+                    stream.write_all(body).unwrap();
+                }
+            }
+        });
+        endpoint
+    }
+
+    fn install(cache_dir: &Path) -> (PathBuf, String) {
+        let model_path = get_model_path(ModelVariant::Qwen3VL2B, Some(cache_dir.to_path_buf())).unwrap();
+        std::fs::create_dir_all(&model_path).unwrap();
+        let file = ModelConfig::from_variant(ModelVariant::Qwen3VL2B, QuantizationConfig::default()).unwrap().files.remove(0);
+        std::fs::write(model_path.join(&file), b"old").unwrap();
+        let revision = FileRevision {
+            hf_repo: "Qwen/Qwen3-VL-2B-Instruct-GGUF".to_string(),
+            revision: "main".to_string(),
+            commit: Some("c1".to_string()),
+        };
+        model_revision::record(&model_path, &file, &revision).unwrap();
+        (model_path, file)
+    }
+
+    fn hub(endpoint: String) -> HubConfig {
+        HubConfig { endpoint: Some(endpoint), token: Some("test".to_string()), ..HubConfig::default() }
+    }
+
+    #[tokio::test]
+    async fn test_check_and_apply_update() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_dir = Some(temp_dir.path().to_path_buf());
+        let (model_path, file) = install(temp_dir.path());
+        let hub = hub(serve(b"hello", HELLO_SHA256.to_string()));
+
+        let updates = check_model_updates(cache_dir.clone(), &hub).await.unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].local_commit.as_deref(), Some("c1"));
+        assert_eq!(updates[0].remote_commit.as_deref(), Some("c2"));
+        assert_eq!(updates[0].update_bytes, 5);
+        assert_eq!(updates[0].files[0].name, file);
+
+        let applied = update_model(&ModelVariant::Qwen3VL2B, cache_dir.clone(), &hub, &ActiveDownloads::default(), |_| {}, async { Ok(()) })
+            .await.unwrap();
+        assert_eq!(applied, Some(updates[0].clone()));
+        assert_eq!(std::fs::read(model_path.join(&file)).unwrap(), b"hello");
+        assert_eq!(model_revision::recorded(&model_path, &file).unwrap().commit.as_deref(), Some("c2"));
+        assert!(!model_path.join(UPDATE_DIR).exists());
+
+        assert!(check_model_updates(cache_dir, &hub).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_update_keeps_working_copy() {
+        let temp_dir = TempDir::new().unwrap();
+        let (model_path, file) = install(temp_dir.path());
+        // The hub announces a hash the served bytes don't have
+        let hub = hub(serve(b"corrupted", HELLO_SHA256.to_string()));
+
+        let result = update_model(
+            &ModelVariant::Qwen3VL2B,
+            Some(temp_dir.path().to_path_buf()),
+            &hub,
+            &ActiveDownloads::default(),
+            |_| {},
+            async { panic!("Nothing to swap in") }
+        ).await;
+        assert!(matches!(result, Err(ModelError::DownloadFailed(_))));
+        assert_eq!(std::fs::read(model_path.join(&file)).unwrap(), b"old");
+        assert_eq!(model_revision::recorded(&model_path, &file).unwrap().commit.as_deref(), Some("c1"));
+    }
+}
@@ -0,0 +1,285 @@
+use anyhow::{ Context, Result };
+use serde::{ Deserialize, Serialize };
+use sha2::{ Digest, Sha256 };
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Verification cache kept in each model directory
+const RECORD_FILE: &str = ".verification.json";
+
+/// Outcome of checking a model file against its published SHA-256
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum Verification {
+    Verified,
+    /// Neither the manifest nor the hub publishes a hash for the file
+    Unverified,
+    Mismatch {
+        expected: String,
+        actual: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+struct FileRecord {
+    /// Hash published on the hub, for files the manifest has none for
+    #[serde(default)]
+    expected_sha256: Option<String>,
+    #[serde(default)]
+    hashed: Option<HashedFile>,
+}
+
+/// Hash of a file as it was at a given size and modification time
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct HashedFile {
+    size: u64,
+    modified_ns: u64,
+    sha256: String,
+}
+
+fn load_records(dir: &Path) -> BTreeMap<String, FileRecord> {
+    std::fs
+        ::read_to_string(dir.join(RECORD_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_records(dir: &Path, records: &BTreeMap<String, FileRecord>) -> Result<()> {
+    let content = serde_json::to_string_pretty(records)?;
+    std::fs::write(dir.join(RECORD_FILE), content).context("Failed to save verification cache")
+}
+
+/// Size and modification time, which identify a file version for the cache
+fn file_stamp(path: &Path) -> Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let modified_ns = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0);
+    Ok((metadata.len(), modified_ns))
+}
+
+/// Lowercase hex SHA-256 of a file's contents
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1 << 20];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Result of a file with hash `actual` against the `expected` one
+pub fn compare(expected: Option<&str>, actual: &str) -> Verification {
+    match expected {
+        None => Verification::Unverified,
+        Some(expected) if expected.eq_ignore_ascii_case(actual) => Verification::Verified,
+        Some(expected) =>
+            Verification::Mismatch {
+                expected: expected.to_lowercase(),
+                actual: actual.to_string(),
+            },
+    }
+}
+
+/// Remember the hash the hub publishes for `file`
+pub fn record_expected(dir: &Path, file: &str, sha256: &str) -> Result<()> {
+    let mut records = load_records(dir);
+    records.entry(file.to_string()).or_default().expected_sha256 = Some(sha256.to_lowercase());
+    save_records(dir, &records)
+}
+
+/// Whether a published hash is known for `file`
+pub fn has_expected(dir: &Path, file: &str, manifest_sha256: Option<&str>) -> bool {
+    manifest_sha256.is_some() ||
+        load_records(dir)
+            .get(file)
+            .is_some_and(|record| record.expected_sha256.is_some())
+}
+
+/// Forget the published hash of `file`, which was replaced by a version the
+/// hub publishes none for
+pub fn forget_expected(dir: &Path, file: &str) -> Result<()> {
+    let mut records = load_records(dir);
+    if let Some(record) = records.get_mut(file) {
+        record.expected_sha256 = None;
+        save_records(dir, &records)?;
+    }
+    Ok(())
+}
+
+/// Drop the cached hash of `file` so the next check reads it again
+pub fn forget_hash(dir: &Path, file: &str) -> Result<()> {
+    let mut records = load_records(dir);
+    if let Some(record) = records.get_mut(file) {
+        record.hashed = None;
+        save_records(dir, &records)?;
+    }
+    Ok(())
+}
+
+/// SHA-256 of `file` in `dir`, read again only when its size or
+/// modification time changed since it was last hashed
+pub fn file_sha256(dir: &Path, file: &str) -> Result<String> {
+    let path = dir.join(file);
+    let (size, modified_ns) = file_stamp(&path)?;
+
+    let mut records = load_records(dir);
+    let record = records.entry(file.to_string()).or_default();
+    if let Some(hashed) = record.hashed.as_ref().filter(|hashed| hashed.size == size && hashed.modified_ns == modified_ns) {
+        return Ok(hashed.sha256.clone());
+    }
+
+    log::info!("Computing SHA-256 of {:?}", path);
+    let sha256 = sha256_file(&path)?;
+    record.hashed = Some(HashedFile { size, modified_ns, sha256: sha256.clone() });
+    save_records(dir, &records)?;
+    Ok(sha256)
+}
+
+/// Cache a hash computed before `file` was moved into `dir`, so checking it
+/// doesn't read the file again
+pub fn record_hash(dir: &Path, file: &str, sha256: &str) -> Result<()> {
+    let (size, modified_ns) = file_stamp(&dir.join(file))?;
+    let mut records = load_records(dir);
+    records.entry(file.to_string()).or_default().hashed = Some(HashedFile {
+        size,
+        modified_ns,
+        sha256: sha256.to_lowercase(),
+    });
+    save_records(dir, &records)
+}
+
+/// Check `file` in `dir` against the manifest hash, or the hub hash
+/// recorded at download time. The file is only hashed again when its size
+/// or modification time changed since the last check.
+pub fn verify_file(dir: &Path, file: &str, manifest_sha256: Option<&str>) -> Result<Verification> {
+    let sha256 = file_sha256(dir, file)?;
+    Ok(compare(expected_sha256(dir, file, manifest_sha256).as_deref(), &sha256))
+}
+
+/// The manifest hash of `file`, or else the hub hash recorded for it
+pub fn expected_sha256(dir: &Path, file: &str, manifest_sha256: Option<&str>) -> Option<String> {
+    manifest_sha256
+        .map(str::to_string)
+        .or_else(|| load_records(dir).remove(file).and_then(|record| record.expected_sha256))
+}
+
+/// The last result for `file` without hashing it; `None` when the file
+/// changed since it was last checked
+pub fn cached_verification(dir: &Path, file: &str, manifest_sha256: Option<&str>) -> Option<Verification> {
+    let (size, modified_ns) = file_stamp(&dir.join(file)).ok()?;
+    let records = load_records(dir);
+    let record = records.get(file)?;
+    let hashed = record.hashed.as_ref().filter(|hashed| hashed.size == size && hashed.modified_ns == modified_ns)?;
+    let expected = manifest_sha256.or(record.expected_sha256.as_deref());
+    Some(compare(expected, &hashed.sha256))
+}
+
+/// SHA-256 from an LFS ETag; plain git files carry a SHA-1 instead
+fn sha256_from_etag(etag: &str) -> Option<String> {
+    let etag = etag.trim_start_matches("W/").trim_matches('"');
+    (etag.len() == 64 && etag.chars().all(|c| c.is_ascii_hexdigit())).then(|| etag.to_lowercase())
+}
+
+/// What the hub publishes about a file, from the headers of its resolve URL
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HubFile {
+    pub sha256: Option<String>,
+    /// Commit the requested revision points at
+    pub commit: Option<String>,
+    pub size: Option<u64>,
+}
+
+/// Look up the file at `url` on the resolve endpoint, before it redirects
+/// to the storage backend
+pub async fn fetch_hub_file(url: &str, token: Option<&str>) -> Result<HubFile> {
+    let client = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).build()?;
+    let mut request = client.head(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.with_context(|| format!("Request to {} failed", url))?;
+    let status = response.status();
+    if !status.is_success() && !status.is_redirection() {
+        anyhow::bail!("{} returned {}", url, status);
+    }
+
+    let headers = response.headers();
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let etag = header("x-linked-etag").or_else(|| header("etag"));
+    // LFS files redirect, so the size is only in the linked header
+    let size = header("x-linked-size")
+        .or_else(|| header("content-length").filter(|_| status.is_success()))
+        .and_then(|size| size.parse().ok());
+    Ok(HubFile {
+        sha256: etag.and_then(sha256_from_etag),
+        commit: header("x-repo-commit").map(str::to_string),
+        size,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn test_verify_against_manifest_and_recorded_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("model.gguf"), "hello").unwrap();
+
+        assert_eq!(verify_file(temp_dir.path(), "model.gguf", Some(HELLO_SHA256)).unwrap(), Verification::Verified);
+        assert_eq!(verify_file(temp_dir.path(), "model.gguf", None).unwrap(), Verification::Unverified);
+
+        record_expected(temp_dir.path(), "model.gguf", &HELLO_SHA256.to_uppercase()).unwrap();
+        assert!(has_expected(temp_dir.path(), "model.gguf", None));
+        assert_eq!(verify_file(temp_dir.path(), "model.gguf", None).unwrap(), Verification::Verified);
+
+        // A truncated copy
+        std::fs::write(temp_dir.path().join("model.gguf"), "hel").unwrap();
+        assert!(matches!(verify_file(temp_dir.path(), "model.gguf", None).unwrap(), Verification::Mismatch { .. }));
+    }
+
+    #[test]
+    fn test_hash_is_cached_by_size_and_mtime() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("model.gguf");
+        std::fs::write(&path, "hello").unwrap();
+        assert_eq!(verify_file(temp_dir.path(), "model.gguf", Some(HELLO_SHA256)).unwrap(), Verification::Verified);
+
+        // Same size and mtime: the cached hash is trusted, so a stale entry shows through
+        let mut records = load_records(temp_dir.path());
+        records.get_mut("model.gguf").unwrap().hashed.as_mut().unwrap().sha256 = "0".repeat(64);
+        save_records(temp_dir.path(), &records).unwrap();
+        assert!(matches!(cached_verification(temp_dir.path(), "model.gguf", Some(HELLO_SHA256)), Some(Verification::Mismatch { .. })));
+        assert!(matches!(verify_file(temp_dir.path(), "model.gguf", Some(HELLO_SHA256)).unwrap(), Verification::Mismatch { .. }));
+
+        forget_hash(temp_dir.path(), "model.gguf").unwrap();
+        assert_eq!(cached_verification(temp_dir.path(), "model.gguf", Some(HELLO_SHA256)), None);
+        assert_eq!(verify_file(temp_dir.path(), "model.gguf", Some(HELLO_SHA256)).unwrap(), Verification::Verified);
+
+        // A different size invalidates the entry
+        std::fs::write(&path, "hello world").unwrap();
+        assert_eq!(cached_verification(temp_dir.path(), "model.gguf", Some(HELLO_SHA256)), None);
+    }
+
+    #[test]
+    fn test_sha256_from_etag() {
+        assert_eq!(sha256_from_etag(&format!("\"{}\"", HELLO_SHA256)).as_deref(), Some(HELLO_SHA256));
+        assert_eq!(sha256_from_etag(&format!("W/\"{}\"", HELLO_SHA256.to_uppercase())).as_deref(), Some(HELLO_SHA256));
+        // Git blob SHA-1 of a small, non-LFS file
+        assert_eq!(sha256_from_etag("\"b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0\""), None);
+    }
+}
@@ -1,17 +1,49 @@
-use crate::candle_inference::{Qwen2VLInference, build_qwen_prompt};
-use crate::model_manager::{check_model_status, get_model_path, ModelStatus};
-use crate::settings::AppSettings;
+use crate::candle_inference::{
+    aggregation_instruction, apply_chat_template, build_prompt_from_template, build_qwen_chat,
+    build_qwen_prompt, random_seed, Cancelled, InferenceProgress, CAPTION_INSTRUCTION,
+};
+use crate::captioning::{caption_images, CaptionCache, ImageCaption};
+use crate::gguf;
+use crate::hardware::thread_count;
+use crate::memory_estimate::{estimate_from_files, RuntimeShape, BYTES_PER_GB};
+use crate::model_manager::{
+    builtin_model_files, check_model_status, check_variant_status, resolve_model_files,
+    ModelConfig, ModelFiles, ModelStatus,
+};
+use crate::image_preprocess::{preprocess_for_model, PreprocessConfig};
+use crate::inference_worker::{InferenceWorker, WorkerError};
+use crate::installed_models;
+use crate::model_pool::ModelPool;
+use crate::model_registry::{find_model, manifest};
+use crate::settings::{
+    AppSettings, ModelVariant, OfflineStrategy, Quantization, QuantizationConfig, RuntimeConfig,
+    SamplingConfig,
+};
+use crate::spec_grammar::{dataset_spec_grammar, Grammar};
 use anyhow::Result;
 use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::Emitter;
 use thiserror::Error;
 
-#[derive(Debug, Error)]
+/// Context tokens kept for the instructions and chat template around the images
+const PROMPT_RESERVE_TOKENS: u32 = 1024;
+
+/// Fewest visual tokens images are shrunk to before settings are rejected
+const MIN_VISUAL_TOKENS: u32 = 256;
+
+#[derive(Debug, Error, Serialize, Deserialize)]
 pub enum OfflineAnalysisError {
     #[error("Model not found. Please download the model first.")]
     ModelNotFound,
 
-    #[error("Insufficient memory. Requires {required}GB, available {available}GB")]
-    InsufficientMemory { required: f32, available: f32 },
+    #[error("Model file {0} failed its checksum. Repair the model from the Settings page.")]
+    ModelCorrupt(String),
+
+    #[error("Insufficient memory. Requires {required:.1}GB, available {available:.1}GB. {suggestion}")]
+    InsufficientMemory { required: f32, available: f32, suggestion: String },
 
     #[error("Model loading failed: {0}")]
     ModelLoadError(String),
@@ -21,88 +53,474 @@ pub enum OfflineAnalysisError {
 
     #[error("Image processing failed: {0}")]
     ImageProcessingError(String),
+
+    #[error("Invalid offline settings: {0}")]
+    InvalidSettings(String),
+
+    #[error("Analysis cancelled")]
+    Cancelled,
+}
+
+impl From<WorkerError> for OfflineAnalysisError {
+    fn from(error: WorkerError) -> Self {
+        match error {
+            // Errors raised by the analysis itself come back intact
+            WorkerError::Remote { data: Some(data), message } => serde_json::from_value(data)
+                .unwrap_or(OfflineAnalysisError::InferenceFailed(message)),
+            other => OfflineAnalysisError::InferenceFailed(other.to_string()),
+        }
+    }
+}
+
+/// How an offline spec was produced, recorded so the run can be repeated
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InferenceMetadata {
+    pub model_variant: ModelVariant,
+    /// Sampling parameters with the seed that was actually used
+    pub sampling: SamplingConfig,
+    /// Strategy the spec was produced with (never `Auto`)
+    #[serde(default)]
+    pub strategy: OfflineStrategy,
+    /// Per-image style descriptions the spec was written from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub image_captions: Vec<ImageCaption>,
+}
+
+/// Result of an offline analysis
+#[derive(Debug)]
+pub struct OfflineAnalysis {
+    pub data: String,
+    pub metadata: InferenceMetadata,
+}
+
+/// Cancellation flag for the running analysis, shared through Tauri managed state
+#[derive(Clone, Default)]
+pub struct AnalysisCancellation(Arc<AtomicBool>);
+
+impl AnalysisCancellation {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
 }
 
-fn get_available_memory_gb() -> f32 {
+pub(crate) fn get_available_memory_gb() -> f32 {
     use sysinfo::System;
     let mut sys = System::new_all();
     sys.refresh_memory();
-    sys.available_memory() as f32 / 1_073_741_824.0 // bytes to GB
+    (sys.available_memory() as f64 / BYTES_PER_GB) as f32
 }
 
-pub fn check_system_requirements(settings: &AppSettings) -> Result<(), OfflineAnalysisError> {
-    use crate::settings::ModelVariant;
+/// Visual tokens the images of one prompt may take so that the instructions
+/// and `max_new_tokens` still fit in the context window
+pub fn visual_token_budget(runtime: &RuntimeConfig, sampling: &SamplingConfig) -> Result<u32, OfflineAnalysisError> {
+    let reserved = (sampling.max_new_tokens as u64) + (PROMPT_RESERVE_TOKENS as u64);
+    let available = (runtime.n_ctx as u64).saturating_sub(reserved);
+    if available < MIN_VISUAL_TOKENS as u64 {
+        return Err(OfflineAnalysisError::InvalidSettings(format!(
+            "max_new_tokens {} leaves too little of the {} token context for images; lower it to at most {} or raise n_ctx",
+            sampling.max_new_tokens,
+            runtime.n_ctx,
+            (runtime.n_ctx as u64).saturating_sub((PROMPT_RESERVE_TOKENS + MIN_VISUAL_TOKENS) as u64)
+        )));
+    }
+    Ok((available as u32).min(PreprocessConfig::default().max_visual_tokens))
+}
 
-    let required_gb = match settings.offline_model_variant {
-        ModelVariant::Qwen3VL2B => 3.0,   // ~1.9GB model + overhead
-        ModelVariant::Qwen3VL4B => 5.0,   // ~3.3GB model + overhead
-        ModelVariant::Qwen3VL8B => 10.0,  // ~6.1GB model + overhead
-    };
+/// Context, batch and thread settings the inference engine runs with
+fn runtime_shape(settings: &AppSettings) -> RuntimeShape {
+    RuntimeShape {
+        n_ctx: settings.runtime.n_ctx,
+        n_batch: settings.runtime.n_batch,
+        n_threads: thread_count(&settings.runtime),
+        max_visual_tokens: visual_token_budget(&settings.runtime, &settings.sampling).unwrap_or(MIN_VISUAL_TOKENS),
+    }
+}
+
+/// Memory a variant needs, in GB: computed from the GGUF headers when the
+/// files are installed, otherwise estimated from the download size
+pub fn estimate_variant_memory_gb(
+    variant: &ModelVariant,
+    quantization: QuantizationConfig,
+    settings: &AppSettings,
+) -> Result<f32, OfflineAnalysisError> {
+    if let ModelVariant::Custom(name) = variant {
+        let model = settings.custom_model(name).ok_or(OfflineAnalysisError::ModelNotFound)?;
+        return Ok(estimate_from_files(&model.model_path, &model.mmproj_path, runtime_shape(settings))
+            .map(|estimate| estimate.total_gb())
+            .unwrap_or(model.memory_estimate_gb));
+    }
+
+    let installed = matches!(
+        check_model_status(variant.clone(), settings.model_cache_dir.clone(), quantization),
+        ModelStatus::Ready
+    );
+    if installed {
+        let files = builtin_model_files(variant, settings.model_cache_dir.clone(), quantization)
+            .map_err(|e| OfflineAnalysisError::ModelLoadError(e.to_string()))?;
+        match estimate_from_files(&files.model, &files.mmproj, runtime_shape(settings)) {
+            Ok(estimate) => return Ok(estimate.total_gb()),
+            Err(e) => log::warn!("Falling back to size-based memory estimate: {:#}", e),
+        }
+    }
+
+    ModelConfig::from_variant(variant.clone(), quantization)
+        .map(|config| config.required_memory_gb())
+        .map_err(|e| OfflineAnalysisError::ModelLoadError(e.to_string()))
+}
+
+/// Largest manifest model and quantization that fits in `available_gb`,
+/// with its estimated memory. F16 is skipped; Q8_0 is near-lossless.
+pub fn fitting_model(
+    settings: &AppSettings,
+    available_gb: f32,
+) -> Option<(ModelVariant, QuantizationConfig, f32)> {
+    let mut models = manifest().models;
+    models.sort_by_key(|model| std::cmp::Reverse(model.largest_file_bytes()));
+    let quantizations = [Quantization::Q8_0, Quantization::Q4KM];
+
+    for entry in &models {
+        let variant = entry.variant();
+        for model in quantizations {
+            let quantization = QuantizationConfig { model, mmproj: Quantization::Q8_0 };
+            if entry.files(quantization).is_err() {
+                continue;
+            }
+            match estimate_variant_memory_gb(&variant, quantization, settings) {
+                Ok(required) if required <= available_gb => {
+                    return Some((variant, quantization, required));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    None
+}
+
+fn suggest_fitting_model(settings: &AppSettings, available_gb: f32) -> String {
+    match fitting_model(settings, available_gb) {
+        Some((variant, quantization, required)) => format!(
+            "Try {} with {} quantization (needs about {:.1}GB).",
+            variant,
+            quantization.model.file_suffix(),
+            required
+        ),
+        None => "No offline model fits in available memory; use cloud analysis instead.".to_string(),
+    }
+}
+
+pub fn check_system_requirements(settings: &AppSettings) -> Result<(), OfflineAnalysisError> {
+    let variant = &settings.offline_model_variant;
+    let required_gb = estimate_variant_memory_gb(variant, settings.quantization_for(variant), settings)?;
 
     let available = get_available_memory_gb();
     if available < required_gb {
         return Err(OfflineAnalysisError::InsufficientMemory {
             required: required_gb,
             available,
+            suggestion: suggest_fitting_model(settings, available),
         });
     }
 
     Ok(())
 }
 
-fn load_images(image_paths: &[String]) -> Result<Vec<DynamicImage>, OfflineAnalysisError> {
-    let mut images = Vec::new();
+/// Chat format of a custom or manifest model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ChatFormat {
+    /// Template with `{images}` and `{prompt}` placeholders; Qwen's when `None`
+    template: Option<String>,
+}
+
+impl ChatFormat {
+    pub(crate) fn for_variant(
+        variant: &ModelVariant,
+        settings: &AppSettings,
+    ) -> Result<Self, OfflineAnalysisError> {
+        let template = match variant {
+            ModelVariant::Custom(name) => {
+                let model = settings.custom_model(name).ok_or(OfflineAnalysisError::ModelNotFound)?;
+                Some(model.chat_template.clone())
+            }
+            _ => find_model(variant).map_err(|_| OfflineAnalysisError::ModelNotFound)?.chat_template,
+        };
+        Ok(Self { template })
+    }
+
+    /// Spec request over all reference images in one prompt
+    pub(crate) fn analysis_prompt(&self, sref_code: &str, num_images: usize) -> String {
+        match &self.template {
+            Some(template) => build_prompt_from_template(template, sref_code, num_images),
+            None => build_qwen_prompt(sref_code, num_images),
+        }
+    }
+
+    fn prompt(&self, instruction: &str, num_images: usize) -> String {
+        match &self.template {
+            Some(template) => apply_chat_template(template, instruction, num_images),
+            None => build_qwen_chat(instruction, num_images),
+        }
+    }
+}
+
+/// Strategy to run with, resolving `Auto` from the model manifest
+fn resolve_strategy(settings: &AppSettings, variant: &ModelVariant) -> OfflineStrategy {
+    match settings.offline_strategy {
+        OfflineStrategy::Auto => {
+            let caption_first = find_model(variant).map(|model| model.caption_first).unwrap_or(false);
+            if caption_first {
+                OfflineStrategy::CaptionThenAggregate
+            } else {
+                OfflineStrategy::SinglePrompt
+            }
+        }
+        strategy => strategy,
+    }
+}
+
+fn load_images(image_paths: &[String], config: &PreprocessConfig) -> Result<Vec<DynamicImage>, OfflineAnalysisError> {
+    let images = preprocess_for_model(image_paths, config)
+        .map_err(|e| OfflineAnalysisError::ImageProcessingError(e.to_string()))?;
+
+    Ok(images.into_iter().map(DynamicImage::ImageRgb8).collect())
+}
+
+/// One analysis as sent to the inference worker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AnalysisJob {
+    pub variant: ModelVariant,
+    pub files: ModelFiles,
+    pub image_paths: Vec<String>,
+    pub sref_code: String,
+    pub chat: ChatFormat,
+    /// Resolved strategy (never `Auto`)
+    pub strategy: OfflineStrategy,
+    /// Sampling parameters with the seed already fixed
+    pub sampling: SamplingConfig,
+    pub runtime: RuntimeConfig,
+    pub keep_loaded: bool,
+}
+
+/// Unvalidated model output of an analysis job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JobOutput {
+    pub text: String,
+    pub image_captions: Vec<ImageCaption>,
+}
+
+/// Run an analysis job on a model from `pool`. This is where llama.cpp runs,
+/// so it is only called inside the inference worker process.
+pub(crate) fn run_job(
+    job: AnalysisJob,
+    pool: &ModelPool,
+    report: &mut dyn FnMut(InferenceProgress) -> bool,
+) -> Result<JobOutput, OfflineAnalysisError> {
+    let AnalysisJob {
+        variant,
+        files,
+        image_paths,
+        sref_code,
+        chat,
+        strategy,
+        sampling,
+        runtime,
+        keep_loaded,
+    } = job;
+
+    // Images are cut to the patch grid of this model's vision encoder
+    let projector = gguf::read_header(&files.mmproj)
+        .map_err(|e| OfflineAnalysisError::ModelLoadError(format!("{:#}", e)))?;
+    let preprocess = PreprocessConfig {
+        max_visual_tokens: visual_token_budget(&runtime, &sampling)?,
+        ..PreprocessConfig::for_projector(&projector)
+    };
+
+    // Captioned images are shown alone, so each gets the whole visual token budget
+    let images = match strategy {
+        OfflineStrategy::CaptionThenAggregate => image_paths
+            .iter()
+            .map(|path| load_images(std::slice::from_ref(path), &preprocess).map(|mut images| images.remove(0)))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => load_images(&image_paths, &preprocess)?,
+    };
+    let grammar = dataset_spec_grammar(&sref_code);
+
+    if !pool.is_loaded(&variant) && !report(InferenceProgress::LoadingModel) {
+        return Err(OfflineAnalysisError::Cancelled);
+    }
+
+    let inference = pool
+        .get_or_load(&variant, &files, &runtime)
+        .map_err(|e| OfflineAnalysisError::ModelLoadError(e.to_string()))?;
+
+    let mut model = inference
+        .lock()
+        .map_err(|_| OfflineAnalysisError::InferenceFailed("Model lock poisoned".to_string()))?;
+
+    let result = match strategy {
+        OfflineStrategy::CaptionThenAggregate => {
+            let cache = CaptionCache::open(&files.model)
+                .map_err(|e| log::warn!("Captions will not be cached: {:#}", e))
+                .ok();
+            let images = image_paths.into_iter().zip(images).collect();
+            let caption_prompt = chat.prompt(CAPTION_INSTRUCTION, 1);
+
+            caption_images(
+                &mut model,
+                images,
+                &caption_prompt,
+                &sampling,
+                &runtime,
+                cache.as_ref(),
+                &mut *report,
+            )
+            .and_then(|captions| {
+                // The spec is written from the descriptions alone
+                let texts: Vec<String> = captions.iter().map(|c| c.caption.clone()).collect();
+                let prompt = chat.prompt(&aggregation_instruction(&sref_code, &texts), 0);
+                model
+                    .analyze_images(Vec::new(), &prompt, Some(&grammar), &sampling, &runtime, &mut *report)
+                    .map(|output| JobOutput {
+                        text: output.text,
+                        image_captions: captions,
+                    })
+            })
+        }
+        _ => {
+            let prompt = chat.analysis_prompt(&sref_code, images.len());
+            model
+                .analyze_images(images, &prompt, Some(&grammar), &sampling, &runtime, &mut *report)
+                .map(|output| JobOutput {
+                    text: output.text,
+                    image_captions: Vec::new(),
+                })
+        }
+    }
+    .map_err(|e| {
+        if e.is::<Cancelled>() {
+            OfflineAnalysisError::Cancelled
+        } else {
+            OfflineAnalysisError::InferenceFailed(e.to_string())
+        }
+    });
+
+    drop(model);
 
-    for path in image_paths {
-        let img = image::open(path)
-            .map_err(|e| OfflineAnalysisError::ImageProcessingError(e.to_string()))?;
-        images.push(img);
+    if !keep_loaded {
+        pool.unload(&variant, "keep_model_loaded disabled");
     }
 
-    Ok(images)
+    result
 }
 
 pub async fn analyze_style(
     image_paths: Vec<String>,
     sref_code: &str,
     settings: &AppSettings,
-) -> Result<String, OfflineAnalysisError> {
-    // 1. Check system requirements
-    check_system_requirements(settings)?;
-
-    // 2. Verify model is available
-    let model_status = check_model_status(
-        settings.offline_model_variant.clone(),
-        settings.model_cache_dir.clone(),
-    );
+    worker: &InferenceWorker,
+    app: Option<tauri::AppHandle>,
+    cancellation: &AnalysisCancellation,
+) -> Result<OfflineAnalysis, OfflineAnalysisError> {
+    let variant = settings.offline_model_variant.clone();
+    visual_token_budget(&settings.runtime, &settings.sampling)?;
 
-    if !matches!(model_status, ModelStatus::Ready) {
-        return Err(OfflineAnalysisError::ModelNotFound);
+    // 1. Check system requirements (a loaded model already has its memory,
+    //    unless it was loaded from other files and has to be reloaded)
+    let loaded = resolve_model_files(&variant, settings)
+        .is_ok_and(|files| worker.is_loaded_from(&variant, &files, &settings.runtime));
+    if !loaded {
+        check_system_requirements(settings)?;
     }
 
-    // 3. Get model path
-    let model_path = get_model_path(
-        settings.offline_model_variant.clone(),
-        settings.model_cache_dir.clone(),
-    )
-    .map_err(|e| OfflineAnalysisError::ModelLoadError(e.to_string()))?;
-
-    // 4. Load images
-    let images = load_images(&image_paths)?;
+    // 2. Verify model is available (hashing new or changed files can take a
+    //    while, so off the async runtime)
+    let model_status = {
+        let (variant, settings) = (variant.clone(), settings.clone());
+        tokio::task::spawn_blocking(move || check_variant_status(&variant, &settings))
+            .await
+            .map_err(|e| OfflineAnalysisError::ModelLoadError(format!("Status check failed: {}", e)))?
+    };
 
-    // 5. Build prompt
-    let prompt = build_qwen_prompt(sref_code, images.len());
+    match model_status {
+        ModelStatus::Ready => {}
+        ModelStatus::Corrupt { file } => {
+            return Err(OfflineAnalysisError::ModelCorrupt(file));
+        }
+        _ => {
+            return Err(OfflineAnalysisError::ModelNotFound);
+        }
+    }
 
-    // 6. Load model and run inference
-    let mut inference = Qwen2VLInference::new(&model_path, settings.offline_model_variant.clone())
-        .await
+    // 3. Resolve model files
+    let model_files = resolve_model_files(&variant, settings)
         .map_err(|e| OfflineAnalysisError::ModelLoadError(e.to_string()))?;
+    installed_models::mark_used(&variant, settings.model_cache_dir.clone());
+
+    // 4. Resolve the strategy, chat format and the grammar that keeps output
+    //    schema-valid
+    let strategy = resolve_strategy(settings, &variant);
+    let chat = ChatFormat::for_variant(&variant, settings)?;
+    let validator = Grammar::parse(&dataset_spec_grammar(sref_code))
+        .map_err(|e| OfflineAnalysisError::InferenceFailed(format!("Invalid output grammar: {}", e)))?;
+
+    // 5. Fix the seed up front so it can be recorded with the result
+    let sampling = SamplingConfig {
+        seed: Some(settings.sampling.seed.unwrap_or_else(random_seed)),
+        ..settings.sampling.clone()
+    };
+    let mut metadata = InferenceMetadata {
+        model_variant: variant.clone(),
+        sampling: sampling.clone(),
+        strategy,
+        image_captions: Vec::new(),
+    };
+
+    // 6. Run the job in the inference worker, so a llama.cpp abort can't take
+    //    the app down with it
+    let job = AnalysisJob {
+        variant,
+        files: model_files,
+        image_paths,
+        sref_code: sref_code.to_string(),
+        chat,
+        strategy,
+        sampling,
+        runtime: settings.runtime.clone(),
+        keep_loaded: settings.keep_model_loaded,
+    };
+    let worker = worker.clone();
+    let cancellation = cancellation.clone();
+    let output = tokio::task::spawn_blocking(move || {
+        worker.analyze(job, &mut |progress: InferenceProgress| {
+            if let Some(app) = &app {
+                let _ = app.emit("analysis-progress", &progress);
+            }
+            !cancellation.is_cancelled()
+        })
+    })
+    .await
+    .map_err(|e| OfflineAnalysisError::InferenceFailed(format!("Inference task failed: {}", e)))??;
 
-    let response = inference
-        .analyze_images(images, &prompt)
-        .map_err(|e| OfflineAnalysisError::InferenceFailed(e.to_string()))?;
+    // Constrained output can still be cut short by the token limit
+    if !validator.accepts(&output.text) {
+        return Err(OfflineAnalysisError::InferenceFailed(
+            "Model output is an incomplete specification (token limit reached)".to_string(),
+        ));
+    }
 
-    Ok(response)
+    metadata.image_captions = output.image_captions;
+    Ok(OfflineAnalysis {
+        data: output.text,
+        metadata,
+    })
 }
 
 #[cfg(test)]
@@ -117,4 +535,81 @@ mod tests {
         // Can't assert pass/fail as depends on system
         println!("System check result: {:?}", result);
     }
+
+    #[test]
+    fn test_suggestion_fits_available_memory() {
+        // Empty cache, so estimates come from download sizes
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let settings = AppSettings {
+            model_cache_dir: Some(temp_dir.path().to_path_buf()),
+            ..AppSettings::default()
+        };
+
+        let roomy = suggest_fitting_model(&settings, 64.0);
+        assert!(roomy.starts_with("Try Qwen3VL8B with Q8_0"), "{}", roomy);
+
+        let tight = suggest_fitting_model(&settings, 4.0);
+        assert!(tight.starts_with("Try Qwen3VL2B"), "{}", tight);
+
+        let none = suggest_fitting_model(&settings, 0.5);
+        assert!(none.starts_with("No offline model fits"));
+    }
+
+    #[test]
+    fn test_visual_token_budget_fits_context() {
+        let sampling = SamplingConfig::default();
+        // 8192 - 4096 generated - 1024 prompt
+        assert_eq!(visual_token_budget(&RuntimeConfig::default(), &sampling).unwrap(), 3072);
+
+        let large = RuntimeConfig { n_ctx: 32768, ..RuntimeConfig::default() };
+        assert_eq!(visual_token_budget(&large, &sampling).unwrap(), PreprocessConfig::default().max_visual_tokens);
+
+        let small = RuntimeConfig { n_ctx: 4096, ..RuntimeConfig::default() };
+        let error = visual_token_budget(&small, &sampling).unwrap_err();
+        assert!(error.to_string().contains("at most 2816"), "{}", error);
+        let shorter = SamplingConfig { max_new_tokens: 2048, ..sampling };
+        assert_eq!(visual_token_budget(&small, &shorter).unwrap(), 1024);
+    }
+
+    #[test]
+    fn test_unregistered_custom_model() {
+        let settings = AppSettings {
+            offline_model_variant: ModelVariant::Custom("missing".to_string()),
+            ..AppSettings::default()
+        };
+        assert!(matches!(
+            check_system_requirements(&settings),
+            Err(OfflineAnalysisError::ModelNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_auto_strategy_follows_manifest() {
+        let settings = AppSettings::default();
+        assert_eq!(
+            resolve_strategy(&settings, &ModelVariant::Qwen3VL2B),
+            OfflineStrategy::CaptionThenAggregate
+        );
+        assert_eq!(resolve_strategy(&settings, &ModelVariant::Qwen3VL8B), OfflineStrategy::SinglePrompt);
+        assert_eq!(
+            resolve_strategy(&settings, &ModelVariant::Custom("local".to_string())),
+            OfflineStrategy::SinglePrompt
+        );
+
+        let single = AppSettings { offline_strategy: OfflineStrategy::SinglePrompt, ..settings };
+        assert_eq!(resolve_strategy(&single, &ModelVariant::Qwen3VL2B), OfflineStrategy::SinglePrompt);
+    }
+
+    #[test]
+    fn test_cancellation_flag() {
+        let cancellation = AnalysisCancellation::default();
+        let shared = cancellation.clone();
+        assert!(!cancellation.is_cancelled());
+
+        shared.cancel();
+        assert!(cancellation.is_cancelled());
+
+        cancellation.reset();
+        assert!(!shared.is_cancelled());
+    }
 }
@@ -0,0 +1,397 @@
+use crate::download_progress::ProgressTracker;
+use anyhow::{ Context, Result };
+use reqwest::header::{ CONTENT_RANGE, ETAG, IF_RANGE, RANGE };
+use reqwest::{ Client, Response, StatusCode };
+use serde::{ Deserialize, Serialize };
+use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::{ Duration, Instant };
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+
+/// How often a stalled transfer looks at its cancellation flag
+const CANCEL_POLL: Duration = Duration::from_millis(200);
+
+/// Returned by `download_file` once its cancellation flag is set; the
+/// partial file is left for the caller to keep or discard
+#[derive(Debug, Error)]
+#[error("Download cancelled")]
+pub struct Cancelled;
+
+/// What a `.part` file is being downloaded from, so a later attempt only
+/// resumes it against the same remote file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct PartInfo {
+    url: String,
+    size: Option<u64>,
+    etag: Option<String>,
+}
+
+/// Partial download of `target`
+pub fn part_path(target: &Path) -> PathBuf {
+    let mut path = target.as_os_str().to_owned();
+    path.push(".part");
+    PathBuf::from(path)
+}
+
+/// Sidecar describing the partial download of `target`
+fn part_info_path(target: &Path) -> PathBuf {
+    let mut path = target.as_os_str().to_owned();
+    path.push(".part.json");
+    PathBuf::from(path)
+}
+
+fn read_part_info(target: &Path) -> Option<PartInfo> {
+    let content = std::fs::read_to_string(part_info_path(target)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Drop a partial download so the next attempt starts from zero
+pub fn discard_part(target: &Path) {
+    let _ = std::fs::remove_file(part_path(target));
+    let _ = std::fs::remove_file(part_info_path(target));
+}
+
+/// URL of a file in a Hugging Face model repository at `revision`
+pub fn hub_file_url(endpoint: &str, repo: &str, revision: &str, file: &str) -> String {
+    format!("{}/{}/resolve/{}/{}", endpoint.trim_end_matches('/'), repo, revision.replace('/', "%2F"), file)
+}
+
+/// Start of the range and total size from a `bytes start-end/total` header
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split_once('-')?.0.parse().ok()?;
+    Some((start, total.parse().ok()))
+}
+
+async fn send(client: &Client, url: &str, token: Option<&str>, offset: u64, etag: Option<&str>) -> Result<Response> {
+    let mut request = client.get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+        // The server sends the whole file instead if it changed since
+        if let Some(etag) = etag {
+            request = request.header(IF_RANGE, etag);
+        }
+    }
+    request.send().await.with_context(|| format!("Request to {} failed", url))
+}
+
+async fn wait_for_cancel(cancelled: &AtomicBool) {
+    while !cancelled.load(Ordering::SeqCst) {
+        tokio::time::sleep(CANCEL_POLL).await;
+    }
+}
+
+/// Download `url` to `target` through a `.part` file, continuing from where
+/// an earlier attempt for the same URL stopped. On failure the partial file
+/// and its sidecar are kept for the next attempt; `target` only appears once
+/// the file is complete. Setting `cancelled` stops the transfer with
+/// `Cancelled`, even while it waits on a stalled connection.
+pub async fn download_file(
+    client: &Client,
+    url: &str,
+    token: Option<&str>,
+    target: &Path,
+    tracker: &mut ProgressTracker,
+    index: usize,
+    cancelled: &AtomicBool
+) -> Result<()> {
+    if cancelled.load(Ordering::SeqCst) {
+        return Err(Cancelled.into());
+    }
+    let part = part_path(target);
+
+    let info = read_part_info(target).filter(|info| info.url == url);
+    let mut offset = match &info {
+        Some(_) => std::fs::metadata(&part).map(|meta| meta.len()).unwrap_or(0),
+        None => 0,
+    };
+    let etag = info.as_ref().and_then(|info| info.etag.clone());
+
+    let mut response = send(client, url, token, offset, etag.as_deref()).await?;
+
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        if offset > 0 && info.as_ref().and_then(|info| info.size) == Some(offset) {
+            // The previous attempt got every byte but stopped before renaming
+            tracker.start_file(index, Some(offset), offset, Instant::now());
+            std::fs::rename(&part, target).with_context(|| format!("Failed to move {:?} into place", part))?;
+            discard_part(target);
+            tracker.finish_file(Instant::now());
+            return Ok(());
+        }
+        log::warn!("Server refused to resume {} at byte {}, starting over", url, offset);
+        discard_part(target);
+        offset = 0;
+        response = send(client, url, token, 0, None).await?;
+    }
+
+    let mut response = response
+        .error_for_status()
+        .with_context(|| format!("Download of {} failed", url))?;
+
+    let size = if response.status() == StatusCode::PARTIAL_CONTENT {
+        let (start, total) = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_content_range)
+            .context("Resumed response has no valid Content-Range")?;
+        if start != offset {
+            discard_part(target);
+            anyhow::bail!("Server resumed {} at byte {} instead of {}", url, start, offset);
+        }
+        total
+    } else {
+        // A full response, either a fresh download or the remote file changed
+        if offset > 0 {
+            log::info!("Server sent all of {} again, discarding {} partial bytes", url, offset);
+        }
+        offset = 0;
+        response.content_length()
+    };
+
+    let new_info = PartInfo {
+        url: url.to_string(),
+        size,
+        etag: response
+            .headers()
+            .get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    };
+    std::fs::write(part_info_path(target), serde_json::to_string(&new_info)?).context(
+        "Failed to write download sidecar"
+    )?;
+
+    let mut file = tokio::fs::OpenOptions
+        ::new()
+        .create(true)
+        .write(true)
+        .append(offset > 0)
+        .truncate(offset == 0)
+        .open(&part).await
+        .with_context(|| format!("Failed to open {:?}", part))?;
+
+    tracker.start_file(index, size, offset, Instant::now());
+
+    let mut received = offset;
+    let body: Result<()> = async {
+        loop {
+            let chunk = tokio::select! {
+                biased;
+                _ = wait_for_cancel(cancelled) => {
+                    return Err(Cancelled.into());
+                }
+                chunk = response.chunk() => chunk?,
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            file.write_all(&chunk).await?;
+            received += chunk.len() as u64;
+            tracker.advance(chunk.len() as u64, Instant::now());
+        }
+        Ok(())
+    }.await;
+    // Keep whatever arrived before a dropped connection for the next attempt
+    file.flush().await?;
+    drop(file);
+    body.with_context(|| format!("Download of {} interrupted after {} bytes", url, received))?;
+
+    if let Some(size) = size {
+        if received != size {
+            anyhow::bail!("Download of {} ended after {} of {} bytes", url, received, size);
+        }
+    }
+
+    std::fs::rename(&part, target).with_context(|| format!("Failed to move {:?} into place", part))?;
+    discard_part(target);
+    tracker.finish_file(Instant::now());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{ BufRead, BufReader, Write };
+    use std::net::TcpListener;
+    use std::sync::{ Arc, Mutex };
+    use tempfile::TempDir;
+
+    struct Served {
+        url: String,
+        /// Range header of each request
+        ranges: Arc<Mutex<Vec<Option<String>>>>,
+    }
+
+    /// Serve `body` with ETag `etag`, honouring Range and If-Range; the nth
+    /// connection closes after `drop_after[n]` body bytes when set
+    fn serve(body: Vec<u8>, etag: &'static str, drop_after: Vec<Option<usize>>) -> Served {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/model.gguf", listener.local_addr().unwrap());
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let seen = ranges.clone();
+
+        std::thread::spawn(move || {
+            for limit in drop_after {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut range = None;
+                let mut if_range = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(": ") {
+                        match name.to_ascii_lowercase().as_str() {
+                            "range" => {
+                                range = Some(value.to_string());
+                            }
+                            "if-range" => {
+                                if_range = Some(value.to_string());
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                seen.lock().unwrap().push(range.clone());
+
+                let start = range
+                    .filter(|_| if_range.as_deref().is_none_or(|tag| tag == etag))
+                    .and_then(|range| range.strip_prefix("bytes=")?.trim_end_matches('-').parse::<usize>().ok());
+                let head = match start {
+                    Some(start) =>
+                        format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n",
+                            start,
+                            body.len() - 1,
+                            body.len(),
+                            body.len() - start
+                        ),
+                    None => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n", body.len()),
+                };
+                let head = format!("{}ETag: {}\r\nConnection: close\r\n\r\n", head, etag);
+                let rest = &body[start.unwrap_or(0)..];
+                let sent = limit.unwrap_or(rest.len()).min(rest.len());
+
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(&rest[..sent]).unwrap();
+                stream.flush().unwrap();
+            }
+        });
+
+        Served { url, ranges }
+    }
+
+    fn body() -> Vec<u8> {
+        (0..10_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn tracker() -> ProgressTracker {
+        ProgressTracker::new(vec![("model.gguf".to_string(), 0)], |_| {})
+    }
+
+    #[tokio::test]
+    async fn test_resumes_after_dropped_connection() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("model.gguf");
+        let served = serve(body(), "\"v1\"", vec![Some(4000), None]);
+        let client = Client::new();
+
+        assert!(download_file(&client, &served.url, None, &target, &mut tracker(), 0, &AtomicBool::new(false)).await.is_err());
+        assert!(!target.exists());
+        assert_eq!(std::fs::metadata(part_path(&target)).unwrap().len(), 4000);
+
+        download_file(&client, &served.url, None, &target, &mut tracker(), 0, &AtomicBool::new(false)).await.unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), body());
+        assert!(!part_path(&target).exists());
+        assert!(!part_info_path(&target).exists());
+        assert_eq!(*served.ranges.lock().unwrap(), vec![None, Some("bytes=4000-".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_restarts_when_remote_file_changed() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("model.gguf");
+        let served = serve(body(), "\"v2\"", vec![None]);
+
+        // A partial download of an older version of the file
+        std::fs::write(part_path(&target), vec![7u8; 3000]).unwrap();
+        let stale = PartInfo { url: served.url.clone(), size: Some(10_000), etag: Some("\"v1\"".to_string()) };
+        std::fs::write(part_info_path(&target), serde_json::to_string(&stale).unwrap()).unwrap();
+
+        download_file(&Client::new(), &served.url, None, &target, &mut tracker(), 0, &AtomicBool::new(false)).await.unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), body());
+    }
+
+    #[tokio::test]
+    async fn test_ignores_part_from_another_url() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("model.gguf");
+        let served = serve(body(), "\"v1\"", vec![None]);
+
+        std::fs::write(part_path(&target), vec![7u8; 3000]).unwrap();
+        let other = PartInfo { url: "http://example.invalid/other.gguf".to_string(), size: None, etag: None };
+        std::fs::write(part_info_path(&target), serde_json::to_string(&other).unwrap()).unwrap();
+
+        download_file(&Client::new(), &served.url, None, &target, &mut tracker(), 0, &AtomicBool::new(false)).await.unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), body());
+        assert_eq!(*served.ranges.lock().unwrap(), vec![None]);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_keeps_partial_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("model.gguf");
+        let client = Client::new();
+
+        let cancelled = AtomicBool::new(true);
+        let err = download_file(&client, "http://127.0.0.1:9/model.gguf", None, &target, &mut tracker(), 0, &cancelled)
+            .await.unwrap_err();
+        assert!(err.is::<Cancelled>());
+
+        // A server that sends part of the body and then stalls
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/model.gguf", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10000\r\n\r\n").unwrap();
+            stream.write_all(&body()[..4000]).unwrap();
+            stream.flush().unwrap();
+            std::thread::sleep(Duration::from_secs(10));
+        });
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = cancelled.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            flag.store(true, Ordering::SeqCst);
+        });
+        let err = download_file(&client, &url, None, &target, &mut tracker(), 0, &cancelled).await.unwrap_err();
+        assert!(err.is::<Cancelled>());
+        assert!(!target.exists());
+        assert_eq!(std::fs::metadata(part_path(&target)).unwrap().len(), 4000);
+    }
+
+    #[test]
+    fn test_hub_file_url_and_content_range() {
+        assert_eq!(
+            hub_file_url("https://huggingface.co/", "org/model", "refs/pr/1", "model.gguf"),
+            "https://huggingface.co/org/model/resolve/refs%2Fpr%2F1/model.gguf"
+        );
+        assert_eq!(parse_content_range("bytes 4000-9999/10000"), Some((4000, Some(10_000))));
+        assert_eq!(parse_content_range("bytes 0-9/*"), Some((0, None)));
+        assert_eq!(parse_content_range("items 0-9/10"), None);
+    }
+}
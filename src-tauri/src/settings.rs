@@ -1,5 +1,8 @@
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::path::PathBuf;
 
@@ -12,10 +15,28 @@ pub enum AnalysisMode {
     Offline,
     /// Automatically choose based on availability
     Auto,
+    /// Deterministic placeholder spec, for development and UI testing only
+    Fake,
 }
 
-/// Available Qwen3-VL model variants
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+/// How offline analysis presents the reference images to the model
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OfflineStrategy {
+    /// Caption first for models the manifest marks as `caption_first`
+    #[default]
+    Auto,
+    /// All images and the spec instruction in one prompt
+    SinglePrompt,
+    /// Describe each image on its own, then write the spec from the descriptions
+    CaptionThenAggregate,
+}
+
+/// Offline model selection.
+///
+/// Downloadable models are described by the model manifest and serialize as
+/// their manifest id (e.g. `"Qwen3VL2B"`); custom models serialize as
+/// `{ "Custom": name }`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ModelVariant {
     /// 2B parameter model (fastest, ~1.9GB)
     Qwen3VL2B,
@@ -23,6 +44,214 @@ pub enum ModelVariant {
     Qwen3VL4B,
     /// 8B parameter model (high quality, ~6.1GB)
     Qwen3VL8B,
+    /// Any other model in the manifest, by id
+    Registry(String),
+    /// User-registered local model, by name
+    Custom(String),
+}
+
+impl ModelVariant {
+    /// Manifest id of a downloadable model; `None` for custom models
+    pub fn registry_id(&self) -> Option<&str> {
+        match self {
+            ModelVariant::Qwen3VL2B => Some("Qwen3VL2B"),
+            ModelVariant::Qwen3VL4B => Some("Qwen3VL4B"),
+            ModelVariant::Qwen3VL8B => Some("Qwen3VL8B"),
+            ModelVariant::Registry(id) => Some(id),
+            ModelVariant::Custom(_) => None,
+        }
+    }
+
+    /// Variant for a manifest id, using the named variants where they exist
+    pub fn from_registry_id(id: &str) -> Self {
+        match id {
+            "Qwen3VL2B" => ModelVariant::Qwen3VL2B,
+            "Qwen3VL4B" => ModelVariant::Qwen3VL4B,
+            "Qwen3VL8B" => ModelVariant::Qwen3VL8B,
+            other => ModelVariant::Registry(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ModelVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelVariant::Custom(name) => write!(f, "{}", name),
+            other => write!(f, "{}", other.registry_id().unwrap_or_default()),
+        }
+    }
+}
+
+impl Serialize for ModelVariant {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ModelVariant::Custom(name) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("Custom", name)?;
+                map.end()
+            }
+            other => serializer.serialize_str(other.registry_id().unwrap_or_default()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ModelVariant {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Id(String),
+            Custom {
+                #[serde(rename = "Custom")]
+                name: String,
+            },
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Id(id) => ModelVariant::from_registry_id(&id),
+            Repr::Custom { name } => ModelVariant::Custom(name),
+        })
+    }
+}
+
+/// ChatML template used by Qwen and most recent instruction-tuned VLMs
+pub const DEFAULT_CHAT_TEMPLATE: &str = "<|im_start|>system\nYou are a vision-language AI assistant specialized in analyzing artistic styles.<|im_end|>\n<|im_start|>user\n{images}{prompt}<|im_end|>\n<|im_start|>assistant\n";
+
+/// A user-registered GGUF vision model (language model + vision projector)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CustomModel {
+    /// Unique display name, referenced by `ModelVariant::Custom`
+    pub name: String,
+    /// Language model GGUF file
+    pub model_path: PathBuf,
+    /// Vision projector (mmproj) GGUF file
+    pub mmproj_path: PathBuf,
+    /// Prompt template with `{images}` and `{prompt}` placeholders; ChatML
+    /// when not given
+    #[serde(default = "default_chat_template")]
+    pub chat_template: String,
+    /// RAM needed to run the model, in GB
+    pub memory_estimate_gb: f32,
+}
+
+/// GGUF quantization level of a model or vision projector file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Quantization {
+    /// ~4.8 bits per weight; smallest, slight quality loss
+    #[serde(rename = "Q4_K_M")]
+    Q4KM,
+    /// 8.5 bits per weight; near-lossless
+    #[default]
+    Q8_0,
+    /// Unquantized half precision
+    F16,
+}
+
+impl Quantization {
+    /// Suffix used in the published GGUF filenames
+    pub fn file_suffix(self) -> &'static str {
+        match self {
+            Quantization::Q4KM => "Q4_K_M",
+            Quantization::Q8_0 => "Q8_0",
+            Quantization::F16 => "F16",
+        }
+    }
+}
+
+/// Quantization of the language model and of its vision projector
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct QuantizationConfig {
+    pub model: Quantization,
+    pub mmproj: Quantization,
+}
+
+/// Where model files are downloaded from
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct HubConfig {
+    /// Base URL of an internal mirror; `None` uses `HF_ENDPOINT` or huggingface.co
+    pub endpoint: Option<String>,
+    /// Access token for gated or private repositories; `None` uses the token
+    /// saved by `huggingface-cli login`. Stored in plain text with the settings.
+    pub token: Option<String>,
+    /// Branch, tag or commit to download instead of the manifest revision, by manifest id
+    pub revisions: BTreeMap<String, String>,
+}
+
+impl HubConfig {
+    /// Pinned revision of a variant, if one is set
+    pub fn revision_for(&self, variant: &ModelVariant) -> Option<&str> {
+        variant
+            .registry_id()
+            .and_then(|id| self.revisions.get(id))
+            .map(|revision| revision.trim())
+            .filter(|revision| !revision.is_empty())
+    }
+}
+
+/// Sampling parameters for local inference
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SamplingConfig {
+    /// Softmax temperature; 0 selects greedy decoding
+    pub temperature: f32,
+    /// Nucleus sampling cutoff
+    pub top_p: f32,
+    /// Keep only the k most likely tokens (0 disables)
+    pub top_k: i32,
+    /// Penalty applied to recently generated tokens
+    pub repeat_penalty: f32,
+    /// Maximum tokens to generate
+    pub max_new_tokens: usize,
+    /// Fixed RNG seed; a random seed is chosen (and recorded) when unset
+    pub seed: Option<u32>,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            temperature: 0.7,
+            top_p: 0.9,
+            top_k: 40,
+            repeat_penalty: 1.05,
+            max_new_tokens: 4096,
+            seed: None,
+        }
+    }
+}
+
+/// llama.cpp runtime options for local inference. The defaults suit any
+/// machine; `hardware::recommended_runtime` tunes threads, context and batch
+/// sizes to the detected one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// CPU threads; `None` uses the detected physical core count
+    pub n_threads: Option<u32>,
+    /// Context window in tokens (images + instructions + generated spec)
+    pub n_ctx: u32,
+    /// Logical batch size for prompt and image evaluation
+    pub n_batch: u32,
+    /// Physical batch size submitted to the backend at once
+    pub n_ubatch: u32,
+    /// Lock model memory so it cannot be swapped out
+    pub use_mlock: bool,
+    /// Use flash attention (lower memory, faster on GPU backends)
+    pub flash_attention: bool,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            n_threads: None,
+            n_ctx: 8192,
+            n_batch: 512,
+            n_ubatch: 512,
+            use_mlock: false,
+            flash_attention: false,
+        }
+    }
 }
 
 /// Application settings for analysis modes
@@ -38,9 +267,57 @@ pub struct AppSettings {
     pub auto_fallback: bool,
     /// Whether to keep model loaded in memory between analyses
     pub keep_model_loaded: bool,
+    /// Seconds a loaded model may sit idle before it is unloaded
+    #[serde(default = "default_model_idle_timeout_secs")]
+    pub model_idle_timeout_secs: u64,
+    /// Sampling parameters for offline inference
+    #[serde(default)]
+    pub sampling: SamplingConfig,
+    /// User-registered local models
+    #[serde(default)]
+    pub custom_models: Vec<CustomModel>,
+    /// Which quantized files to download and load, by manifest id
+    #[serde(default)]
+    pub model_quantization: BTreeMap<String, QuantizationConfig>,
+    /// llama.cpp threads, context and batch sizes, and memory options
+    #[serde(default)]
+    pub runtime: RuntimeConfig,
+    /// One prompt with all images, or per-image captions then aggregation
+    #[serde(default)]
+    pub offline_strategy: OfflineStrategy,
+    /// Allow the fake provider in release builds; only set by hand
+    #[serde(default)]
+    pub allow_fake_provider: bool,
+    /// Hugging Face endpoint, token and pinned revisions for downloads
+    #[serde(default)]
+    pub hub: HubConfig,
+}
+
+impl AppSettings {
+    /// Quantization selected for a variant (custom models bring their own files)
+    pub fn quantization_for(&self, variant: &ModelVariant) -> QuantizationConfig {
+        variant
+            .registry_id()
+            .and_then(|id| self.model_quantization.get(id))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Look up a registered custom model by name
+    pub fn custom_model(&self, name: &str) -> Option<&CustomModel> {
+        self.custom_models.iter().find(|m| m.name == name)
+    }
+}
+
+fn default_model_idle_timeout_secs() -> u64 {
+    600
+}
+
+fn default_chat_template() -> String {
+    DEFAULT_CHAT_TEMPLATE.to_string()
 }
 
-fn get_config_dir() -> Result<PathBuf> {
+pub(crate) fn get_config_dir() -> Result<PathBuf> {
     let config_dir = dirs::config_dir()
         .context("Failed to get config directory")?
         .join("rzem-mj-lora");
@@ -91,6 +368,14 @@ impl Default for AppSettings {
             model_cache_dir: None,
             auto_fallback: true,
             keep_model_loaded: true,
+            model_idle_timeout_secs: default_model_idle_timeout_secs(),
+            sampling: SamplingConfig::default(),
+            custom_models: Vec::new(),
+            model_quantization: BTreeMap::new(),
+            runtime: RuntimeConfig::default(),
+            offline_strategy: OfflineStrategy::Auto,
+            allow_fake_provider: false,
+            hub: HubConfig::default(),
         }
     }
 }
@@ -108,6 +393,7 @@ mod tests {
         assert_eq!(settings.model_cache_dir, None);
         assert_eq!(settings.auto_fallback, true);
         assert_eq!(settings.keep_model_loaded, true);
+        assert_eq!(settings.model_idle_timeout_secs, 600);
     }
 
     #[test]
@@ -121,4 +407,103 @@ mod tests {
 
         assert_eq!(loaded.analysis_mode, AnalysisMode::Offline);
     }
+
+    #[test]
+    fn test_load_settings_missing_new_fields() {
+        // Settings files written by older versions lack newer fields
+        let json = r#"{
+            "analysis_mode": "Offline",
+            "offline_model_variant": "Qwen3VL4B",
+            "model_cache_dir": null,
+            "auto_fallback": false,
+            "keep_model_loaded": true
+        }"#;
+        let loaded: AppSettings = serde_json::from_str(json).unwrap();
+
+        assert_eq!(loaded.offline_model_variant, ModelVariant::Qwen3VL4B);
+        assert_eq!(loaded.model_idle_timeout_secs, 600);
+        assert_eq!(loaded.sampling, SamplingConfig::default());
+        assert_eq!(loaded.quantization_for(&ModelVariant::Qwen3VL4B), QuantizationConfig::default());
+        assert_eq!(loaded.runtime, RuntimeConfig::default());
+        assert_eq!(loaded.offline_strategy, OfflineStrategy::Auto);
+        assert!(!loaded.allow_fake_provider);
+        assert_eq!(loaded.hub, HubConfig::default());
+    }
+
+    #[test]
+    fn test_quantization_serializes_as_file_suffix() {
+        let json = r#"{ "Qwen3VL4B": { "model": "Q4_K_M" } }"#;
+        let settings = AppSettings {
+            model_quantization: serde_json::from_str(json).unwrap(),
+            ..AppSettings::default()
+        };
+
+        let quantization = settings.quantization_for(&ModelVariant::Qwen3VL4B);
+        assert_eq!(quantization.model, Quantization::Q4KM);
+        assert_eq!(quantization.mmproj, Quantization::Q8_0);
+        assert_eq!(settings.quantization_for(&ModelVariant::Qwen3VL2B), QuantizationConfig::default());
+        assert_eq!(serde_json::to_string(&Quantization::Q4KM).unwrap(), "\"Q4_K_M\"");
+    }
+
+    #[test]
+    fn test_variant_serialization() {
+        let variants = [
+            (ModelVariant::Qwen3VL2B, r#""Qwen3VL2B""#),
+            (ModelVariant::Registry("SmolVLM2".to_string()), r#""SmolVLM2""#),
+            (ModelVariant::Custom("Local".to_string()), r#"{"Custom":"Local"}"#),
+        ];
+
+        for (variant, json) in variants {
+            assert_eq!(serde_json::to_string(&variant).unwrap(), json);
+            assert_eq!(serde_json::from_str::<ModelVariant>(json).unwrap(), variant);
+        }
+    }
+
+    #[test]
+    fn test_custom_variant_round_trip() {
+        let mut settings = AppSettings {
+            offline_model_variant: ModelVariant::Custom("MiniCPM-V".to_string()),
+            ..AppSettings::default()
+        };
+        settings.custom_models.push(CustomModel {
+            name: "MiniCPM-V".to_string(),
+            model_path: PathBuf::from("/models/minicpm.gguf"),
+            mmproj_path: PathBuf::from("/models/mmproj-minicpm.gguf"),
+            chat_template: DEFAULT_CHAT_TEMPLATE.to_string(),
+            memory_estimate_gb: 6.0,
+        });
+
+        let json = serde_json::to_string(&settings).unwrap();
+        let loaded: AppSettings = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded.offline_model_variant, ModelVariant::Custom("MiniCPM-V".to_string()));
+        assert!(loaded.custom_model("MiniCPM-V").is_some());
+        assert!(loaded.custom_model("missing").is_none());
+
+        // Models registered without a template get ChatML
+        let json = r#"{ "name": "Llava", "model_path": "/m.gguf", "mmproj_path": "/p.gguf", "memory_estimate_gb": 4.0 }"#;
+        let model: CustomModel = serde_json::from_str(json).unwrap();
+        assert_eq!(model.chat_template, DEFAULT_CHAT_TEMPLATE);
+    }
+
+    #[test]
+    fn test_pinned_revisions() {
+        let mut hub = HubConfig::default();
+        hub.revisions.insert("Qwen3VL4B".to_string(), " 0123abcd ".to_string());
+        hub.revisions.insert("Qwen3VL8B".to_string(), String::new());
+
+        assert_eq!(hub.revision_for(&ModelVariant::Qwen3VL4B), Some("0123abcd"));
+        assert_eq!(hub.revision_for(&ModelVariant::Qwen3VL8B), None);
+        assert_eq!(hub.revision_for(&ModelVariant::Custom("Qwen3VL4B".to_string())), None);
+    }
+
+    #[test]
+    fn test_partial_sampling_config() {
+        let json = r#"{ "temperature": 0.0, "seed": 42 }"#;
+        let sampling: SamplingConfig = serde_json::from_str(json).unwrap();
+
+        assert_eq!(sampling.temperature, 0.0);
+        assert_eq!(sampling.seed, Some(42));
+        assert_eq!(sampling.max_new_tokens, 4096);
+    }
 }
@@ -0,0 +1,650 @@
+use anyhow::{ Context, Result };
+use std::cell::RefCell;
+use std::collections::{ BTreeSet, HashMap };
+
+/// Shape of a JSON value in the dataset specification schema
+#[derive(Debug, Clone)]
+pub enum SchemaNode {
+    /// Object with fields in a fixed order; `false` marks an optional field
+    Object(Vec<(&'static str, SchemaNode, bool)>),
+    /// Array of items with a minimum length of 0 or 1
+    Array(Box<SchemaNode>, usize),
+    /// Object with arbitrary string keys
+    Map(Box<SchemaNode>),
+    String,
+    Integer,
+    Number,
+    Boolean,
+    /// One of a fixed set of strings
+    Enum(Vec<&'static str>),
+    /// Exact JSON value, written verbatim
+    Const(String),
+    /// String that must end with `--sref <code>`
+    SrefPrompt(String),
+}
+
+/// The `DatasetSpecification` schema (see `src/types/schema.ts`)
+pub fn dataset_spec_schema(sref_code: &str) -> SchemaNode {
+    use SchemaNode::*;
+
+    let strings = || Array(Box::new(String), 0);
+
+    Object(
+        vec![
+            ("sref_code", Const(json_string(sref_code)), true),
+            (
+                "style_analysis",
+                Object(
+                    vec![
+                        ("primary_style", String, true),
+                        ("era_influence", String, true),
+                        ("color_palette", strings(), true),
+                        ("key_characteristics", strings(), true),
+                        ("best_subjects", strings(), true),
+                        ("avoid_subjects", strings(), true)
+                    ]
+                ),
+                true,
+            ),
+            (
+                "training_recommendations",
+                Object(
+                    vec![
+                        ("recommended_dataset_size", Integer, true),
+                        ("optimal_subject_distribution", Map(Box::new(Number)), true)
+                    ]
+                ),
+                true,
+            ),
+            (
+                "permutation_batches",
+                Array(
+                    Box::new(
+                        Object(
+                            vec![
+                                ("batch_number", Integer, true),
+                                ("batch_name", String, true),
+                                ("category", String, true),
+                                ("image_count", Const("40".to_string()), true),
+                                ("prompt", SrefPrompt(sref_code.to_string()), true),
+                                ("priority", Enum(vec!["high", "medium", "low"]), true),
+                                ("notes", String, false)
+                            ]
+                        )
+                    ),
+                    1
+                ),
+                true,
+            ),
+            (
+                "prompt_guidelines",
+                Object(
+                    vec![
+                        ("keep_simple", Boolean, true),
+                        ("avoid_style_keywords", strings(), true),
+                        ("recommended_additions", strings(), true)
+                    ]
+                ),
+                true,
+            )
+        ]
+    )
+}
+
+/// Build a GBNF grammar that only accepts a valid dataset specification
+pub fn dataset_spec_grammar(sref_code: &str) -> String {
+    schema_to_gbnf(&dataset_spec_schema(sref_code))
+}
+
+/// Convert a schema into a GBNF grammar rooted at `root`
+pub fn schema_to_gbnf(schema: &SchemaNode) -> String {
+    let mut builder = GbnfBuilder { rules: Vec::new() };
+    let root = builder.expr("root", schema);
+    if root != "root" {
+        builder.rules.insert(0, ("root".to_string(), root));
+    }
+
+    let mut grammar = String::new();
+    for (name, body) in &builder.rules {
+        grammar.push_str(&format!("{} ::= {}\n", name, body));
+    }
+
+    grammar.push_str(
+        r#"ws ::= [ \t\n]*
+string ::= "\"" char* "\""
+char ::= [^"\\\x00-\x1F] | "\\" (["\\/bfnrt] | "u" hex hex hex hex)
+hex ::= [0-9a-fA-F]
+integer ::= "-"? [0-9]+
+number ::= "-"? [0-9]+ ("." [0-9]+)?
+boolean ::= "true" | "false"
+"#
+    );
+
+    grammar
+}
+
+struct GbnfBuilder {
+    rules: Vec<(String, String)>,
+}
+
+impl GbnfBuilder {
+    /// Return a GBNF expression for `node`, emitting named rules for objects
+    fn expr(&mut self, name: &str, node: &SchemaNode) -> String {
+        match node {
+            SchemaNode::Object(fields) => {
+                // Reserve the slot first so parents are listed before children
+                let slot = self.rules.len();
+                self.rules.push((name.to_string(), String::new()));
+
+                let mut parts = vec![r#""{" ws"#.to_string()];
+                let mut first = true;
+
+                for (key, field, required) in fields {
+                    let value = self.expr(&key.replace('_', "-"), field);
+                    let separator = if first { "" } else { r#""," ws "# };
+                    let member = format!(r#"{}"\"{}\"" ws ":" ws {} ws"#, separator, key, value);
+
+                    if *required {
+                        parts.push(member);
+                        first = false;
+                    } else {
+                        parts.push(format!("({})?", member));
+                    }
+                }
+
+                parts.push(r#""}""#.to_string());
+
+                self.rules[slot].1 = parts.join(" ");
+                name.to_string()
+            }
+            SchemaNode::Array(item, min) => {
+                let item = self.expr(&format!("{}-item", name), item);
+                let items = format!(r#"{} (ws "," ws {})*"#, item, item);
+                if *min == 0 {
+                    format!(r#""[" ws ({})? ws "]""#, items)
+                } else {
+                    format!(r#""[" ws {} ws "]""#, items)
+                }
+            }
+            SchemaNode::Map(value) => {
+                let value = self.expr(&format!("{}-value", name), value);
+                let entry = format!(r#"string ws ":" ws {}"#, value);
+                format!(r#""{{" ws ({} (ws "," ws {})*)? ws "}}""#, entry, entry)
+            }
+            SchemaNode::String => "string".to_string(),
+            SchemaNode::Integer => "integer".to_string(),
+            SchemaNode::Number => "number".to_string(),
+            SchemaNode::Boolean => "boolean".to_string(),
+            SchemaNode::Enum(values) => {
+                let alternatives: Vec<String> = values
+                    .iter()
+                    .map(|v| gbnf_literal(&json_string(v)))
+                    .collect();
+                format!("({})", alternatives.join(" | "))
+            }
+            SchemaNode::Const(json) => gbnf_literal(json),
+            SchemaNode::SrefPrompt(code) => {
+                let suffix = json_string(&format!(" --sref {}", code));
+                // Drop the opening quote; the suffix closes the string
+                format!(r#""\"" char* {}"#, gbnf_literal(&suffix[1..]))
+            }
+        }
+    }
+}
+
+/// Encode a string as a JSON string literal (with quotes)
+fn json_string(value: &str) -> String {
+    serde_json::Value::String(value.to_string()).to_string()
+}
+
+/// Quote text as a GBNF string literal
+fn gbnf_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\r' => literal.push_str("\\r"),
+            _ => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[derive(Debug, Clone)]
+enum Atom {
+    Literal(Vec<char>),
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    Rule(String),
+    Group(Vec<Vec<Term>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Repeat {
+    One,
+    Optional,
+    ZeroOrMore,
+    OneOrMore,
+}
+
+#[derive(Debug, Clone)]
+struct Term {
+    atom: Atom,
+    repeat: Repeat,
+}
+
+/// A parsed GBNF grammar that can check strings without llama.cpp.
+///
+/// Supports the subset emitted by `schema_to_gbnf`: literals, character
+/// classes, rule references, groups, alternation and `? * +`.
+pub struct Grammar {
+    rules: HashMap<String, Vec<Vec<Term>>>,
+    memo: RefCell<HashMap<(String, usize), BTreeSet<usize>>>,
+}
+
+impl Grammar {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parser = GbnfParser { chars: text.chars().collect(), pos: 0 };
+        let mut rules = HashMap::new();
+
+        parser.skip_space();
+        while !parser.at_end() {
+            let name = parser.identifier().context("Expected rule name")?;
+            parser.skip_space();
+            if !parser.eat_str("::=") {
+                anyhow::bail!("Expected '::=' after rule '{}'", name);
+            }
+            let alternatives = parser.alternatives()?;
+            rules.insert(name, alternatives);
+            parser.skip_space();
+        }
+
+        if !rules.contains_key("root") {
+            anyhow::bail!("Grammar has no root rule");
+        }
+
+        Ok(Self { rules, memo: RefCell::new(HashMap::new()) })
+    }
+
+    /// Whether the grammar accepts the entire input
+    pub fn accepts(&self, input: &str) -> bool {
+        let chars: Vec<char> = input.chars().collect();
+        self.memo.borrow_mut().clear();
+        self.match_rule("root", &chars, 0).contains(&chars.len())
+    }
+
+    fn match_rule(&self, name: &str, input: &[char], pos: usize) -> BTreeSet<usize> {
+        let key = (name.to_string(), pos);
+        if let Some(ends) = self.memo.borrow().get(&key) {
+            return ends.clone();
+        }
+
+        let ends = match self.rules.get(name) {
+            Some(alternatives) => self.match_alternatives(alternatives, input, pos),
+            None => BTreeSet::new(),
+        };
+
+        self.memo.borrow_mut().insert(key, ends.clone());
+        ends
+    }
+
+    fn match_alternatives(&self, alternatives: &[Vec<Term>], input: &[char], pos: usize) -> BTreeSet<usize> {
+        alternatives
+            .iter()
+            .flat_map(|sequence| self.match_sequence(sequence, input, pos))
+            .collect()
+    }
+
+    fn match_sequence(&self, sequence: &[Term], input: &[char], pos: usize) -> BTreeSet<usize> {
+        let mut positions = BTreeSet::from([pos]);
+        for term in sequence {
+            positions = positions
+                .iter()
+                .flat_map(|&p| self.match_term(term, input, p))
+                .collect();
+            if positions.is_empty() {
+                break;
+            }
+        }
+        positions
+    }
+
+    fn match_term(&self, term: &Term, input: &[char], pos: usize) -> BTreeSet<usize> {
+        match term.repeat {
+            Repeat::One => self.match_atom(&term.atom, input, pos),
+            Repeat::Optional => {
+                let mut ends = self.match_atom(&term.atom, input, pos);
+                ends.insert(pos);
+                ends
+            }
+            Repeat::ZeroOrMore | Repeat::OneOrMore => {
+                let mut ends = BTreeSet::new();
+                if term.repeat == Repeat::ZeroOrMore {
+                    ends.insert(pos);
+                }
+
+                let mut frontier = BTreeSet::from([pos]);
+                while !frontier.is_empty() {
+                    let next: BTreeSet<usize> = frontier
+                        .iter()
+                        .flat_map(|&p| self.match_atom(&term.atom, input, p))
+                        .filter(|end| !ends.contains(end))
+                        .collect();
+                    ends.extend(next.iter().copied());
+                    frontier = next;
+                }
+                ends
+            }
+        }
+    }
+
+    fn match_atom(&self, atom: &Atom, input: &[char], pos: usize) -> BTreeSet<usize> {
+        match atom {
+            Atom::Literal(text) => {
+                if input[pos..].starts_with(text) {
+                    BTreeSet::from([pos + text.len()])
+                } else {
+                    BTreeSet::new()
+                }
+            }
+            Atom::Class { negated, ranges } => {
+                match input.get(pos) {
+                    Some(&c) if ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated =>
+                        BTreeSet::from([pos + 1]),
+                    _ => BTreeSet::new(),
+                }
+            }
+            Atom::Rule(name) => self.match_rule(name, input, pos),
+            Atom::Group(alternatives) => self.match_alternatives(alternatives, input, pos),
+        }
+    }
+}
+
+struct GbnfParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl GbnfParser {
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<char> {
+        let c = self.peek().context("Unexpected end of grammar")?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn eat_str(&mut self, s: &str) -> bool {
+        let expected: Vec<char> = s.chars().collect();
+        if self.chars[self.pos..].starts_with(&expected) {
+            self.pos += expected.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skip whitespace, newlines and `#` comments
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.pos += 1;
+            } else if c == '#' {
+                while let Some(c) = self.peek() {
+                    self.pos += 1;
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> Option<String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        (self.pos > start).then(|| self.chars[start..self.pos].iter().collect())
+    }
+
+    /// Whether the upcoming tokens start a new `name ::=` rule
+    fn at_rule_start(&mut self) -> bool {
+        let saved = self.pos;
+        let is_rule =
+            self.identifier().is_some() &&
+            {
+                self.skip_space();
+                self.eat_str("::=")
+            };
+        self.pos = saved;
+        is_rule
+    }
+
+    fn alternatives(&mut self) -> Result<Vec<Vec<Term>>> {
+        let mut alternatives = vec![self.sequence()?];
+        loop {
+            self.skip_space();
+            if self.peek() == Some('|') {
+                self.pos += 1;
+                alternatives.push(self.sequence()?);
+            } else {
+                return Ok(alternatives);
+            }
+        }
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Term>> {
+        let mut terms = Vec::new();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                None | Some('|') | Some(')') => {
+                    return Ok(terms);
+                }
+                _ if self.at_rule_start() => {
+                    return Ok(terms);
+                }
+                _ => {}
+            }
+
+            let atom = self.atom()?;
+            let repeat = match self.peek() {
+                Some('?') => Repeat::Optional,
+                Some('*') => Repeat::ZeroOrMore,
+                Some('+') => Repeat::OneOrMore,
+                _ => Repeat::One,
+            };
+            if repeat != Repeat::One {
+                self.pos += 1;
+            }
+            terms.push(Term { atom, repeat });
+        }
+    }
+
+    fn atom(&mut self) -> Result<Atom> {
+        match self.next()? {
+            '"' => {
+                let mut text = Vec::new();
+                loop {
+                    match self.next()? {
+                        '"' => {
+                            return Ok(Atom::Literal(text));
+                        }
+                        '\\' => text.push(self.escape()?),
+                        c => text.push(c),
+                    }
+                }
+            }
+            '[' => {
+                let negated = self.peek() == Some('^');
+                if negated {
+                    self.pos += 1;
+                }
+
+                let mut ranges = Vec::new();
+                loop {
+                    let lo = match self.next()? {
+                        ']' => {
+                            return Ok(Atom::Class { negated, ranges });
+                        }
+                        '\\' => self.escape()?,
+                        c => c,
+                    };
+                    let hi = if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+                        self.pos += 1;
+                        match self.next()? {
+                            '\\' => self.escape()?,
+                            c => c,
+                        }
+                    } else {
+                        lo
+                    };
+                    ranges.push((lo, hi));
+                }
+            }
+            '(' => {
+                let alternatives = self.alternatives()?;
+                self.skip_space();
+                if self.next()? != ')' {
+                    anyhow::bail!("Expected ')' at offset {}", self.pos);
+                }
+                Ok(Atom::Group(alternatives))
+            }
+            c if c.is_ascii_alphanumeric() || c == '-' || c == '_' => {
+                self.pos -= 1;
+                let name = self.identifier().context("Expected rule reference")?;
+                Ok(Atom::Rule(name))
+            }
+            c => anyhow::bail!("Unexpected '{}' at offset {}", c, self.pos - 1),
+        }
+    }
+
+    fn escape(&mut self) -> Result<char> {
+        Ok(match self.next()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'x' => {
+                let hex: String = [self.next()?, self.next()?].iter().collect();
+                let code = u32::from_str_radix(&hex, 16).context("Invalid \\x escape")?;
+                char::from_u32(code).context("Invalid \\x escape")?
+            }
+            c => c,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_spec(sref: &str, prompt: &str, priority: &str) -> String {
+        format!(
+            r##"{{
+  "sref_code": "{sref}",
+  "style_analysis": {{
+    "primary_style": "Art deco poster",
+    "era_influence": "1930s",
+    "color_palette": ["#1E3A8A", "gold"],
+    "key_characteristics": ["geometric \"sunburst\" forms"],
+    "best_subjects": ["architecture"],
+    "avoid_subjects": []
+  }},
+  "training_recommendations": {{
+    "recommended_dataset_size": 120,
+    "optimal_subject_distribution": {{ "nature": 0.3, "objects": 0.7 }}
+  }},
+  "permutation_batches": [
+    {{
+      "batch_number": 1,
+      "batch_name": "Cities",
+      "category": "Architecture",
+      "image_count": 40,
+      "prompt": "{prompt}",
+      "priority": "{priority}"
+    }},
+    {{"batch_number": 2, "batch_name": "Trees", "category": "Nature", "image_count": 40, "prompt": "{{oak, pine}} --sref {sref}", "priority": "low", "notes": "keep simple"}}
+  ],
+  "prompt_guidelines": {{
+    "keep_simple": true,
+    "avoid_style_keywords": ["stylized"],
+    "recommended_additions": ["lighting"]
+  }}
+}}"##
+        )
+    }
+
+    #[test]
+    fn test_grammar_parses() {
+        let grammar = dataset_spec_grammar("123456");
+        assert!(grammar.starts_with("root ::= "));
+        assert!(Grammar::parse(&grammar).is_ok());
+    }
+
+    #[test]
+    fn test_accepts_valid_spec() {
+        let grammar = Grammar::parse(&dataset_spec_grammar("123456")).unwrap();
+        let spec = sample_spec("123456", "{city, town} with {dawn, dusk} --sref 123456", "high");
+        assert!(grammar.accepts(&spec));
+        assert!(serde_json::from_str::<serde_json::Value>(&spec).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_invalid_priority() {
+        let grammar = Grammar::parse(&dataset_spec_grammar("123456")).unwrap();
+        let spec = sample_spec("123456", "{city, town} --sref 123456", "urgent");
+        assert!(!grammar.accepts(&spec));
+    }
+
+    #[test]
+    fn test_requires_sref_suffix() {
+        let grammar = Grammar::parse(&dataset_spec_grammar("123456")).unwrap();
+        assert!(!grammar.accepts(&sample_spec("123456", "{city, town} with {dawn}", "high")));
+        assert!(!grammar.accepts(&sample_spec("123456", "{city} --sref 999999", "high")));
+        assert!(!grammar.accepts(&sample_spec("123456", "--sref 123456 {city}", "high")));
+    }
+
+    #[test]
+    fn test_requires_matching_sref_code() {
+        let grammar = Grammar::parse(&dataset_spec_grammar("123456")).unwrap();
+        let spec = sample_spec("stub-qwen3vl", "{city} --sref 123456", "high");
+        assert!(!grammar.accepts(&spec));
+    }
+
+    #[test]
+    fn test_rejects_almost_json() {
+        let grammar = Grammar::parse(&dataset_spec_grammar("123456")).unwrap();
+        let spec = sample_spec("123456", "{city} --sref 123456", "high");
+        assert!(!grammar.accepts(&spec.replacen("\"image_count\": 40,", "\"image_count\": 40,,", 1)));
+        assert!(!grammar.accepts(&spec.replacen("\"image_count\": 40", "\"image_count\": 36", 1)));
+        assert!(!grammar.accepts(&spec[..spec.len() - 1]));
+    }
+
+    #[test]
+    fn test_escapes_unusual_sref_codes() {
+        let code = "a\"b\\c";
+        let grammar = Grammar::parse(&dataset_spec_grammar(code)).unwrap();
+        let sref = r#"a\"b\\c"#;
+        let spec = sample_spec(sref, &format!("{{city}} --sref {}", sref), "medium");
+        assert!(grammar.accepts(&spec));
+    }
+}
@@ -1,18 +1,82 @@
 import { defineStore } from 'pinia';
 import { ref, computed } from 'vue';
 import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 import type {
   ProjectData,
   DatasetSpecification,
   PermutationBatch,
+  InferenceMetadata,
+  SamplingConfig,
 } from '../types/schema';
 import { exportAsJSON, exportAsMarkdown } from '../utils/export';
 
 export type Step = 'upload' | 'analysis' | 'batches' | 'export';
 
 // Settings types (matches Rust backend)
-export type AnalysisMode = 'CloudAPI' | 'Offline' | 'Auto';
-export type ModelVariant = 'Qwen3VL2B' | 'Qwen3VL4B' | 'Qwen3VL8B';
+export type AnalysisMode = 'CloudAPI' | 'Offline' | 'Auto' | 'Fake';
+// Manifest model id (e.g. 'Qwen3VL2B') or a user-registered custom model
+export type ModelVariant = string | { Custom: string };
+
+// GGUF quantization levels (matches Rust Quantization)
+export type Quantization = 'Q4_K_M' | 'Q8_0' | 'F16';
+
+export interface QuantizationConfig {
+  model: Quantization;
+  mmproj: Quantization;
+}
+
+// Downloadable file in the model manifest (matches Rust ManifestFile)
+export interface ManifestFile {
+  file: string;
+  size_bytes: number;
+  sha256: string | null;
+}
+
+// Downloadable model described by the model manifest (matches Rust ManifestModel)
+export interface ManifestModel {
+  id: string;
+  name: string;
+  description: string;
+  badge: string | null;
+  hf_repo: string;
+  revision: string;
+  directory: string;
+  chat_template: string | null;
+  runtime_overhead_gb: number;
+  model_files: Partial<Record<Quantization, ManifestFile>>;
+  mmproj_files: Partial<Record<Quantization, ManifestFile>>;
+}
+
+// How offline analysis presents images to the model (matches Rust OfflineStrategy)
+export type OfflineStrategy = 'Auto' | 'SinglePrompt' | 'CaptionThenAggregate';
+
+// llama.cpp runtime options (matches Rust RuntimeConfig)
+export interface RuntimeConfig {
+  n_threads: number | null;
+  n_ctx: number;
+  n_batch: number;
+  n_ubatch: number;
+  use_mlock: boolean;
+  flash_attention: boolean;
+}
+
+// User-registered GGUF vision model (matches Rust CustomModel)
+export interface CustomModel {
+  name: string;
+  model_path: string;
+  mmproj_path: string;
+  chat_template: string;
+  memory_estimate_gb: number;
+}
+
+// Download endpoint, token and pinned revisions (matches Rust HubConfig)
+export interface HubConfig {
+  endpoint: string | null;
+  token: string | null;
+  // Keyed by manifest model id
+  revisions: Record<string, string>;
+}
 
 export interface AppSettings {
   analysis_mode: AnalysisMode;
@@ -20,12 +84,55 @@ export interface AppSettings {
   model_cache_dir: string | null;
   auto_fallback: boolean;
   keep_model_loaded: boolean;
+  model_idle_timeout_secs: number;
+  sampling: SamplingConfig;
+  custom_models: CustomModel[];
+  // Keyed by manifest model id
+  model_quantization: Record<string, QuantizationConfig>;
+  runtime: RuntimeConfig;
+  offline_strategy: OfflineStrategy;
+  // Enables the fake provider in release builds; set in settings.json only
+  allow_fake_provider: boolean;
+  hub: HubConfig;
+}
+
+// Offline inference progress (matches Rust InferenceProgress)
+export type InferenceProgress =
+  | { stage: 'loading_model' }
+  | { stage: 'captioning_image'; index: number; total: number }
+  | { stage: 'encoding_image'; index: number; total: number }
+  | { stage: 'prompt_eval'; tokens: number }
+  | {
+      stage: 'generating';
+      tokens_generated: number;
+      tokens_per_second: number;
+      eta_secs: number | null;
+    };
+
+function describeProgress(progress: InferenceProgress): string {
+  switch (progress.stage) {
+    case 'loading_model':
+      return 'Loading offline model...';
+    case 'captioning_image':
+      return `Describing image ${progress.index} of ${progress.total}...`;
+    case 'encoding_image':
+      return `Encoding image ${progress.index} of ${progress.total}...`;
+    case 'prompt_eval':
+      return `Prompt evaluated (${progress.tokens} tokens)...`;
+    case 'generating': {
+      const speed = progress.tokens_per_second.toFixed(1);
+      const eta = progress.eta_secs !== null ? `, ~${Math.ceil(progress.eta_secs)}s left` : '';
+      return `Generating: ${progress.tokens_generated} tokens (${speed} tok/s${eta})`;
+    }
+  }
 }
 
 export interface AnalysisResult {
   data: string;
-  mode_used: string; // "cloud" or "offline"
+  mode_used: string; // "cloud", "offline" or "fake"
   fallback_used: boolean;
+  fake: boolean;
+  inference_metadata: InferenceMetadata | null;
 }
 
 export const useProjectStore = defineStore('project', () => {
@@ -41,6 +148,9 @@ export const useProjectStore = defineStore('project', () => {
   const settings = ref<AppSettings | null>(null);
   const lastModeUsed = ref<string | null>(null);
   const lastFallbackUsed = ref(false);
+  const fakeResult = ref(false);
+  const inferenceMetadata = ref<InferenceMetadata | null>(null);
+  let unlistenProgress: UnlistenFn | null = null;
 
   // Computed
   const hasImages = computed(() => imagePaths.value.length >= 3);
@@ -102,6 +212,10 @@ export const useProjectStore = defineStore('project', () => {
 
       statusMessage.value = 'Analyzing style characteristics...';
 
+      unlistenProgress = await listen<InferenceProgress>('analysis-progress', (event) => {
+        statusMessage.value = describeProgress(event.payload);
+      });
+
       const result = await invoke<AnalysisResult>('analyze_style', {
         imagePaths: imagePaths.value,
         srefCode: String(srefCode.value),
@@ -110,6 +224,8 @@ export const useProjectStore = defineStore('project', () => {
       // Store which mode was actually used
       lastModeUsed.value = result.mode_used;
       lastFallbackUsed.value = result.fallback_used;
+      fakeResult.value = result.fake;
+      inferenceMetadata.value = result.inference_metadata;
 
       statusMessage.value = 'Processing response...';
       await new Promise(resolve => setTimeout(resolve, 100));
@@ -120,7 +236,9 @@ export const useProjectStore = defineStore('project', () => {
       currentStep.value = 'analysis';
 
       // Show appropriate completion message
-      if (result.fallback_used) {
+      if (result.fake) {
+        statusMessage.value = 'Fake provider output - placeholder data, not a real analysis';
+      } else if (result.fallback_used) {
         statusMessage.value = 'Analysis complete (used offline fallback)!';
       } else {
         statusMessage.value = `Analysis complete (${result.mode_used} mode)!`;
@@ -134,12 +252,21 @@ export const useProjectStore = defineStore('project', () => {
       throw e;
     } finally {
       isLoading.value = false;
+      if (unlistenProgress) {
+        unlistenProgress();
+        unlistenProgress = null;
+      }
       setTimeout(() => {
         statusMessage.value = null;
       }, 1000);
     }
   }
 
+  async function cancelAnalysis() {
+    statusMessage.value = 'Cancelling...';
+    await invoke('cancel_analysis');
+  }
+
   function updateSpecification(spec: DatasetSpecification) {
     specification.value = spec;
     isDirty.value = true;
@@ -205,6 +332,8 @@ export const useProjectStore = defineStore('project', () => {
       imagePaths: imagePaths.value,
       srefCode: srefCode.value,
       specification: specification.value,
+      inferenceMetadata: inferenceMetadata.value,
+      fakeResult: fakeResult.value,
       lastModified: Date.now(),
     };
 
@@ -231,6 +360,8 @@ export const useProjectStore = defineStore('project', () => {
       imagePaths.value = projectData.imagePaths;
       srefCode.value = projectData.srefCode;
       specification.value = projectData.specification;
+      inferenceMetadata.value = projectData.inferenceMetadata ?? null;
+      fakeResult.value = projectData.fakeResult ?? false;
       isDirty.value = false;
 
       if (specification.value) {
@@ -280,6 +411,8 @@ export const useProjectStore = defineStore('project', () => {
     imagePaths.value = [];
     srefCode.value = '';
     specification.value = null;
+    inferenceMetadata.value = null;
+    fakeResult.value = false;
     currentStep.value = 'upload';
     isDirty.value = false;
     error.value = null;
@@ -320,6 +453,8 @@ export const useProjectStore = defineStore('project', () => {
     settings,
     lastModeUsed,
     lastFallbackUsed,
+    fakeResult,
+    inferenceMetadata,
 
     // Computed
     hasImages,
@@ -333,6 +468,7 @@ export const useProjectStore = defineStore('project', () => {
     removeImage,
     setSrefCode,
     analyzeStyle,
+    cancelAnalysis,
     updateSpecification,
     updateBatch,
     addBatch,
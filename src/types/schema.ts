@@ -42,11 +42,43 @@ export interface DatasetSpecification {
   prompt_guidelines: PromptGuidelines;
 }
 
+export interface SamplingConfig {
+  temperature: number;
+  top_p: number;
+  top_k: number;
+  repeat_penalty: number;
+  max_new_tokens: number;
+  seed: number | null;
+}
+
+/**
+ * Model and sampling settings used for an offline analysis, kept so the
+ * specification can be regenerated exactly
+ */
+export interface InferenceMetadata {
+  model_variant: string | { Custom: string };
+  sampling: SamplingConfig;
+  strategy?: 'SinglePrompt' | 'CaptionThenAggregate';
+  // Present when each image was described before the spec was written
+  image_captions?: ImageCaption[];
+}
+
+/**
+ * Style description of one reference image from caption-then-aggregate analysis
+ */
+export interface ImageCaption {
+  image_path: string;
+  caption: string;
+  cached: boolean;
+}
+
 export interface ProjectData {
   images: string[];  // base64 encoded
   imagePaths: string[];  // original file paths
   srefCode: string;
   specification: DatasetSpecification | null;
+  inferenceMetadata?: InferenceMetadata | null;
+  fakeResult?: boolean;  // specification came from the fake provider
   lastModified: number;
 }
 